@@ -0,0 +1,425 @@
+//! `ldb` inspects and edits a database from the command line.
+//!
+//! Run `ldb` without arguments for the list of commands.
+
+use std::cmp::Ordering;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+use myleveldb_rs::{
+    dump_file, escape_string, repair_db, Comparator, Error, ErrorKind, LdbIterator, Options,
+    ReadOptions, Result, WriteOptions, DB,
+};
+
+const USAGE: &str = "\
+Usage: ldb <command> [arguments]
+
+Commands:
+  get <db> <key> [--hex]
+  put <db> <key> <value> [--hex]
+  delete <db> <key> [--hex]
+  scan <db> [--from=<key>] [--to=<key>] [--hex]
+  compact <db> [--from=<key>] [--to=<key>] [--hex]
+  repair <db>
+  stats <db>
+  dump-log <file>...
+  dump-table <file>...
+  dump-manifest <file>...
+
+With --hex, keys and values are given and printed in hex.  Otherwise
+they are printed with the non-printable bytes escaped as \\xNN.
+`scan` starts at --from and stops before --to.";
+
+// The parsed command line arguments following the command name
+#[derive(Debug)]
+struct Args {
+    positional: Vec<String>,
+    from: Option<String>,
+    to: Option<String>,
+    hex: bool,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut parsed = Args {
+            positional: vec![],
+            from: None,
+            to: None,
+            hex: false,
+        };
+        for arg in args {
+            if arg == "--hex" {
+                parsed.hex = true;
+            } else if let Some(from) = arg.strip_prefix("--from=") {
+                parsed.from = Some(from.to_owned());
+            } else if let Some(to) = arg.strip_prefix("--to=") {
+                parsed.to = Some(to.to_owned());
+            } else if arg.starts_with("--") {
+                return Err(usage_error(&format!("unknown option {}", arg)));
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+        Ok(parsed)
+    }
+
+    // Returns the positional arguments, which must be `n` exactly
+    fn expect(&self, n: usize) -> Result<&[String]> {
+        if self.positional.len() != n {
+            return Err(usage_error(&format!(
+                "expected {} arguments, got {}",
+                n,
+                self.positional.len()
+            )));
+        }
+        Ok(&self.positional)
+    }
+
+    // Decodes a key or a value given on the command line
+    fn decode(&self, arg: &str) -> Result<Vec<u8>> {
+        if self.hex {
+            decode_hex(arg)
+        } else {
+            Ok(arg.as_bytes().to_vec())
+        }
+    }
+
+    // Formats a key or a value for printing
+    fn encode(&self, data: &[u8]) -> String {
+        if self.hex {
+            encode_hex(data)
+        } else {
+            escape_string(data)
+        }
+    }
+}
+
+fn usage_error(hint: &str) -> Error {
+    Error::from(ErrorKind::InvalidArgument(format!("{}\n\n{}", hint, USAGE)))
+}
+
+// `usize::is_multiple_of` needs a newer toolchain than the crate does
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    if digits.len() % 2 != 0 || !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ErrorKind::InvalidArgument(format!("invalid hex string {}", s)).into());
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    let mut s = String::from("0x");
+    for c in data {
+        s.push_str(&format!("{:02X}", c));
+    }
+    s
+}
+
+// Only `put` creates the database, the other commands expect it to exist
+fn open_db(path: &str, create_if_missing: bool) -> Result<DB> {
+    DB::open(
+        path,
+        Options {
+            create_if_missing,
+            ..Options::default()
+        },
+    )
+}
+
+fn get(args: &Args, out: &mut dyn Write) -> Result<()> {
+    let positional = args.expect(2)?;
+    let db = open_db(&positional[0], false)?;
+    match db.get(ReadOptions::default(), &args.decode(&positional[1])?)? {
+        Some(value) => writeln!(out, "{}", args.encode(&value))?,
+        None => return Err(ErrorKind::NotFound(Some(positional[1].clone())).into()),
+    }
+    db.close()
+}
+
+fn put(args: &Args, _out: &mut dyn Write) -> Result<()> {
+    let positional = args.expect(3)?;
+    let db = open_db(&positional[0], true)?;
+    db.put(
+        WriteOptions { sync: true },
+        &args.decode(&positional[1])?,
+        &args.decode(&positional[2])?,
+    )?;
+    db.close()
+}
+
+fn delete(args: &Args, _out: &mut dyn Write) -> Result<()> {
+    let positional = args.expect(2)?;
+    let db = open_db(&positional[0], false)?;
+    db.delete(WriteOptions { sync: true }, &args.decode(&positional[1])?)?;
+    db.close()
+}
+
+fn scan(args: &Args, out: &mut dyn Write) -> Result<()> {
+    let db = open_db(&args.expect(1)?[0], false)?;
+    let options: Options = Options::default();
+    scan_db(&db, &options.comparator, args, out)?;
+    db.close()
+}
+
+// Prints the entries of `db` from --from up to, and excluding, --to.
+// The bounds are ordered by `comparator`, the comparator of the db.
+fn scan_db<C: Comparator + Clone + Send + Sync + 'static>(
+    db: &DB<C>,
+    comparator: &C,
+    args: &Args,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let to = args.to.as_deref().map(|to| args.decode(to)).transpose()?;
+    let mut iter = db.new_iterator(ReadOptions::default())?;
+    match &args.from {
+        Some(from) => iter.seek(&args.decode(from)?),
+        None => iter.seek_to_first(),
+    }
+    while iter.valid() {
+        if let Some(to) = &to {
+            if comparator.compare(iter.key(), to) != Ordering::Less {
+                break;
+            }
+        }
+        writeln!(
+            out,
+            "{} ==> {}",
+            args.encode(iter.key()),
+            args.encode(iter.value())
+        )?;
+        iter.next();
+    }
+    iter.status()
+}
+
+fn compact(args: &Args, _out: &mut dyn Write) -> Result<()> {
+    let db = open_db(&args.expect(1)?[0], false)?;
+    let from = args.from.as_deref().map(|k| args.decode(k)).transpose()?;
+    let to = args.to.as_deref().map(|k| args.decode(k)).transpose()?;
+    db.compact_range(from.as_deref(), to.as_deref())?;
+    db.close()
+}
+
+fn repair(args: &Args, out: &mut dyn Write) -> Result<()> {
+    let options: Options = Options::default();
+    let report = repair_db(&args.expect(1)?[0], options)?;
+    writeln!(out, "converted logs:    {}", report.logs)?;
+    writeln!(out, "tables:            {}", report.tables)?;
+    writeln!(out, "entries:           {}", report.entries)?;
+    writeln!(out, "dropped log bytes: {}", report.dropped_log_bytes)?;
+    writeln!(out, "dropped entries:   {}", report.dropped_entries)?;
+    for file in &report.lost_files {
+        writeln!(out, "lost file:         {}", file)?;
+    }
+    Ok(())
+}
+
+fn stats(args: &Args, out: &mut dyn Write) -> Result<()> {
+    let db = open_db(&args.expect(1)?[0], false)?;
+    for property in &["leveldb.stats", "leveldb.sstables"] {
+        if let Some(value) = db.get_property(property) {
+            write!(out, "{}", value)?;
+        }
+    }
+    db.close()
+}
+
+// Dumps every file of `args`, whose names must satisfy `is_expected`
+fn dump(args: &Args, out: &mut dyn Write, is_expected: fn(&str) -> bool) -> Result<()> {
+    if args.positional.is_empty() {
+        return Err(usage_error("expected at least one file"));
+    }
+    let env = myleveldb_rs::env::default_env();
+    for file in &args.positional {
+        let path = Path::new(file);
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        if !is_expected(&name) {
+            return Err(
+                ErrorKind::InvalidArgument(format!("{}: unexpected file type", file)).into(),
+            );
+        }
+        dump_file(env.as_ref(), path, out)?;
+    }
+    Ok(())
+}
+
+fn run(command: &str, args: &Args, out: &mut dyn Write) -> Result<()> {
+    match command {
+        "get" => get(args, out),
+        "put" => put(args, out),
+        "delete" => delete(args, out),
+        "scan" => scan(args, out),
+        "compact" => compact(args, out),
+        "repair" => repair(args, out),
+        "stats" => stats(args, out),
+        "dump-log" => dump(args, out, |name| name.ends_with(".log")),
+        "dump-table" => dump(args, out, |name| {
+            name.ends_with(".ldb") || name.ends_with(".sst")
+        }),
+        "dump-manifest" => dump(args, out, |name| name.starts_with("MANIFEST-")),
+        _ => Err(usage_error(&format!("unknown command {}", command))),
+    }
+}
+
+// Splits the command line into the command and its parsed arguments.
+// Returns `None` when the usage is asked for.
+fn parse_command_line(argv: &[String]) -> Result<Option<(&str, Args)>> {
+    match argv.split_first() {
+        None => Ok(None),
+        Some((command, _)) if command == "help" || command == "--help" => Ok(None),
+        Some((command, args)) => Ok(Some((command, Args::parse(args)?))),
+    }
+}
+
+fn main() {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = parse_command_line(&argv).and_then(|parsed| match parsed {
+        Some((command, args)) => run(command, &args, &mut out),
+        None => writeln!(out, "{}", USAGE).map_err(Error::from),
+    });
+    if let Err(e) = result {
+        let _ = out.flush();
+        match e.kind() {
+            ErrorKind::NotFound(Some(key)) => eprintln!("ldb: {}: not found", key),
+            _ => eprintln!("ldb: {}", e),
+        }
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use myleveldb_rs::ReverseBytewiseComparator;
+    use std::fs;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("").unwrap(), b"");
+        assert_eq!(decode_hex("0x").unwrap(), b"");
+        assert_eq!(decode_hex("0x00FF10").unwrap(), b"\x00\xff\x10");
+        assert_eq!(decode_hex("0Xabcd").unwrap(), b"\xab\xcd");
+        assert_eq!(decode_hex("6b6579").unwrap(), b"key");
+        for invalid in &["0x123", "1", "0xzz", "key", "0x0x00"] {
+            let err = decode_hex(invalid).unwrap_err();
+            assert!(
+                matches!(err.kind(), ErrorKind::InvalidArgument(_)),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(encode_hex(b"\x00\xff\x10"), "0x00FF10");
+        assert_eq!(decode_hex(&encode_hex(b"key")).unwrap(), b"key");
+    }
+
+    #[test]
+    fn test_parse_command_line() {
+        assert!(parse_command_line(&[]).unwrap().is_none());
+        assert!(parse_command_line(&strings(&["help"])).unwrap().is_none());
+        assert!(parse_command_line(&strings(&["--help", "scan"]))
+            .unwrap()
+            .is_none());
+
+        let argv = strings(&["scan", "/db", "--from=a", "--hex", "--to=0x7a"]);
+        let (command, args) = parse_command_line(&argv).unwrap().unwrap();
+        assert_eq!(command, "scan");
+        assert_eq!(args.positional, vec!["/db"]);
+        assert_eq!(args.from.as_deref(), Some("a"));
+        assert_eq!(args.to.as_deref(), Some("0x7a"));
+        assert!(args.hex);
+        assert_eq!(args.expect(1).unwrap(), &["/db".to_owned()][..]);
+        assert_eq!(args.decode("0x7a").unwrap(), b"z");
+        assert_eq!(args.encode(b"z"), "0x7A");
+        assert!(args.decode("z").is_err());
+
+        let argv = strings(&["get", "/db", "key"]);
+        let (_, args) = parse_command_line(&argv).unwrap().unwrap();
+        assert!(!args.hex);
+        assert_eq!(args.decode("0x7a").unwrap(), b"0x7a");
+        assert_eq!(args.encode(b"a\x01"), "a\\x01");
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        let err = parse_command_line(&strings(&["scan", "/db", "--limit=3"])).unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidArgument(hint) => {
+                assert!(hint.starts_with("unknown option --limit=3"))
+            }
+            _ => panic!("expect an invalid argument error"),
+        }
+        let args = Args::parse(&strings(&["/db", "key"])).unwrap();
+        assert!(args.expect(2).is_ok());
+        let err = args.expect(3).unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidArgument(hint) => {
+                assert!(hint.starts_with("expected 3 arguments, got 2"))
+            }
+            _ => panic!("expect an invalid argument error"),
+        }
+        let mut out = vec![];
+        let err = run("frobnicate", &args, &mut out).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)));
+        let no_files = Args::parse(&[]).unwrap();
+        assert!(run("dump-log", &no_files, &mut out).is_err());
+        let table = Args::parse(&strings(&["/db/000005.ldb"])).unwrap();
+        assert!(run("dump-log", &table, &mut out).is_err());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_scan_bounds() {
+        let dir = std::env::temp_dir().join(format!("ldb-scan-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let options: Options<ReverseBytewiseComparator> = Options {
+            create_if_missing: true,
+            ..Options::default()
+        };
+        let comparator = options.comparator.clone();
+        let db = DB::open(&dir, options).unwrap();
+        for key in &["a", "b", "c", "d"] {
+            db.put(WriteOptions::default(), key.as_bytes(), b"v")
+                .unwrap();
+        }
+
+        let scan = |args: &[&str]| {
+            let mut argv = strings(&["/db"]);
+            argv.extend(strings(args));
+            let mut out = vec![];
+            scan_db(&db, &comparator, &Args::parse(&argv).unwrap(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // The keys are in the order of the db, so "a" comes last
+        assert_eq!(scan(&[]), "d ==> v\nc ==> v\nb ==> v\na ==> v\n");
+        assert_eq!(scan(&["--from=c"]), "c ==> v\nb ==> v\na ==> v\n");
+        assert_eq!(scan(&["--to=b"]), "d ==> v\nc ==> v\n");
+        assert_eq!(scan(&["--from=c", "--to=a"]), "c ==> v\nb ==> v\n");
+        assert_eq!(scan(&["--from=bb", "--to=a"]), "b ==> v\n");
+        assert_eq!(scan(&["--from=b", "--to=b"]), "");
+        assert_eq!(
+            scan(&["--from=0x63", "--to=0x61", "--hex"]),
+            "0x63 ==> 0x76\n0x62 ==> 0x76\n"
+        );
+
+        db.close().unwrap();
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,11 +1,9 @@
 use std::{cmp::Ordering, mem::size_of, sync::Arc};
 
 use crate::util::{
-    coding::{
-        decode_fixed_32, get_varint_32, get_varint_32_prefix_ptr, put_fixed_32, put_varint_32,
-    },
+    coding::{decode_fixed_32, get_varint_32, put_fixed_32, put_varint_32},
     comparator::{BytewiseComparator, Comparator},
-    status::Error,
+    status::{Error, ErrorKind},
 };
 
 use super::ldbiterator::LdbIterator;
@@ -125,6 +123,7 @@ impl<C: Comparator + Clone> BlockBuilder<C> {
     }
 }
 
+#[derive(Clone)]
 pub struct Block {
     size: u32,
     data: Arc<Vec<u8>>,
@@ -166,9 +165,7 @@ impl Block {
             }
         }
 
-        Err(Error::Corruption(
-            "[block] read invalid block content".to_owned(),
-        ))
+        Err(ErrorKind::Corruption("[block] read invalid block content".to_owned()).into())
     }
 
     pub fn iter<C: Comparator + Clone>(&self, c: C) -> BlockIterator<C> {
@@ -200,38 +197,131 @@ impl<C: Comparator + Clone> LdbIterator for BlockIterator<C> {
     }
 
     fn seek_to_first(&mut self) {
-        todo!()
+        if self.nums_restarts == 0 {
+            return;
+        }
+        self.seek_to_restart_point(0);
+        self.parse_next_key();
     }
 
     fn seek_to_last(&mut self) {
-        todo!()
+        if self.nums_restarts == 0 {
+            return;
+        }
+        self.seek_to_restart_point(self.nums_restarts - 1);
+        // Keep skipping
+        while self.parse_next_key() && self.next_entry_offset() < self.restarts {}
     }
 
     fn seek(&mut self, target: &[u8]) {
-        todo!()
+        if self.nums_restarts == 0 {
+            return;
+        }
+        // Binary search in restart array to find the last restart point
+        // with a key < target
+        let mut left = 0;
+        let mut right = self.nums_restarts - 1;
+        while left < right {
+            let mid = (left + right + 1) / 2;
+            let region_offset = self.get_restart_point(mid);
+            let src = &self.data[region_offset as usize..self.restarts as usize];
+            match decode_entry_header(src) {
+                Some((shared, not_shared, _, n)) if shared == 0 => {
+                    let key_start = region_offset as usize + n;
+                    let mid_key = &self.data[key_start..key_start + not_shared as usize];
+                    if self.cmp.compare(mid_key, target) == Ordering::Less {
+                        // Key at "mid" is smaller than "target".  Therefore all
+                        // blocks before "mid" are uninteresting.
+                        left = mid;
+                    } else {
+                        // Key at "mid" is >= "target".  Therefore all blocks at or
+                        // after "mid" are uninteresting.
+                        right = mid - 1;
+                    }
+                }
+                _ => {
+                    self.corruption_error();
+                    return;
+                }
+            }
+        }
+
+        // Linear search (within restart block) for first key >= target
+        self.seek_to_restart_point(left);
+        loop {
+            if !self.parse_next_key() {
+                return;
+            }
+            if self.cmp.compare(&self.key, target) != Ordering::Less {
+                return;
+            }
+        }
     }
 
     fn next(&mut self) {
-        todo!()
+        assert!(self.valid());
+        self.parse_next_key();
     }
 
     fn key(&self) -> &[u8] {
-        todo!()
+        assert!(self.valid());
+        &self.key
     }
 
     fn value(&self) -> &[u8] {
-        todo!()
+        assert!(self.valid());
+        let val_offset = (self.key_offset + self.not_shared) as usize;
+        &self.data[val_offset..val_offset + self.value_len as usize]
     }
 
     fn prev(&mut self) {
-        todo!()
+        assert!(self.valid());
+        // Scan backwards to a restart point before current
+        let original = self.current;
+        while self.get_restart_point(self.restart_index) >= original {
+            if self.restart_index == 0 {
+                // No more entries
+                self.current = self.restarts;
+                self.restart_index = self.nums_restarts;
+                return;
+            }
+            self.restart_index -= 1;
+        }
+        self.seek_to_restart_point(self.restart_index);
+        // Loop until end of current entry hits the start of original entry
+        while self.parse_next_key() && self.next_entry_offset() < original {}
     }
 
-    fn status(&self) {
-        todo!()
+    fn status(&mut self) -> Result<(), Error> {
+        match &self.err {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
     }
 }
 
+// Decodes the header of the entry at the start of `src`.
+// Returns `(shared, not_shared, value_length, header_length)`, or `None`
+// if the header is malformed or the entry overruns `src`.
+fn decode_entry_header(src: &[u8]) -> Option<(u32, u32, u32, usize)> {
+    if src.len() < 3 {
+        return None;
+    }
+    let (shared, not_shared, value_len, n) = if (src[0] | src[1] | src[2]) < 128 {
+        // Fast path: all three values are encoded in one byte each
+        (src[0] as u32, src[1] as u32, src[2] as u32, 3)
+    } else {
+        let (shared, n0) = get_varint_32(src)?;
+        let (not_shared, n1) = get_varint_32(&src[n0..])?;
+        let (value_len, n2) = get_varint_32(&src[n0 + n1..])?;
+        (shared, not_shared, value_len, n0 + n1 + n2)
+    };
+    if src.len() - n < (not_shared + value_len) as usize {
+        return None;
+    }
+    Some((shared, not_shared, value_len, n))
+}
+
 impl<C: Comparator + Clone> BlockIterator<C> {
     pub fn new(cmp: C, data: Arc<Vec<u8>>, restarts: u32, nums_restarts: u32) -> Self {
         Self {
@@ -240,7 +330,7 @@ impl<C: Comparator + Clone> BlockIterator<C> {
             data,
             restarts,
             nums_restarts,
-            restart_index: 0,
+            restart_index: nums_restarts,
             current: restarts,
             shared: 0,
             not_shared: 0,
@@ -249,88 +339,75 @@ impl<C: Comparator + Clone> BlockIterator<C> {
             key: vec![],
         }
     }
-    // decode an entry which starts from current
-    fn decode_entry(&mut self) -> bool {
+
+    // Decodes the entry which starts at `next_entry_offset()` and makes it
+    // the current one. Returns false if there are no more entries or the
+    // entry is corrupted.
+    fn parse_next_key(&mut self) -> bool {
+        self.current = self.next_entry_offset();
         if self.current >= self.restarts {
+            // No more entries to return.  Mark as invalid.
             self.current = self.restarts;
             self.restart_index = self.nums_restarts;
             return false;
         }
 
-        let offset = self.current;
-        let src = &self.data[offset as usize..];
-        assert!(src.len() > 3);
-        if src[0] | src[1] | src[2] > 128u8 {
-            //fast path : all three values are encoded in one byte each
-            self.shared = offset;
-            self.not_shared = offset + 1;
-            self.value_len = get_varint_32(&src[offset as usize + 2..]).unwrap().0;
-            self.current += 3;
-            return true;
-        }
-        let (shared, n0) = if let Some((_shared, _n0)) = get_varint_32_prefix_ptr(0, 0, src) {
-            (_shared, _n0)
-        } else {
-            return false;
-        };
-        let (unshared, n1) =
-            if let Some((_unshared, _n1)) = get_varint_32_prefix_ptr(0, 0, &src[n0..]) {
-                (_unshared, _n1)
-            } else {
-                return false;
-            };
-        let (val_len, n2) =
-            if let Some((_val_len, _n2)) = get_varint_32_prefix_ptr(0, 0, &src[n0 + n1..]) {
-                (_val_len, _n2)
-            } else {
-                return false;
-            };
-        let n = (n1 + n2 + n0) as u32;
-        if offset + n + val_len + unshared > self.restarts {
-            self.corruption_error();
-            return false;
-        }
-        self.key_offset = self.current + n;
-        self.shared = shared;
-        self.not_shared = unshared;
-        self.value_len = val_len;
-        let total_key_len = shared + unshared;
-        self.key.resize(total_key_len as usize, 0);
-        let delta =
-            &self.data[self.key_offset as usize..(self.key_offset + self.not_shared) as usize];
-        //decompress key
-
-        for i in shared as usize..self.key.len() {
-            self.key[i] = delta[i - shared as usize];
-        }
-        //update restart index
-        while self.restart_index + 1 < self.nums_restarts
-            && self.get_restart_point(self.restart_index + 1) < self.current
-        {
-            self.restart_index += 1;
+        let src = &self.data[self.current as usize..self.restarts as usize];
+        match decode_entry_header(src) {
+            Some((shared, not_shared, value_len, n)) if shared as usize <= self.key.len() => {
+                self.key_offset = self.current + n as u32;
+                self.shared = shared;
+                self.not_shared = not_shared;
+                self.value_len = value_len;
+                // Decompress key
+                let delta_start = self.key_offset as usize;
+                self.key.truncate(shared as usize);
+                self.key
+                    .extend_from_slice(&self.data[delta_start..delta_start + not_shared as usize]);
+                // Update restart index
+                while self.restart_index + 1 < self.nums_restarts
+                    && self.get_restart_point(self.restart_index + 1) < self.current
+                {
+                    self.restart_index += 1;
+                }
+                true
+            }
+            _ => {
+                self.corruption_error();
+                false
+            }
         }
-        true
     }
+
     fn corruption_error(&mut self) {
-        self.err = Some(Error::Corruption("bad entry in block".to_owned()));
+        self.err = Some(Error::from(ErrorKind::Corruption(
+            "bad entry in block".to_owned(),
+        )));
         self.key.clear();
         self.current = self.restarts;
         self.restart_index = self.nums_restarts;
     }
+
     #[inline]
     fn next_entry_offset(&self) -> u32 {
         self.key_offset + self.not_shared + self.value_len
     }
+
     #[inline]
     fn get_restart_point(&self, index: u32) -> u32 {
         assert!(index < self.nums_restarts);
-        decode_fixed_32(&self.data[self.restarts as usize + (index as usize) << 2..])
+        decode_fixed_32(&self.data[self.restarts as usize + ((index as usize) << 2)..])
     }
+
     #[inline]
     fn seek_to_restart_point(&mut self, index: u32) {
         self.key.clear();
         self.restart_index = index;
-        self.current = self.get_restart_point(index);
+        // `current` will be fixed by `parse_next_key()`, which starts
+        // decoding at the end of the (empty) entry placed here.
+        self.key_offset = self.get_restart_point(index);
+        self.not_shared = 0;
+        self.value_len = 0;
     }
 }
 
@@ -412,4 +489,81 @@ mod test {
         iter.next();
         assert!(!iter.valid());
     }
+
+    #[test]
+    fn test_block_iter_forward_and_backward() {
+        let samples = vec!["1", "12", "123", "abc", "abd", "acd", "bbb"];
+        let block = Block::new(new_test_block()).unwrap();
+        let mut iter = block.iter(BytewiseComparator::default());
+        assert!(!iter.valid());
+
+        iter.seek_to_first();
+        for key in samples.iter() {
+            assert!(iter.valid());
+            assert_eq!(iter.key(), key.as_bytes());
+            assert_eq!(iter.value(), key.as_bytes());
+            iter.next();
+        }
+        assert!(!iter.valid());
+
+        iter.seek_to_last();
+        for key in samples.iter().rev() {
+            assert!(iter.valid());
+            assert_eq!(iter.key(), key.as_bytes());
+            assert_eq!(iter.value(), key.as_bytes());
+            iter.prev();
+        }
+        assert!(!iter.valid());
+        assert!(iter.status().is_ok());
+    }
+
+    #[test]
+    fn test_block_iter_seek() {
+        let block = Block::new(new_test_block()).unwrap();
+        let mut iter = block.iter(BytewiseComparator::default());
+        let tests = vec![
+            ("", Some("1")),
+            ("1", Some("1")),
+            ("10", Some("12")),
+            ("123", Some("123")),
+            ("2", Some("abc")),
+            ("abd", Some("abd")),
+            ("abe", Some("acd")),
+            ("b", Some("bbb")),
+            ("bbb", Some("bbb")),
+            ("bbc", None),
+            ("z", None),
+        ];
+        for (target, expect) in tests {
+            iter.seek(target.as_bytes());
+            match expect {
+                Some(key) => {
+                    assert!(iter.valid(), "seek {:?}", target);
+                    assert_eq!(iter.key(), key.as_bytes(), "seek {:?}", target);
+                }
+                None => assert!(!iter.valid(), "seek {:?}", target),
+            }
+        }
+        // prev across a restart point after seeking
+        iter.seek(b"abc");
+        iter.prev();
+        assert_eq!(iter.key(), b"123");
+        iter.prev();
+        assert_eq!(iter.key(), b"12");
+        iter.next();
+        iter.next();
+        assert_eq!(iter.key(), b"abc");
+    }
+
+    #[test]
+    fn test_block_iter_corrupted_entry() {
+        let mut data = new_test_block();
+        // Make the `unshared` length of the first entry run past the restarts array
+        data[1] = 0x7f;
+        let block = Block::new(data).unwrap();
+        let mut iter = block.iter(BytewiseComparator::default());
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        assert!(iter.status().unwrap_err().is_corruption());
+    }
 }
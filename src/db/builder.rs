@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use super::filename::{generate_filename, FileType};
+use super::format::InternalKey;
+use super::ldbiterator::LdbIterator;
+use super::table::TableBuilder;
+use super::table_cache::{TableCache, TableOptions};
+use super::version_edit::FileMetaData;
+use crate::env::WritableFile;
+use crate::options::ReadOptions;
+use crate::util::comparator::Comparator;
+use crate::util::status::Result;
+
+/// Build a Table file from the contents of `iter`.  The generated file
+/// will be named according to `meta.number`.  On success, the rest of
+/// `meta` will be filled with metadata about the generated table.
+/// If no data is present in `iter`, `meta.file_size` will be set to
+/// zero, and no Table file will be produced.
+pub fn build_table<C: Comparator + Clone, I: LdbIterator>(
+    dbname: &Path,
+    options: &TableOptions<C>,
+    table_cache: &TableCache<C>,
+    mut iter: I,
+    meta: &mut FileMetaData,
+) -> Result<()> {
+    meta.file_size = 0;
+    iter.seek_to_first();
+    let filename = generate_filename(dbname, FileType::Table, meta.number);
+    let result = if iter.valid() {
+        write_table(&filename, options, &mut iter, meta).and_then(|_| {
+            // Verify that the table is usable
+            let mut it =
+                table_cache.new_iterator(ReadOptions::default(), meta.number, meta.file_size)?;
+            it.seek_to_first();
+            it.status()
+        })
+    } else {
+        Ok(())
+    };
+    // Check for input iterator errors
+    let result = result.and(iter.status());
+    if result.is_err() || meta.file_size == 0 {
+        let _ = options.env.remove(&filename);
+    }
+    result
+}
+
+fn write_table<C: Comparator + Clone, I: LdbIterator>(
+    filename: &Path,
+    options: &TableOptions<C>,
+    iter: &mut I,
+    meta: &mut FileMetaData,
+) -> Result<()> {
+    let file = options.env.new_writable_file(filename)?;
+    let mut builder = TableBuilder::new(options.clone(), file);
+    meta.smallest = InternalKey::decode_from(iter.key());
+    let mut largest = vec![];
+    while iter.valid() {
+        largest.clear();
+        largest.extend_from_slice(iter.key());
+        builder.add(iter.key(), iter.value())?;
+        iter.next();
+    }
+    meta.largest = InternalKey::decode_from(&largest);
+
+    // Finish and check for builder errors
+    builder.finish()?;
+    meta.file_size = builder.file_size();
+    assert!(meta.file_size > 0);
+    // Finish and check for file errors
+    builder.into_inner().sync()
+}
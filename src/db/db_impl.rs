@@ -0,0 +1,2142 @@
+use std::cell::RefCell;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use super::builder::build_table;
+use super::db_iter::DBIterator;
+use super::filename::{generate_filename, parse_filename, set_current_file, FileType};
+use super::format::{
+    extract_user_key, InternalKey, InternalKeyComparator, LookUpKey, ParsedInteralKey, ValueType,
+    K_MAX_SEQUENCE_NUMBER, VALUE_TYPE_FOR_SEEK,
+};
+use super::inlineskiplist::MAX_NODE_ARENA_SIZE;
+use super::ldbiterator::LdbIterator;
+use super::log::{Reader, Reporter, Writer};
+use super::memtable::MemTable;
+use super::snapshot::{Snapshot, SnapshotList};
+use super::table::{MergingIterator, TableBuilder};
+use super::table_cache::{table_options, TableCache, TableOptions};
+use super::version_edit::{FileMetaData, VersionEdit, NUM_LEVELS};
+use super::version_set::{
+    Compaction, LogReporter, Version, VersionSet, L0_SLOWDOWN_WRITES_TRIGGER,
+    L0_STOP_WRITES_TRIGGER,
+};
+use super::write_batch::{WriteBatch, HEADER as WRITE_BATCH_HEADER};
+use super::SequenceNumber;
+use crate::env::{FileLock, WritableFile};
+use crate::options::{CompressionType, Options, ReadOptions, WriteOptions};
+use crate::util::comparator::{BytewiseComparator, Comparator};
+use crate::util::status::{Error, ErrorKind, Result};
+
+/// A `DB` is a persistent ordered map from keys to values.
+///
+/// A `DB` is safe for concurrent access from multiple threads without
+/// any external synchronization.
+pub struct DB<C: Comparator + Clone + Send + Sync + 'static = BytewiseComparator> {
+    inner: Arc<DBImpl<C>>,
+}
+
+impl<C: Comparator + Clone + Send + Sync + 'static> DB<C> {
+    /// Open the database with the specified `path`.
+    /// Returns an error if the database does not exist and
+    /// `options.create_if_missing` is false, or if it exists and
+    /// `options.error_if_exists` is true.
+    pub fn open<P: AsRef<Path>>(path: P, options: Options<C>) -> Result<Self> {
+        let db = Arc::new(DBImpl::new(path.as_ref(), options));
+        if let Err(e) = db.recover() {
+            db.shutdown();
+            return Err(e);
+        }
+        db.maybe_schedule_compaction(&mut db.state.lock().unwrap());
+        Ok(DB { inner: db })
+    }
+
+    /// Set the database entry for `key` to `value`.
+    /// Note: consider setting `options.sync = true`.
+    pub fn put(&self, options: WriteOptions, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.put(key, value);
+        self.write(options, batch)
+    }
+
+    /// Remove the database entry (if any) for `key`.
+    /// It is not an error if `key` did not exist in the database.
+    /// Note: consider setting `options.sync = true`.
+    pub fn delete(&self, options: WriteOptions, key: &[u8]) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.delete(key);
+        self.write(options, batch)
+    }
+
+    /// Apply the specified updates to the database atomically.
+    /// Note: consider setting `options.sync = true`.
+    pub fn write(&self, options: WriteOptions, batch: WriteBatch) -> Result<()> {
+        self.inner.write(options, batch)
+    }
+
+    /// Returns the value stored for `key` or `None` if the database
+    /// contains no entry for it.
+    pub fn get(&self, options: ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner.get(options, key)
+    }
+
+    /// Return an iterator over the contents of the database.
+    /// The iterator yields the newest value of every live key in user
+    /// key order.  It sees the state of `options.snapshot` or, if none,
+    /// the state of the database when it is created.
+    pub fn new_iterator(
+        &self,
+        options: ReadOptions,
+    ) -> Result<DBIterator<MergingIterator<InternalKeyComparator<C>>, C>> {
+        self.inner.new_iterator(options)
+    }
+
+    /// Return a handle to the current DB state.  Reads created with this
+    /// handle as `ReadOptions::snapshot` will observe a stable snapshot of
+    /// the current DB state.  The caller must hand the snapshot back to
+    /// `release_snapshot` when it is no longer needed.
+    pub fn get_snapshot(&self) -> Snapshot {
+        self.inner.get_snapshot()
+    }
+
+    /// Release a previously acquired snapshot.
+    pub fn release_snapshot(&self, snapshot: Snapshot) {
+        self.inner.release_snapshot(snapshot)
+    }
+
+    /// Compact the underlying storage for the key range `[begin, end]`.
+    /// In particular, deleted and overwritten versions are discarded,
+    /// and the data is rearranged to reduce the cost of operations
+    /// needed to access the data.  This operation should typically only
+    /// be invoked by users who understand the underlying implementation.
+    ///
+    /// `begin == None` is treated as a key before all keys in the database.
+    /// `end == None` is treated as a key after all keys in the database.
+    /// Therefore the following call will compact the entire database:
+    ///    `db.compact_range(None, None)`
+    pub fn compact_range(&self, begin: Option<&[u8]>, end: Option<&[u8]>) -> Result<()> {
+        self.inner.compact_range(begin, end)
+    }
+
+    /// DB implementations can export properties about their state via this
+    /// method.  If `property` is a valid property understood by this DB
+    /// implementation, returns its value.  Otherwise returns `None`.
+    ///
+    /// Valid property names include:
+    ///
+    /// * "leveldb.num-files-at-level<N>" - return the number of files at
+    ///   level <N>, where <N> is an ASCII representation of a level number
+    ///   (e.g. "0").
+    /// * "leveldb.stats" - returns a multi-line string that describes
+    ///   statistics about the files of the DB.
+    /// * "leveldb.sstables" - returns a multi-line string that describes
+    ///   all of the sstables that make up the db contents.
+    /// * "leveldb.approximate-memory-usage" - returns the approximate number
+    ///   of bytes of memory in use by the memtables.
+    pub fn get_property(&self, property: &str) -> Option<String> {
+        self.inner.get_property(property)
+    }
+
+    /// Wait for the pending memtable flush and the running background
+    /// compaction to finish, then flush and close the log.  Every
+    /// following operation on this `DB` returns `ErrorKind::DBClosed`.
+    pub fn close(&self) -> Result<()> {
+        self.inner.close()
+    }
+}
+
+impl<C: Comparator + Clone + Send + Sync + 'static> Drop for DB<C> {
+    fn drop(&mut self) {
+        self.inner.shutdown();
+    }
+}
+
+struct DBImpl<C: Comparator + Clone> {
+    dbname: PathBuf,
+    options: Options<C>,
+    internal_comparator: InternalKeyComparator<C>,
+    table_options: TableOptions<C>,
+    table_cache: Arc<TableCache<C>>,
+    state: Mutex<DBState<C>>,
+    // Signalled when background work finishes
+    background_work_finished_signal: Condvar,
+    shutting_down: AtomicBool,
+    // So the background thread can detect a pending immutable memtable
+    // without taking the lock
+    has_imm: AtomicBool,
+}
+
+// The mutable state of a `DBImpl`, guarded by `DBImpl::state`
+struct DBState<C: Comparator + Clone> {
+    mem: Arc<MemTable<C>>,
+    // Memtable being compacted
+    imm: Option<Arc<MemTable<C>>>,
+    log: Option<Writer<Box<dyn WritableFile>>>,
+    logfile_number: u64,
+    versions: VersionSet<C>,
+    // Table files that are being written by a compaction and must not be
+    // deleted although no version references them yet
+    pending_outputs: HashSet<u64>,
+    // Has a background compaction been scheduled or is running?
+    background_compaction_scheduled: bool,
+    // The first error of a background compaction or log write.  Once
+    // set, every following write fails with it.
+    bg_error: Option<Error>,
+    // The compaction requested by `compact_range`, if any
+    manual_compaction: Option<ManualCompaction>,
+    snapshots: SnapshotList,
+    // Lock over the persistent DB state.  Some iff successfully acquired.
+    db_lock: Option<FileLock>,
+    closed: bool,
+}
+
+// Information for a manual compaction
+struct ManualCompaction {
+    level: usize,
+    done: bool,
+    // None means beginning of key range
+    begin: Option<InternalKey>,
+    // None means end of key range
+    end: Option<InternalKey>,
+}
+
+impl<C: Comparator + Clone + 'static> DBImpl<C> {
+    fn new(dbname: &Path, options: Options<C>) -> Self {
+        let internal_comparator = InternalKeyComparator::new(options.comparator.clone());
+        let mem = Arc::new(MemTable::new(
+            internal_comparator.clone(),
+            mem_arena_capacity(options.write_buffer_size),
+        ));
+        let table_options = table_options(&options);
+        let table_cache = Arc::new(TableCache::new(dbname, table_options.clone()));
+        let versions = VersionSet::new(dbname, options.clone(), table_cache.clone());
+        DBImpl {
+            dbname: dbname.to_owned(),
+            options,
+            internal_comparator,
+            table_options,
+            table_cache,
+            state: Mutex::new(DBState {
+                mem,
+                imm: None,
+                log: None,
+                logfile_number: 0,
+                versions,
+                pending_outputs: HashSet::new(),
+                background_compaction_scheduled: false,
+                bg_error: None,
+                manual_compaction: None,
+                snapshots: SnapshotList::new(),
+                db_lock: None,
+                closed: false,
+            }),
+            background_work_finished_signal: Condvar::new(),
+            shutting_down: AtomicBool::new(false),
+            has_imm: AtomicBool::new(false),
+        }
+    }
+
+    // Creates the MANIFEST and CURRENT files of an empty database
+    fn new_db(&self) -> Result<()> {
+        let mut new_db = VersionEdit::new();
+        new_db.set_comparator_name(self.options.comparator.name());
+        new_db.set_log_number(0);
+        new_db.set_next_file(2);
+        new_db.set_last_sequence(0);
+
+        let manifest = generate_filename(&self.dbname, FileType::Descriptor, 1);
+        let env = self.options.env.as_ref();
+        let result = env
+            .new_writable_file(&manifest)
+            .and_then(|file| {
+                let mut log = Writer::new(file);
+                let mut record = vec![];
+                new_db.encode_to(&mut record);
+                log.add_record(&record)?;
+                log.flush()?;
+                log.get_mut().sync()
+            })
+            // Make "CURRENT" file that points to the new manifest file.
+            .and_then(|_| set_current_file(env, &self.dbname, 1));
+        if result.is_err() {
+            let _ = env.remove(&manifest);
+        }
+        result
+    }
+
+    // Recovers the descriptor from the MANIFEST, replays the log files that
+    // are not covered by it into the memtable and starts a new log for the
+    // following writes.
+    fn recover(&self) -> Result<()> {
+        let compression = self.options.compression;
+        if compression != CompressionType::NoCompression
+            && self.options.compressors.get(compression).is_none()
+        {
+            return Err(ErrorKind::InvalidArgument(format!(
+                "no compressor registered for {:?}",
+                compression
+            ))
+            .into());
+        }
+        let env = self.options.env.as_ref();
+        let exists = env.file_exists(&generate_filename(&self.dbname, FileType::Current, 0));
+        if !exists && !self.options.create_if_missing {
+            return Err(ErrorKind::InvalidArgument(format!(
+                "{}: does not exist (create_if_missing is false)",
+                self.dbname.display()
+            ))
+            .into());
+        }
+        if exists && self.options.error_if_exists {
+            return Err(ErrorKind::InvalidArgument(format!(
+                "{}: exists (error_if_exists is true)",
+                self.dbname.display()
+            ))
+            .into());
+        }
+        env.create_dir(&self.dbname)?;
+        let lock = env.lock_file(&generate_filename(&self.dbname, FileType::Lock, 0))?;
+        self.state.lock().unwrap().db_lock = Some(lock);
+        if !exists {
+            self.new_db()?;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.versions.recover()?;
+
+        // Recover from all newer log files than the ones named in the
+        // descriptor (new log files may have been added by the previous
+        // incarnation without registering them in the descriptor).
+        //
+        // Note that prev_log_number() is no longer used, but we pay
+        // attention to it in case we are recovering a database
+        // produced by an older version of leveldb.
+        let min_log = state.versions.log_number();
+        let prev_log = state.versions.prev_log_number();
+        let mut logs = vec![];
+        for name in env.get_children(&self.dbname)? {
+            if let Some((FileType::Log, number)) = parse_filename(&name) {
+                if number >= min_log || number == prev_log {
+                    logs.push(number);
+                }
+            }
+        }
+        // Recover in the order in which the logs were generated
+        logs.sort_unstable();
+        let mut edit = VersionEdit::new();
+        let mut max_sequence = state.versions.last_sequence();
+        for number in logs {
+            max_sequence = max_sequence.max(self.replay_log_file(number, &mut state, &mut edit)?);
+            // The previous incarnation may not have written any MANIFEST
+            // records after allocating this log number.  So we manually
+            // update the file number allocation counter in VersionSet.
+            state.versions.mark_file_number_used(number);
+        }
+        state.versions.set_last_sequence(max_sequence);
+        if !state.mem.is_empty() {
+            let mem = std::mem::replace(&mut state.mem, Arc::new(self.new_memtable(0)));
+            let number = state.versions.new_file_number();
+            self.write_level0_table(number, &mem, &mut edit, None)?;
+        }
+
+        let number = state.versions.new_file_number();
+        let file =
+            env.new_writable_file(&generate_filename(&self.dbname, FileType::Log, number))?;
+        state.log = Some(Writer::new(file));
+        state.logfile_number = number;
+
+        // Every recovered log has been written to a table, so the
+        // older logs are not needed anymore
+        edit.set_prev_log_number(0);
+        edit.set_log_number(number);
+        state.versions.log_and_apply(&mut edit)?;
+        self.delete_obsolete_files(&mut state);
+        Ok(())
+    }
+
+    // Inserts the batches of a log file into the memtable, flushing it to
+    // level-0 tables when it gets full, and returns the largest sequence
+    // number found
+    fn replay_log_file(
+        &self,
+        number: u64,
+        state: &mut DBState<C>,
+        edit: &mut VersionEdit,
+    ) -> Result<SequenceNumber> {
+        let file = self.options.env.new_sequential_file(&generate_filename(
+            &self.dbname,
+            FileType::Log,
+            number,
+        ))?;
+        // Corruptions are only fatal with paranoid checks, otherwise the
+        // damaged records are dropped
+        let status = Rc::new(RefCell::new(Ok(())));
+        let reporter: Option<Box<dyn Reporter>> = if self.options.paranoid_checks {
+            Some(Box::new(LogReporter {
+                status: status.clone(),
+            }))
+        } else {
+            None
+        };
+        // We intentionally make the log reader do checksumming so that
+        // corruptions cause entire commits to be skipped instead of
+        // propagating bad information (like overly large sequence
+        // numbers).
+        let mut reader = Reader::new(file, reporter, true, 0);
+        let mut record = vec![];
+        let mut batch = WriteBatch::new();
+        let mut max_sequence = 0;
+        while reader.read_record(&mut record) && status.borrow().is_ok() {
+            if record.len() < WRITE_BATCH_HEADER {
+                // Skip the log record that is too small to be a WriteBatch
+                continue;
+            }
+            batch.set_contents(&record);
+            if !self.has_room_for(&state.mem, &batch) {
+                let reserve = batch_arena_reserve(&batch);
+                let mem = std::mem::replace(&mut state.mem, Arc::new(self.new_memtable(reserve)));
+                let number = state.versions.new_file_number();
+                self.write_level0_table(number, &mem, edit, None)?;
+            }
+            batch.insert_into(&state.mem)?;
+            let last_sequence = batch.sequence() + u64::from(batch.count()) - 1;
+            max_sequence = max_sequence.max(last_sequence);
+        }
+        status.replace(Ok(()))?;
+        Ok(max_sequence)
+    }
+
+    // Writes the contents of `mem` to the table `number` and adds the
+    // table to `edit`.  The table goes to level 0 unless `base` is given
+    // and a deeper level can take it without overlap.  Does not touch the
+    // state, so the lock may be released around it.
+    fn write_level0_table(
+        &self,
+        number: u64,
+        mem: &MemTable<C>,
+        edit: &mut VersionEdit,
+        base: Option<&Version<C>>,
+    ) -> Result<()> {
+        let mut meta = FileMetaData {
+            number,
+            ..FileMetaData::default()
+        };
+        build_table(
+            &self.dbname,
+            &self.table_options,
+            &self.table_cache,
+            mem.iter(),
+            &mut meta,
+        )?;
+
+        // Note that if file_size is zero, the file has been deleted and
+        // should not be added to the manifest.
+        if meta.file_size > 0 {
+            let level = match base {
+                Some(v) => v.pick_level_for_memtable_output(
+                    meta.smallest.user_key(),
+                    meta.largest.user_key(),
+                ),
+                None => 0,
+            };
+            edit.add_file(
+                level,
+                meta.number,
+                meta.file_size,
+                meta.smallest,
+                meta.largest,
+            );
+        }
+        Ok(())
+    }
+
+    // Writes the immutable memtable to a table and installs it in a new
+    // version.  The logs older than the current one become obsolete.  The
+    // lock is released while the table is built.
+    fn compact_mem_table<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, DBState<C>>,
+    ) -> MutexGuard<'a, DBState<C>> {
+        let imm = state.imm.clone().unwrap();
+        // Save the contents of the memtable as a new Table
+        let mut edit = VersionEdit::new();
+        let base = state.versions.current();
+        let number = state.versions.new_file_number();
+        state.pending_outputs.insert(number);
+        drop(state);
+        let result = self.write_level0_table(number, &imm, &mut edit, Some(&base));
+        let mut state = self.state.lock().unwrap();
+        state.pending_outputs.remove(&number);
+
+        let result = result.and_then(|_| {
+            if self.shutting_down.load(Ordering::Acquire) {
+                return Err(ErrorKind::DBClosed(format!(
+                    "{}: closed during memtable compaction",
+                    self.dbname.display()
+                ))
+                .into());
+            }
+            // Replace immutable memtable with the generated Table
+            edit.set_prev_log_number(0);
+            // Earlier logs no longer needed
+            edit.set_log_number(state.logfile_number);
+            state.versions.log_and_apply(&mut edit)
+        });
+        match result {
+            Ok(()) => {
+                state.imm = None;
+                self.has_imm.store(false, Ordering::Release);
+                self.delete_obsolete_files(&mut state);
+            }
+            Err(e) => self.record_background_error(&mut state, e),
+        }
+        state
+    }
+
+    // Performs the compaction picked by the version set (or moves a single
+    // file down a level when no merge is needed).  The lock is released
+    // while the inputs are merged.
+    fn background_compaction<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, DBState<C>>,
+    ) -> MutexGuard<'a, DBState<C>> {
+        if state.imm.is_some() {
+            return self.compact_mem_table(state);
+        }
+
+        let is_manual = state.manual_compaction.as_ref().map_or(false, |m| !m.done);
+        let mut c = match state.manual_compaction.as_ref().filter(|m| !m.done) {
+            Some(m) => {
+                let (level, begin, end) = (m.level, m.begin.clone(), m.end.clone());
+                let c = state
+                    .versions
+                    .compact_range(level, begin.as_ref(), end.as_ref());
+                let m = state.manual_compaction.as_mut().unwrap();
+                match &c {
+                    // We only compacted part of the requested range.  Update
+                    // the manual compaction to resume where we left off.
+                    Some(c) => m.begin = c.inputs[0].last().map(|f| f.largest.clone()),
+                    None => m.done = true,
+                }
+                match c {
+                    Some(c) => c,
+                    None => return state,
+                }
+            }
+            None => match state.versions.pick_compaction() {
+                Some(c) => c,
+                None => return state,
+            },
+        };
+        // A manual compaction is asked to rewrite the files, so it never
+        // just moves them
+        let result = if !is_manual && c.is_trivial_move() {
+            // Move file to next level
+            let f = c.inputs[0][0].clone();
+            let level = c.level();
+            c.edit().delete_file(level, f.number);
+            c.edit().add_file(
+                level + 1,
+                f.number,
+                f.file_size,
+                f.smallest.clone(),
+                f.largest.clone(),
+            );
+            state.versions.log_and_apply(c.edit())
+        } else {
+            let input = state.versions.make_input_iterator(&c);
+            let smallest_snapshot = state
+                .snapshots
+                .oldest()
+                .unwrap_or_else(|| state.versions.last_sequence());
+            let mut compact = CompactionState::new(c, smallest_snapshot);
+            // Release the lock while we're actually doing the compaction work
+            drop(state);
+            let result =
+                input.and_then(|mut input| self.do_compaction_work(&mut compact, &mut input));
+            state = self.state.lock().unwrap();
+            let result =
+                result.and_then(|_| self.install_compaction_results(&mut state, &mut compact));
+            self.cleanup_compaction(&mut state, compact);
+            result
+        };
+        match result {
+            Ok(()) => self.delete_obsolete_files(&mut state),
+            // Ignore compaction errors found during shutting down
+            Err(_) if self.shutting_down.load(Ordering::Acquire) => {}
+            Err(e) => self.record_background_error(&mut state, e),
+        }
+        state
+    }
+
+    // Merges the inputs of the compaction into new tables at level+1,
+    // dropping the entries no snapshot can see.  Called without the lock.
+    fn do_compaction_work(
+        &self,
+        compact: &mut CompactionState<C>,
+        input: &mut MergingIterator<InternalKeyComparator<C>>,
+    ) -> Result<()> {
+        let ucmp = &self.internal_comparator.user_comparator;
+        input.seek_to_first();
+        let mut current_user_key: Option<Vec<u8>> = None;
+        let mut last_sequence_for_key = K_MAX_SEQUENCE_NUMBER;
+        while input.valid() && !self.shutting_down.load(Ordering::Acquire) {
+            // Prioritize immutable compaction work
+            if self.has_imm.load(Ordering::Acquire) {
+                let mut state = self.state.lock().unwrap();
+                if state.imm.is_some() {
+                    state = self.compact_mem_table(state);
+                    // Wake up make_room_for_write() if necessary.
+                    self.background_work_finished_signal.notify_all();
+                }
+            }
+
+            let key = input.key().to_vec();
+            let key = key.as_slice();
+            // should_stop_before() tracks the grandparent overlap, so it has
+            // to see every key, including the ones before the first output
+            if compact.compaction.should_stop_before(key) && compact.builder.is_some() {
+                self.finish_compaction_output_file(compact, input.status())?;
+            }
+
+            // Handle key/value, add to state, etc.
+            let mut drop = false;
+            match ParsedInteralKey::decode_from(key) {
+                None => {
+                    // Do not hide error keys
+                    current_user_key = None;
+                    last_sequence_for_key = K_MAX_SEQUENCE_NUMBER;
+                }
+                Some(ikey) => {
+                    let user_key = extract_user_key(key);
+                    if current_user_key
+                        .as_ref()
+                        .map_or(true, |k| ucmp.compare(user_key, k) != CmpOrdering::Equal)
+                    {
+                        // First occurrence of this user key
+                        current_user_key = Some(user_key.to_vec());
+                        last_sequence_for_key = K_MAX_SEQUENCE_NUMBER;
+                    }
+
+                    if last_sequence_for_key <= compact.smallest_snapshot {
+                        // Hidden by an newer entry for same user key
+                        drop = true; // (A)
+                    } else if ikey.value_type() == ValueType::KTypeDeletion
+                        && ikey.sequence() <= compact.smallest_snapshot
+                        && compact.compaction.is_base_level_for_key(user_key)
+                    {
+                        // For this user key:
+                        // (1) there is no data in higher levels
+                        // (2) data in lower levels will have larger sequence numbers
+                        // (3) data in layers that are being compacted here and have
+                        //     smaller sequence numbers will be dropped in the next
+                        //     few iterations of this loop (by rule (A) above).
+                        // Therefore this deletion marker is obsolete and can be dropped.
+                        drop = true;
+                    }
+                    last_sequence_for_key = ikey.sequence();
+                }
+            }
+
+            if !drop {
+                // Open output file if necessary
+                if compact.builder.is_none() {
+                    self.open_compaction_output_file(compact)?;
+                }
+                let builder = compact.builder.as_mut().unwrap();
+                let output = compact.outputs.last_mut().unwrap();
+                if builder.num_entries() == 0 {
+                    output.smallest = InternalKey::decode_from(key);
+                }
+                output.largest = InternalKey::decode_from(key);
+                builder.add(key, input.value())?;
+
+                // Close output file if it is big enough
+                if builder.file_size() >= compact.compaction.max_output_file_size() {
+                    self.finish_compaction_output_file(compact, input.status())?;
+                }
+            }
+            input.next();
+        }
+
+        if self.shutting_down.load(Ordering::Acquire) {
+            return Err(ErrorKind::DBClosed(format!(
+                "{}: closed during compaction",
+                self.dbname.display()
+            ))
+            .into());
+        }
+        if compact.builder.is_some() {
+            self.finish_compaction_output_file(compact, input.status())?;
+        }
+        input.status()
+    }
+
+    // Starts a new output table of the compaction
+    fn open_compaction_output_file(&self, compact: &mut CompactionState<C>) -> Result<()> {
+        assert!(compact.builder.is_none());
+        let number = {
+            let mut state = self.state.lock().unwrap();
+            let number = state.versions.new_file_number();
+            state.pending_outputs.insert(number);
+            number
+        };
+        compact.outputs.push(FileMetaData {
+            number,
+            ..FileMetaData::default()
+        });
+
+        // Make the output file
+        let file = self.options.env.new_writable_file(&generate_filename(
+            &self.dbname,
+            FileType::Table,
+            number,
+        ))?;
+        compact.builder = Some(TableBuilder::new(self.table_options.clone(), file));
+        Ok(())
+    }
+
+    // Finishes the current output table of the compaction, or abandons it
+    // if `input_status` is an error
+    fn finish_compaction_output_file(
+        &self,
+        compact: &mut CompactionState<C>,
+        input_status: Result<()>,
+    ) -> Result<()> {
+        let mut builder = compact.builder.take().unwrap();
+        let output = compact.outputs.last_mut().unwrap();
+        assert!(output.number != 0);
+
+        // Check for iterator errors
+        let current_entries = builder.num_entries();
+        if let Err(e) = input_status {
+            builder.abandon();
+            return Err(e);
+        }
+        builder.finish()?;
+        output.file_size = builder.file_size();
+
+        // Finish and check for file errors
+        builder.into_inner().sync()?;
+
+        if current_entries > 0 {
+            // Verify that the table is usable
+            let mut it = self.table_cache.new_iterator(
+                ReadOptions::default(),
+                output.number,
+                output.file_size,
+            )?;
+            it.seek_to_first();
+            it.status()?;
+        }
+        Ok(())
+    }
+
+    // Installs the outputs of the compaction in place of its inputs
+    fn install_compaction_results(
+        &self,
+        state: &mut DBState<C>,
+        compact: &mut CompactionState<C>,
+    ) -> Result<()> {
+        // Add compaction outputs
+        let c = &mut compact.compaction;
+        c.add_input_deletions();
+        let level = c.level();
+        for output in compact.outputs.iter() {
+            c.edit().add_file(
+                level + 1,
+                output.number,
+                output.file_size,
+                output.smallest.clone(),
+                output.largest.clone(),
+            );
+        }
+        state.versions.log_and_apply(c.edit())
+    }
+
+    fn cleanup_compaction(&self, state: &mut DBState<C>, compact: CompactionState<C>) {
+        if let Some(mut builder) = compact.builder {
+            // May happen if we get a shutdown call in the middle of compaction
+            builder.abandon();
+        }
+        for output in compact.outputs.iter() {
+            state.pending_outputs.remove(&output.number);
+        }
+    }
+
+    fn record_background_error(&self, state: &mut DBState<C>, e: Error) {
+        if state.bg_error.is_none() {
+            state.bg_error = Some(e);
+            self.background_work_finished_signal.notify_all();
+        }
+    }
+
+    // Returns whether `batch` can be inserted into `mem` without exceeding
+    // `write_buffer_size` by more than the batch itself
+    fn has_room_for(&self, mem: &MemTable<C>, batch: &WriteBatch) -> bool {
+        mem.approximate_memory_usage() <= self.options.write_buffer_size
+            && batch_arena_reserve(batch) <= self.options.write_buffer_size
+    }
+
+    // Creates a memtable that can hold `write_buffer_size` bytes and then
+    // take `reserve` more bytes of arena memory
+    fn new_memtable(&self, reserve: usize) -> MemTable<C> {
+        MemTable::new(
+            self.internal_comparator.clone(),
+            mem_arena_capacity(self.options.write_buffer_size.max(reserve)),
+        )
+    }
+
+    // Deletes any unneeded files.  Failures are ignored on purpose: a file
+    // left behind is retried by the next call and must not fail the db.
+    fn delete_obsolete_files(&self, state: &mut DBState<C>) {
+        if state.bg_error.is_some() {
+            // After a background error, we don't know whether a new version may
+            // or may not have been committed, so we cannot safely garbage collect.
+            return;
+        }
+
+        // Make a set of all of the live files
+        let live = state.versions.live_files();
+        let versions = &state.versions;
+        let env = self.options.env.as_ref();
+        let children = match env.get_children(&self.dbname) {
+            Ok(children) => children,
+            Err(_) => return,
+        };
+        for name in children {
+            if let Some((file_type, number)) = parse_filename(&name) {
+                let keep = match file_type {
+                    FileType::Log => {
+                        number >= versions.log_number() || number == versions.prev_log_number()
+                    }
+                    // Keep my manifest file, and any newer incarnations'
+                    // (in case there is a race that allows other incarnations)
+                    FileType::Descriptor => number >= versions.manifest_file_number(),
+                    FileType::Table => {
+                        live.contains(&number) || state.pending_outputs.contains(&number)
+                    }
+                    FileType::Temp => false,
+                    _ => true,
+                };
+                if !keep {
+                    if file_type == FileType::Table {
+                        self.table_cache.evict(number);
+                    }
+                    let _ = env.remove(&self.dbname.join(&name));
+                }
+            }
+        }
+    }
+
+    fn get(&self, options: ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (lookup_key, current) = {
+            let state = self.lock_state()?;
+            let sequence = match options.snapshot {
+                Some(snapshot) => snapshot.sequence(),
+                None => state.versions.last_sequence(),
+            };
+            let lookup_key = LookUpKey::new(key, sequence);
+            // First look in the memtable, then in the immutable memtable (if any).
+            for mem in std::iter::once(&state.mem).chain(state.imm.iter()) {
+                match mem.get(&lookup_key) {
+                    Some(Ok(value)) => return Ok(Some(value)),
+                    // The key has been deleted
+                    Some(Err(e)) if e.is_not_found() => return Ok(None),
+                    Some(Err(e)) => return Err(e),
+                    None => {}
+                }
+            }
+            (lookup_key, state.versions.current())
+        };
+        // Then search the tables without holding the lock
+        current.get(options.without_snapshot(), &lookup_key)
+    }
+
+    fn new_iterator(
+        &self,
+        options: ReadOptions,
+    ) -> Result<DBIterator<MergingIterator<InternalKeyComparator<C>>, C>> {
+        let state = self.lock_state()?;
+        let sequence = match options.snapshot {
+            Some(snapshot) => snapshot.sequence(),
+            None => state.versions.last_sequence(),
+        };
+        // Collect together all needed child iterators
+        let mut iters: Vec<Box<dyn LdbIterator>> = vec![Box::new(state.mem.iter())];
+        if let Some(imm) = &state.imm {
+            iters.push(Box::new(imm.iter()));
+        }
+        state
+            .versions
+            .current()
+            .add_iterators(options.without_snapshot(), &mut iters)?;
+        let internal_iter = MergingIterator::new(self.internal_comparator.clone(), iters);
+        Ok(DBIterator::new(
+            internal_iter,
+            self.options.comparator.clone(),
+            sequence,
+        ))
+    }
+
+    fn get_snapshot(&self) -> Snapshot {
+        let mut state = self.state.lock().unwrap();
+        let sequence = state.versions.last_sequence();
+        state.snapshots.acquire(sequence)
+    }
+
+    fn release_snapshot(&self, snapshot: Snapshot) {
+        self.state.lock().unwrap().snapshots.release(snapshot)
+    }
+
+    fn get_property(&self, property: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let property = property.strip_prefix("leveldb.")?;
+        if let Some(level) = property.strip_prefix("num-files-at-level") {
+            let level: usize = level.parse().ok()?;
+            if level >= NUM_LEVELS {
+                return None;
+            }
+            return Some(state.versions.num_level_files(level).to_string());
+        }
+        match property {
+            "stats" => {
+                let mut value = String::from("Level  Files Size(MB)\n--------------------\n");
+                for level in 0..NUM_LEVELS {
+                    let files = state.versions.num_level_files(level);
+                    if files > 0 {
+                        value.push_str(&format!(
+                            "{:>3} {:>8} {:>8.0}\n",
+                            level,
+                            files,
+                            state.versions.num_level_bytes(level) as f64 / 1048576.0
+                        ));
+                    }
+                }
+                Some(value)
+            }
+            "sstables" => Some(format!("{:?}", state.versions.current())),
+            "approximate-memory-usage" => {
+                let mut total = state.mem.approximate_memory_usage();
+                if let Some(imm) = &state.imm {
+                    total += imm.approximate_memory_usage();
+                }
+                Some(total.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn close(&self) -> Result<()> {
+        let mut state = self.lock_state()?;
+        state.closed = true;
+        // Wake up the writers waiting for room
+        self.background_work_finished_signal.notify_all();
+        // Let the pending memtable flush finish, or the entries of the
+        // immutable memtable would only be found in an older log
+        while state.imm.is_some() && state.background_compaction_scheduled {
+            state = self.background_work_finished_signal.wait(state).unwrap();
+        }
+        state = self.wait_for_background_work(state);
+        let mut result = Ok(());
+        if let Some(mut log) = state.log.take() {
+            result = log.flush().and_then(|_| log.get_mut().sync());
+            if let Err(e) = &result {
+                self.record_background_error(&mut state, e.clone());
+            }
+        }
+        // Unlock the db even if the log could not be synced, the first
+        // error is returned
+        if let Some(lock) = state.db_lock.take() {
+            result = result.and(self.options.env.unlock_file(lock));
+        }
+        result
+    }
+
+    // Keeps new background compactions from being scheduled and waits
+    // for the running one to finish
+    fn shutdown(&self) {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            // A thread panicked while holding the lock, there is no
+            // consistent state left to wait on
+            Err(_) => return,
+        };
+        let mut state = self.wait_for_background_work(state);
+        if let Some(lock) = state.db_lock.take() {
+            let _ = self.options.env.unlock_file(lock);
+        }
+    }
+
+    fn wait_for_background_work<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, DBState<C>>,
+    ) -> MutexGuard<'a, DBState<C>> {
+        self.shutting_down.store(true, Ordering::Release);
+        while state.background_compaction_scheduled {
+            state = self.background_work_finished_signal.wait(state).unwrap();
+        }
+        state
+    }
+
+    // Acquires the state lock and makes sure the db is still open
+    fn lock_state(&self) -> Result<MutexGuard<DBState<C>>> {
+        let state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(ErrorKind::DBClosed(self.dbname.display().to_string()).into());
+        }
+        Ok(state)
+    }
+}
+
+impl<C: Comparator + Clone + Send + Sync + 'static> DBImpl<C> {
+    fn write(self: &Arc<Self>, options: WriteOptions, mut batch: WriteBatch) -> Result<()> {
+        let state = self.lock_state()?;
+        let mut state = self.make_room_for_write(state, false, &batch)?;
+        let sequence = state.versions.last_sequence() + 1;
+        batch.set_sequence(sequence);
+        let log = state.log.as_mut().unwrap();
+        let result = log.add_record(batch.contents()).and_then(|_| {
+            if options.sync {
+                log.flush()?;
+                log.get_mut().sync()?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            // The state of the log file is indeterminate: the log record we
+            // just added may or may not show up when the DB is re-opened.
+            // So we force the DB into a mode where all future writes fail.
+            self.record_background_error(&mut state, e.clone());
+            return Err(e);
+        }
+        batch.insert_into(&state.mem)?;
+        let last_sequence = sequence + u64::from(batch.count()) - 1;
+        state.versions.set_last_sequence(last_sequence);
+        Ok(())
+    }
+
+    // Makes sure the memtable has room for `batch`: switches to a new
+    // memtable and log once the current memtable is full (or `force` is
+    // true) and hands the full one over to the background thread.
+    // Writes are delayed while level 0 has too many files.
+    fn make_room_for_write<'a>(
+        self: &'a Arc<Self>,
+        mut state: MutexGuard<'a, DBState<C>>,
+        force: bool,
+        batch: &WriteBatch,
+    ) -> Result<MutexGuard<'a, DBState<C>>> {
+        let mut allow_delay = !force;
+        loop {
+            if state.closed {
+                return Err(ErrorKind::DBClosed(self.dbname.display().to_string()).into());
+            } else if let Some(e) = &state.bg_error {
+                // Yield previous error
+                return Err(e.clone());
+            } else if allow_delay && state.versions.num_level_files(0) >= L0_SLOWDOWN_WRITES_TRIGGER
+            {
+                // We are getting close to hitting a hard limit on the number of
+                // L0 files.  Rather than delaying a single write by several
+                // seconds when we hit the hard limit, start delaying each
+                // individual write by 1ms to reduce latency variance.  Also,
+                // this delay hands over some CPU to the compaction thread in
+                // case it is sharing the same core as the writer.
+                drop(state);
+                self.options.env.sleep_for_microseconds(1000);
+                // Do not delay a single write more than once
+                allow_delay = false;
+                state = self.lock_state()?;
+            } else if !force && self.has_room_for(&state.mem, batch) {
+                // There is room in current memtable
+                return Ok(state);
+            } else if state.imm.is_some() {
+                // We have filled up the current memtable, but the previous
+                // one is still being compacted, so we wait.
+                state = self.background_work_finished_signal.wait(state).unwrap();
+            } else if state.versions.num_level_files(0) >= L0_STOP_WRITES_TRIGGER {
+                // There are too many level-0 files.
+                state = self.background_work_finished_signal.wait(state).unwrap();
+            } else {
+                // Attempt to switch to a new memtable and trigger compaction of old
+                let new_log_number = state.versions.new_file_number();
+                let file = match self.options.env.new_writable_file(&generate_filename(
+                    &self.dbname,
+                    FileType::Log,
+                    new_log_number,
+                )) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        // Avoid chewing through file number space in a tight loop.
+                        state.versions.reuse_file_number(new_log_number);
+                        return Err(e);
+                    }
+                };
+                if let Some(mut log) = state.log.replace(Writer::new(file)) {
+                    if let Err(e) = log.flush() {
+                        // We may have lost some data written to the previous
+                        // log file.  Switch to the new log file anyway, but
+                        // record as a background error so we do not attempt
+                        // any more writes.
+                        self.record_background_error(&mut state, e);
+                    }
+                }
+                state.logfile_number = new_log_number;
+                let mem = std::mem::replace(
+                    &mut state.mem,
+                    Arc::new(self.new_memtable(batch_arena_reserve(batch))),
+                );
+                state.imm = Some(mem);
+                self.has_imm.store(true, Ordering::Release);
+                self.maybe_schedule_compaction(&mut state);
+                // The new memtable is large enough for `batch`
+                return Ok(state);
+            }
+        }
+    }
+
+    fn compact_range(self: &Arc<Self>, begin: Option<&[u8]>, end: Option<&[u8]>) -> Result<()> {
+        let mut max_level_with_files = 1;
+        {
+            let state = self.lock_state()?;
+            let base = state.versions.current();
+            for level in 1..NUM_LEVELS {
+                if base.overlap_in_level(level, begin, end) {
+                    max_level_with_files = level;
+                }
+            }
+        }
+        self.flush_mem_table()?;
+        for level in 0..max_level_with_files {
+            self.compact_level_range(level, begin, end)?;
+        }
+        Ok(())
+    }
+
+    // Switches to a new memtable and waits until the old one is written
+    // to a table
+    fn flush_mem_table(self: &Arc<Self>) -> Result<()> {
+        let state = self.lock_state()?;
+        let mut state = self.make_room_for_write(state, true, &WriteBatch::new())?;
+        while state.imm.is_some() && state.bg_error.is_none() && !state.closed {
+            state = self.background_work_finished_signal.wait(state).unwrap();
+        }
+        match &state.bg_error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    // Compacts the files of `level` overlapping `[begin, end]` into
+    // `level + 1` and waits until it is done
+    fn compact_level_range(
+        self: &Arc<Self>,
+        level: usize,
+        begin: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<()> {
+        assert!(level + 1 < NUM_LEVELS);
+        let mut state = self.lock_state()?;
+        // Only one manual compaction runs at a time
+        while state.manual_compaction.is_some() && !state.closed {
+            state = self.background_work_finished_signal.wait(state).unwrap();
+        }
+        state.manual_compaction = Some(ManualCompaction {
+            level,
+            done: false,
+            begin: begin.map(|k| InternalKey::new(k, K_MAX_SEQUENCE_NUMBER, VALUE_TYPE_FOR_SEEK)),
+            end: end.map(|k| InternalKey::new(k, 0, ValueType::KTypeDeletion)),
+        });
+        self.maybe_schedule_compaction(&mut state);
+        while !state.manual_compaction.as_ref().unwrap().done
+            && !self.shutting_down.load(Ordering::Acquire)
+            && state.bg_error.is_none()
+        {
+            state = self.background_work_finished_signal.wait(state).unwrap();
+        }
+        // Cancel the manual compaction if it has not finished
+        state.manual_compaction = None;
+        self.background_work_finished_signal.notify_all();
+        match &state.bg_error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn maybe_schedule_compaction(self: &Arc<Self>, state: &mut DBState<C>) {
+        if state.background_compaction_scheduled {
+            // Already scheduled
+        } else if self.shutting_down.load(Ordering::Acquire) {
+            // DB is being deleted; no more background compactions
+        } else if state.bg_error.is_some() {
+            // Already got an error; no more changes
+        } else if state.imm.is_none()
+            && state.manual_compaction.as_ref().map_or(true, |m| m.done)
+            && !state.versions.needs_compaction()
+        {
+            // No work to be done
+        } else {
+            state.background_compaction_scheduled = true;
+            let db = self.clone();
+            self.options
+                .env
+                .schedule(Box::new(move || db.background_call()));
+        }
+    }
+
+    fn background_call(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        assert!(state.background_compaction_scheduled);
+        if !self.shutting_down.load(Ordering::Acquire) && state.bg_error.is_none() {
+            state = self.background_compaction(state);
+        }
+        state.background_compaction_scheduled = false;
+
+        // Previous compaction may have produced too many files in a level,
+        // so reschedule another compaction if needed.
+        self.maybe_schedule_compaction(&mut state);
+        self.background_work_finished_signal.notify_all();
+    }
+}
+
+// The state of a running major compaction
+struct CompactionState<C: Comparator + Clone> {
+    compaction: Compaction<C>,
+    // Sequence numbers < smallest_snapshot are not significant since we
+    // will never have to service a snapshot below smallest_snapshot.
+    // Therefore if we have seen a sequence number S <= smallest_snapshot,
+    // we can drop all entries for the same key with sequence numbers < S.
+    smallest_snapshot: SequenceNumber,
+    // Files produced by the compaction
+    outputs: Vec<FileMetaData>,
+    // The builder of the output being generated
+    builder: Option<TableBuilder<InternalKeyComparator<C>, Box<dyn WritableFile>>>,
+}
+
+impl<C: Comparator + Clone> CompactionState<C> {
+    fn new(compaction: Compaction<C>, smallest_snapshot: SequenceNumber) -> Self {
+        CompactionState {
+            compaction,
+            smallest_snapshot,
+            outputs: vec![],
+            builder: None,
+        }
+    }
+}
+
+// The arena backing a memtable can't grow, so leave room for a write
+// that is issued once the buffer is almost full.
+pub(crate) fn mem_arena_capacity(write_buffer_size: usize) -> usize {
+    write_buffer_size * 2
+}
+
+// The most arena memory inserting `batch` into a memtable may take
+pub(crate) fn batch_arena_reserve(batch: &WriteBatch) -> usize {
+    batch.count() as usize * MAX_NODE_ARENA_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::version_set::L0_COMPACTION_TRIGGER;
+    use crate::env::MemEnv;
+    use std::fs;
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A db directory under the system temp dir that is removed on drop
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "leveldb-{}-{}-{}",
+                name,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            TestDir(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn new_options() -> Options {
+        Options {
+            create_if_missing: true,
+            ..Options::default()
+        }
+    }
+
+    fn get(db: &DB, key: &str) -> Option<String> {
+        db.get(ReadOptions::default(), key.as_bytes())
+            .unwrap()
+            .map(|v| String::from_utf8(v).unwrap())
+    }
+
+    fn put(db: &DB, key: &str, value: &str) {
+        db.put(WriteOptions::default(), key.as_bytes(), value.as_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_open_missing() {
+        let dir = TestDir::new("open_missing");
+        let options: Options = Options::default();
+        let err = DB::open(&dir.0, options)
+            .err()
+            .expect("open should fail when create_if_missing is false");
+        assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)));
+        assert!(!dir.0.exists());
+    }
+
+    #[test]
+    fn test_error_if_exists() {
+        let dir = TestDir::new("error_if_exists");
+        DB::open(&dir.0, new_options()).unwrap().close().unwrap();
+        let options = Options {
+            error_if_exists: true,
+            ..new_options()
+        };
+        let err = DB::open(&dir.0, options)
+            .err()
+            .expect("open should fail when error_if_exists is true");
+        assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)));
+        let options: Options = Options::default();
+        DB::open(&dir.0, options).unwrap();
+    }
+
+    #[test]
+    fn test_unregistered_compression() {
+        let dir = TestDir::new("unregistered_compression");
+        let options = Options {
+            compression: CompressionType::Custom(42),
+            ..new_options()
+        };
+        let err = DB::open(&dir.0, options)
+            .err()
+            .expect("open should fail without a codec for the compression");
+        assert!(matches!(err.kind(), ErrorKind::InvalidArgument(_)));
+        assert!(!dir.0.exists());
+        let options = Options {
+            compression: CompressionType::NoCompression,
+            ..new_options()
+        };
+        DB::open(&dir.0, options).unwrap();
+    }
+
+    #[test]
+    fn test_locking() {
+        let dir = TestDir::new("locking");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        assert!(DB::open(&dir.0, new_options()).is_err());
+        db.close().unwrap();
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        drop(db);
+        DB::open(&dir.0, new_options()).unwrap();
+    }
+
+    #[test]
+    fn test_mem_env() {
+        let dbname = Path::new("/mem_env_db");
+        let options = Options {
+            env: Arc::new(MemEnv::default()),
+            write_buffer_size: 10000,
+            ..new_options()
+        };
+        let db = DB::open(dbname, options.clone()).unwrap();
+        let value = "v".repeat(100);
+        for i in 0..500 {
+            put(&db, &format!("key{:06}", i), &value);
+        }
+        wait_for_compactions(&db);
+        assert!(num_table_files(&db) > 0);
+        db.close().unwrap();
+
+        let db = DB::open(dbname, options.clone()).unwrap();
+        let entries = scan(&db, ReadOptions::default());
+        assert_eq!(entries.len(), 500);
+        assert_eq!(entries[0], ("key000000".to_owned(), value.clone()));
+        assert!(options.env.file_exists(&dbname.join("CURRENT")));
+        // Nothing touched the disk
+        assert!(!dbname.exists());
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let dir = TestDir::new("put_get_delete");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        assert_eq!(get(&db, "foo"), None);
+        put(&db, "foo", "v1");
+        assert_eq!(get(&db, "foo"), Some("v1".to_owned()));
+        put(&db, "bar", "v2");
+        put(&db, "foo", "v3");
+        assert_eq!(get(&db, "foo"), Some("v3".to_owned()));
+        assert_eq!(get(&db, "bar"), Some("v2".to_owned()));
+        db.delete(WriteOptions::default(), b"foo").unwrap();
+        assert_eq!(get(&db, "foo"), None);
+        assert_eq!(get(&db, "bar"), Some("v2".to_owned()));
+        // Deleting a missing key is not an error
+        db.delete(WriteOptions::default(), b"missing").unwrap();
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let dir = TestDir::new("write_batch");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "a", "va");
+        let mut batch = WriteBatch::new();
+        batch.put(b"b", b"vb");
+        batch.delete(b"a");
+        batch.put(b"c", b"vc");
+        batch.put(b"c", b"vc2");
+        db.write(WriteOptions { sync: true }, batch).unwrap();
+        assert_eq!(get(&db, "a"), None);
+        assert_eq!(get(&db, "b"), Some("vb".to_owned()));
+        assert_eq!(get(&db, "c"), Some("vc2".to_owned()));
+    }
+
+    // A log file whose writes all fail
+    struct FailingFile;
+
+    impl io::Write for FailingFile {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("write failed"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("flush failed"))
+        }
+    }
+
+    impl WritableFile for FailingFile {
+        fn sync(&mut self) -> Result<()> {
+            Err(Error::from(io::Error::other("sync failed")))
+        }
+    }
+
+    #[test]
+    fn test_log_write_failure() {
+        let dir = TestDir::new("log_write_failure");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "a", "va");
+        db.inner.state.lock().unwrap().log = Some(Writer::new(Box::new(FailingFile)));
+        assert!(db.put(WriteOptions { sync: true }, b"b", b"vb").is_err());
+        // Every following write fails, even once the log works again
+        let file = dir.0.join("fresh.log");
+        let file = db.inner.options.env.new_writable_file(&file).unwrap();
+        db.inner.state.lock().unwrap().log = Some(Writer::new(file));
+        assert!(db.put(WriteOptions::default(), b"c", b"vc").is_err());
+        assert_eq!(get(&db, "a"), Some("va".to_owned()));
+        assert_eq!(get(&db, "b"), None);
+    }
+
+    #[test]
+    fn test_close_log_failure() {
+        let dir = TestDir::new("close_log_failure");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "a", "va");
+        db.inner.state.lock().unwrap().log = Some(Writer::new(Box::new(FailingFile)));
+        assert!(db.close().is_err());
+        assert!(db.inner.state.lock().unwrap().bg_error.is_some());
+        // The db was unlocked all the same
+        let reopened = DB::open(&dir.0, new_options()).unwrap();
+        assert_eq!(get(&reopened, "a"), Some("va".to_owned()));
+    }
+
+    #[test]
+    fn test_recover() {
+        let dir = TestDir::new("recover");
+        {
+            let db = DB::open(&dir.0, new_options()).unwrap();
+            put(&db, "foo", "v1");
+            put(&db, "baz", "v5");
+            db.close().unwrap();
+        }
+        {
+            let db = DB::open(&dir.0, new_options()).unwrap();
+            assert_eq!(get(&db, "foo"), Some("v1".to_owned()));
+            assert_eq!(get(&db, "baz"), Some("v5".to_owned()));
+            put(&db, "bar", "v2");
+            put(&db, "foo", "v3");
+            db.delete(WriteOptions::default(), b"baz").unwrap();
+        }
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        assert_eq!(get(&db, "foo"), Some("v3".to_owned()));
+        assert_eq!(get(&db, "bar"), Some("v2".to_owned()));
+        assert_eq!(get(&db, "baz"), None);
+        // Sequence numbers keep increasing across reopens
+        put(&db, "foo", "v4");
+        assert_eq!(get(&db, "foo"), Some("v4".to_owned()));
+    }
+
+    #[test]
+    fn test_recover_corrupted_log() {
+        let dir = TestDir::new("recover_corrupted_log");
+        {
+            let db = DB::open(&dir.0, new_options()).unwrap();
+            put(&db, "k1", "v1");
+            put(&db, "k2", "v2");
+            db.close().unwrap();
+        }
+        // Corrupt the payload of the first record
+        let log = generate_filename(
+            &dir.0,
+            FileType::Log,
+            files_of_type(&dir.0, FileType::Log)[0],
+        );
+        let mut contents = fs::read(&log).unwrap();
+        contents[10] ^= 0xff;
+        fs::write(&log, contents).unwrap();
+
+        let paranoid = Options {
+            paranoid_checks: true,
+            ..new_options()
+        };
+        let err = DB::open(&dir.0, paranoid)
+            .err()
+            .expect("paranoid checks should fail on a corrupted log");
+        assert!(err.is_corruption());
+        // Otherwise the rest of the corrupted log block is dropped
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        assert_eq!(get(&db, "k1"), None);
+        assert_eq!(get(&db, "k2"), None);
+        put(&db, "k1", "v3");
+        assert_eq!(get(&db, "k1"), Some("v3".to_owned()));
+    }
+
+    #[test]
+    fn test_paranoid_checks_corrupted_table() {
+        let dir = TestDir::new("paranoid_checks_corrupted_table");
+        let options = Options {
+            compression: CompressionType::NoCompression,
+            ..new_options()
+        };
+        {
+            let db = DB::open(&dir.0, options.clone()).unwrap();
+            put(&db, "foo", "value");
+            db.inner.flush_mem_table().unwrap();
+            db.close().unwrap();
+        }
+        // Flip one byte of the value in the data block
+        let table = generate_filename(
+            &dir.0,
+            FileType::Table,
+            files_of_type(&dir.0, FileType::Table)[0],
+        );
+        let mut contents = fs::read(&table).unwrap();
+        let pos = contents.windows(5).position(|w| w == b"value").unwrap();
+        contents[pos] = b'V';
+        fs::write(&table, contents).unwrap();
+
+        // Paranoid checks verify the checksum of the data block even
+        // though the read options don't ask for it
+        let paranoid = Options {
+            paranoid_checks: true,
+            ..options
+        };
+        let db = DB::open(&dir.0, paranoid).unwrap();
+        let err = db.get(ReadOptions::default(), b"foo").unwrap_err();
+        assert!(err.is_corruption());
+    }
+
+    #[test]
+    fn test_closed() {
+        let dir = TestDir::new("closed");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "foo", "v1");
+        db.close().unwrap();
+        let err = db.get(ReadOptions::default(), b"foo").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::DBClosed(_)));
+        let err = db.put(WriteOptions::default(), b"foo", b"v2").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::DBClosed(_)));
+    }
+
+    fn current_manifest(dir: &Path) -> String {
+        let current = fs::read_to_string(dir.join("CURRENT")).unwrap();
+        assert!(current.ends_with('\n'));
+        current.trim_end().to_owned()
+    }
+
+    #[test]
+    fn test_manifest() {
+        let dir = TestDir::new("manifest");
+        {
+            let db = DB::open(&dir.0, new_options()).unwrap();
+            put(&db, "foo", "v1");
+        }
+        let manifest = current_manifest(&dir.0);
+        assert!(dir.0.join(&manifest).exists());
+        assert!(!dir.0.join("MANIFEST-000001").exists());
+
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        let new_manifest = current_manifest(&dir.0);
+        assert_ne!(manifest, new_manifest);
+        // The descriptor of the previous incarnation is obsolete
+        assert!(!dir.0.join(&manifest).exists());
+        assert!(dir.0.join(&new_manifest).exists());
+        assert_eq!(get(&db, "foo"), Some("v1".to_owned()));
+    }
+
+    #[test]
+    fn test_bad_current() {
+        let dir = TestDir::new("bad_current");
+        DB::open(&dir.0, new_options()).unwrap().close().unwrap();
+        fs::write(dir.0.join("CURRENT"), "MANIFEST-000001").unwrap();
+        let err = DB::open(&dir.0, new_options())
+            .err()
+            .expect("open should fail when CURRENT has no newline");
+        assert!(err.is_corruption());
+        fs::write(dir.0.join("CURRENT"), "MANIFEST-000100\n").unwrap();
+        let err = DB::open(&dir.0, new_options())
+            .err()
+            .expect("open should fail when CURRENT points to a missing file");
+        assert!(err.is_corruption());
+    }
+
+    #[derive(Clone, Default)]
+    struct ReverseComparator {}
+
+    impl Comparator for ReverseComparator {
+        fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+            b.cmp(a)
+        }
+        fn name(&self) -> &str {
+            "test.ReverseComparator"
+        }
+        fn find_shortest_separator(&self, start: &[u8], _limit: &[u8]) -> Vec<u8> {
+            start.to_owned()
+        }
+        fn find_short_successor(&self, key: &[u8]) -> Vec<u8> {
+            key.to_owned()
+        }
+    }
+
+    #[test]
+    fn test_comparator_mismatch() {
+        let dir = TestDir::new("comparator_mismatch");
+        DB::open(&dir.0, new_options()).unwrap().close().unwrap();
+        let options: Options<ReverseComparator> = Options {
+            create_if_missing: true,
+            ..Options::default()
+        };
+        let err = DB::open(&dir.0, options)
+            .err()
+            .expect("open should fail with a different comparator");
+        match err.kind() {
+            ErrorKind::InvalidArgument(msg) => assert!(msg.contains("does not match")),
+            _ => panic!("expect an invalid argument error"),
+        }
+    }
+
+    #[test]
+    fn test_dyn_comparator() {
+        let dir = TestDir::new("dyn_comparator");
+        let comparator: Arc<dyn Comparator> = Arc::new(ReverseComparator::default());
+        let options = new_options().with_comparator(comparator);
+        let db = DB::open(&dir.0, options.clone()).unwrap();
+        for key in &["a", "c", "b"] {
+            db.put(WriteOptions::default(), key.as_bytes(), b"v")
+                .unwrap();
+        }
+        let keys: Vec<Vec<u8>> = db
+            .new_iterator(ReadOptions::default())
+            .unwrap()
+            .entries()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(keys, vec![b"c".to_vec(), b"b".to_vec(), b"a".to_vec()]);
+        db.close().unwrap();
+        drop(db);
+
+        // The name recorded in the MANIFEST is checked on open
+        let bytewise: Arc<dyn Comparator> = Arc::new(BytewiseComparator::default());
+        let err = DB::open(&dir.0, new_options().with_comparator(bytewise))
+            .err()
+            .expect("open should fail with a different comparator");
+        match err.kind() {
+            ErrorKind::InvalidArgument(msg) => assert_eq!(
+                msg,
+                "leveldb.BytewiseComparator does not match existing comparator test.ReverseComparator"
+            ),
+            _ => panic!("expect an invalid argument error"),
+        }
+        let db = DB::open(&dir.0, options).unwrap();
+        assert_eq!(
+            db.get(ReadOptions::default(), b"b").unwrap(),
+            Some(b"v".to_vec())
+        );
+    }
+
+    fn files_of_type(dir: &Path, file_type: FileType) -> Vec<u64> {
+        let mut files: Vec<u64> = fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|e| {
+                let name = e.unwrap().file_name();
+                match name.to_str().and_then(parse_filename) {
+                    Some((t, number)) if t == file_type => Some(number),
+                    _ => None,
+                }
+            })
+            .collect();
+        files.sort_unstable();
+        files
+    }
+
+    // Waits until the background thread has nothing left to do
+    fn wait_for_compactions(db: &DB) {
+        let inner = &db.inner;
+        let mut state = inner.state.lock().unwrap();
+        while state.background_compaction_scheduled {
+            state = inner.background_work_finished_signal.wait(state).unwrap();
+        }
+    }
+
+    fn num_table_files(db: &DB) -> usize {
+        let state = db.inner.state.lock().unwrap();
+        let current = state.versions.current();
+        (0..NUM_LEVELS).map(|level| current.num_files(level)).sum()
+    }
+
+    #[test]
+    fn test_minor_compaction() {
+        let dir = TestDir::new("minor_compaction");
+        let options = Options {
+            write_buffer_size: 10000,
+            ..new_options()
+        };
+        let db = DB::open(&dir.0, options.clone()).unwrap();
+        let value = "v".repeat(100);
+        for i in 0..1000 {
+            put(&db, &format!("key{:06}", i), &value);
+        }
+        wait_for_compactions(&db);
+        assert!(num_table_files(&db) > 1);
+        // Only the log of the current memtable is kept
+        assert_eq!(files_of_type(&dir.0, FileType::Log).len(), 1);
+        assert_eq!(
+            files_of_type(&dir.0, FileType::Table).len(),
+            num_table_files(&db)
+        );
+        for i in 0..1000 {
+            assert_eq!(get(&db, &format!("key{:06}", i)), Some(value.clone()));
+        }
+
+        // Overwrites and deletions shadow the flushed tables
+        for i in (0..1000).step_by(2) {
+            db.delete(WriteOptions::default(), format!("key{:06}", i).as_bytes())
+                .unwrap();
+        }
+        put(&db, "key000001", "new");
+        db.close().unwrap();
+
+        let db = DB::open(&dir.0, options).unwrap();
+        assert_eq!(get(&db, "key000000"), None);
+        assert_eq!(get(&db, "key000001"), Some("new".to_owned()));
+        assert_eq!(get(&db, "key000003"), Some(value.clone()));
+        assert_eq!(get(&db, "key000998"), None);
+        assert_eq!(get(&db, "key000999"), Some(value));
+    }
+
+    #[test]
+    fn test_recover_flushes_memtable() {
+        let dir = TestDir::new("recover_flush");
+        {
+            let db = DB::open(&dir.0, new_options()).unwrap();
+            put(&db, "foo", "v1");
+            put(&db, "bar", "v2");
+            assert_eq!(num_table_files(&db), 0);
+        }
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        // The recovered log has been written to a level-0 table
+        {
+            let state = db.inner.state.lock().unwrap();
+            assert_eq!(state.versions.num_level_files(0), 1);
+            assert!(state.mem.is_empty());
+        }
+        assert_eq!(files_of_type(&dir.0, FileType::Log).len(), 1);
+        assert_eq!(get(&db, "foo"), Some("v1".to_owned()));
+        assert_eq!(get(&db, "bar"), Some("v2".to_owned()));
+    }
+
+    #[test]
+    fn test_memtable_output_level() {
+        let dir = TestDir::new("memtable_output_level");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        for i in 0..100 {
+            put(&db, &format!("a{:06}", i), "v");
+        }
+        db.inner.flush_mem_table().unwrap();
+        // Nothing overlaps the first table, so it is pushed to level 2
+        {
+            let state = db.inner.state.lock().unwrap();
+            assert_eq!(state.versions.num_level_files(2), 1);
+        }
+        // A table overlapping the first one stays above it
+        put(&db, "a000000", "v2");
+        db.inner.flush_mem_table().unwrap();
+        let state = db.inner.state.lock().unwrap();
+        assert_eq!(state.versions.num_level_files(2), 1);
+        assert_eq!(state.versions.num_level_files(1), 1);
+    }
+
+    #[test]
+    fn test_large_batch() {
+        let dir = TestDir::new("large_batch");
+        let options = Options {
+            write_buffer_size: 10000,
+            ..new_options()
+        };
+        let db = DB::open(&dir.0, options).unwrap();
+        let mut batch = WriteBatch::new();
+        for i in 0..5000 {
+            batch.put(format!("key{:06}", i).as_bytes(), b"v");
+        }
+        db.write(WriteOptions::default(), batch).unwrap();
+        put(&db, "next", "v");
+        assert_eq!(get(&db, "key004999"), Some("v".to_owned()));
+        assert_eq!(get(&db, "next"), Some("v".to_owned()));
+    }
+
+    // Returns the entries of every table file at `level`
+    fn level_entries(db: &DB, level: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let current = db.inner.state.lock().unwrap().versions.current();
+        let mut entries = vec![];
+        for f in current.files(level) {
+            let mut iter = db
+                .inner
+                .table_cache
+                .new_iterator(ReadOptions::default(), f.number, f.file_size)
+                .unwrap();
+            iter.seek_to_first();
+            while iter.valid() {
+                entries.push((iter.key().to_vec(), iter.value().to_vec()));
+                iter.next();
+            }
+        }
+        entries
+    }
+
+    #[test]
+    fn test_level0_compaction() {
+        let dir = TestDir::new("level0_compaction");
+        let options = Options {
+            write_buffer_size: 10000,
+            ..new_options()
+        };
+        let db = DB::open(&dir.0, options.clone()).unwrap();
+        // Every round overwrites the same keys, so the flushed tables
+        // overlap and pile up in level 0 until they are compacted
+        for round in 0..20 {
+            for i in 0..100 {
+                put(
+                    &db,
+                    &format!("key{:03}", i),
+                    &format!("{}{}", round, "v".repeat(100)),
+                );
+            }
+        }
+        wait_for_compactions(&db);
+        {
+            let state = db.inner.state.lock().unwrap();
+            assert!(state.versions.num_level_files(0) < L0_COMPACTION_TRIGGER);
+            assert!(state.versions.num_level_files(1) > 0);
+        }
+        // The compaction inputs have been deleted
+        assert_eq!(
+            files_of_type(&dir.0, FileType::Table).len(),
+            num_table_files(&db)
+        );
+        // Only the newest version of each key survives a compaction
+        let entries = level_entries(&db, 1);
+        let mut user_keys: Vec<&[u8]> = entries.iter().map(|(k, _)| extract_user_key(k)).collect();
+        let total = user_keys.len();
+        user_keys.dedup();
+        assert_eq!(user_keys.len(), total);
+
+        let expected = format!("19{}", "v".repeat(100));
+        for i in 0..100 {
+            assert_eq!(get(&db, &format!("key{:03}", i)), Some(expected.clone()));
+        }
+        db.close().unwrap();
+        let db = DB::open(&dir.0, options).unwrap();
+        for i in 0..100 {
+            assert_eq!(get(&db, &format!("key{:03}", i)), Some(expected.clone()));
+        }
+    }
+
+    #[test]
+    fn test_compaction_drops_deletions() {
+        let dir = TestDir::new("compaction_deletions");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "a", "va");
+        put(&db, "foo", "v1");
+        db.compact_range(None, None).unwrap();
+        // The first table is pushed to level 2
+        assert_eq!(level_entries(&db, 2).len(), 2);
+
+        put(&db, "foo", "v2");
+        db.delete(WriteOptions::default(), b"foo").unwrap();
+        db.delete(WriteOptions::default(), b"a").unwrap();
+        db.inner.flush_mem_table().unwrap();
+        // The table overlapping level 2 is placed in level 1
+        assert_eq!(level_entries(&db, 1).len(), 3);
+
+        db.compact_range(None, None).unwrap();
+        // The values are shadowed by the deletions, and the deletions
+        // hide nothing in a deeper level
+        assert_eq!(num_table_files(&db), 0);
+        assert_eq!(files_of_type(&dir.0, FileType::Table).len(), 0);
+        assert_eq!(get(&db, "foo"), None);
+        assert_eq!(get(&db, "a"), None);
+    }
+
+    #[test]
+    fn test_compaction_keeps_deletions() {
+        let dir = TestDir::new("compaction_keeps_deletions");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "a", "va");
+        put(&db, "z", "vz");
+        db.compact_range(None, None).unwrap();
+        // Move the table from level 2 down to level 3
+        db.inner.compact_level_range(2, None, None).unwrap();
+        assert_eq!(level_entries(&db, 3).len(), 2);
+        put(&db, "m", "vm");
+        db.inner.flush_mem_table().unwrap();
+        assert_eq!(level_entries(&db, 2).len(), 1);
+        put(&db, "a", "va2");
+        db.delete(WriteOptions::default(), b"z").unwrap();
+        db.inner.flush_mem_table().unwrap();
+        assert_eq!(level_entries(&db, 1).len(), 2);
+
+        // The deletion of "z" still hides a value in level 3
+        db.inner.compact_level_range(1, None, None).unwrap();
+        let entries = level_entries(&db, 2);
+        assert_eq!(entries.len(), 3);
+        let parsed = ParsedInteralKey::decode_from(&entries[2].0).unwrap();
+        assert_eq!(extract_user_key(&entries[2].0), b"z");
+        assert_eq!(parsed.value_type(), ValueType::KTypeDeletion);
+        assert_eq!(get(&db, "z"), None);
+
+        db.compact_range(None, None).unwrap();
+        let entries = level_entries(&db, 3);
+        let user_keys: Vec<&[u8]> = entries.iter().map(|(k, _)| extract_user_key(k)).collect();
+        assert_eq!(user_keys, vec![b"a", b"m"]);
+        assert_eq!(get(&db, "a"), Some("va2".to_owned()));
+        assert_eq!(get(&db, "z"), None);
+    }
+
+    #[test]
+    fn test_compact_range() {
+        let dir = TestDir::new("compact_range");
+        let options = Options {
+            write_buffer_size: 10000,
+            ..new_options()
+        };
+        let db = DB::open(&dir.0, options).unwrap();
+        let value = "v".repeat(100);
+        for round in 0..5 {
+            for i in 0..200 {
+                put(&db, &format!("key{:03}", i), &format!("{}{}", round, value));
+            }
+        }
+        db.compact_range(None, None).unwrap();
+        wait_for_compactions(&db);
+        // Everything has been pushed into a single level without
+        // overwritten versions
+        let levels: Vec<usize> = (0..NUM_LEVELS)
+            .filter(|level| !level_entries(&db, *level).is_empty())
+            .collect();
+        assert_eq!(levels.len(), 1);
+        assert_eq!(level_entries(&db, levels[0]).len(), 200);
+        for i in 0..200 {
+            assert_eq!(
+                get(&db, &format!("key{:03}", i)),
+                Some(format!("4{}", value))
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_range_rewrites_single_file() {
+        let dir = TestDir::new("compact_range_rewrites_single_file");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "a", "va");
+        db.delete(WriteOptions::default(), b"a").unwrap();
+        db.inner.flush_mem_table().unwrap();
+        let level = (0..NUM_LEVELS - 1)
+            .find(|level| !level_entries(&db, *level).is_empty())
+            .unwrap();
+        assert_eq!(level_entries(&db, level).len(), 2);
+        // A trivial move would keep the overwritten value and the
+        // deletion marker of the only table
+        db.inner.compact_level_range(level, None, None).unwrap();
+        wait_for_compactions(&db);
+        for level in 0..NUM_LEVELS {
+            assert!(level_entries(&db, level).is_empty());
+        }
+        assert_eq!(get(&db, "a"), None);
+    }
+
+    #[test]
+    fn test_get_property() {
+        let dir = TestDir::new("get_property");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "foo", "v1");
+        assert_eq!(
+            db.get_property("leveldb.num-files-at-level0"),
+            Some("0".to_owned())
+        );
+        db.compact_range(None, None).unwrap();
+        wait_for_compactions(&db);
+        let files: usize = (0..NUM_LEVELS)
+            .map(|level| {
+                db.get_property(&format!("leveldb.num-files-at-level{}", level))
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap()
+            })
+            .sum();
+        assert_eq!(files, 1);
+        let stats = db.get_property("leveldb.stats").unwrap();
+        assert_eq!(stats.lines().count(), 3);
+        assert!(db
+            .get_property("leveldb.sstables")
+            .unwrap()
+            .contains("'foo' @ 1 : KTypeValue"));
+        assert!(db
+            .get_property("leveldb.approximate-memory-usage")
+            .is_some());
+        assert_eq!(db.get_property("leveldb.num-files-at-level7"), None);
+        assert_eq!(db.get_property("leveldb.unknown"), None);
+        assert_eq!(db.get_property("stats"), None);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = TestDir::new("snapshot");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        put(&db, "foo", "v1");
+        let s1 = db.get_snapshot();
+        put(&db, "foo", "v2");
+        let s2 = db.get_snapshot();
+        let s3 = db.get_snapshot();
+        db.delete(WriteOptions::default(), b"foo").unwrap();
+        put(&db, "bar", "v3");
+
+        let get_at = |snapshot: &Snapshot, key: &str| {
+            let options = ReadOptions {
+                snapshot: Some(snapshot),
+                ..ReadOptions::default()
+            };
+            db.get(options, key.as_bytes())
+                .unwrap()
+                .map(|v| String::from_utf8(v).unwrap())
+        };
+        assert_eq!(get_at(&s1, "foo"), Some("v1".to_owned()));
+        assert_eq!(get_at(&s2, "foo"), Some("v2".to_owned()));
+        assert_eq!(get_at(&s2, "bar"), None);
+        assert_eq!(get(&db, "foo"), None);
+        assert_eq!(get(&db, "bar"), Some("v3".to_owned()));
+
+        // Compactions keep the versions the live snapshots need
+        db.compact_range(None, None).unwrap();
+        assert_eq!(get_at(&s1, "foo"), Some("v1".to_owned()));
+        assert_eq!(get_at(&s3, "foo"), Some("v2".to_owned()));
+        assert_eq!(get(&db, "foo"), None);
+        db.release_snapshot(s2);
+        assert_eq!(get_at(&s3, "foo"), Some("v2".to_owned()));
+
+        db.release_snapshot(s1);
+        db.release_snapshot(s3);
+        put(&db, "baz", "v4");
+        db.compact_range(None, None).unwrap();
+        // Once released, the old versions of "foo" and its deletion are
+        // dropped
+        let user_keys: Vec<Vec<u8>> = (0..NUM_LEVELS)
+            .flat_map(|level| level_entries(&db, level))
+            .map(|(k, _)| extract_user_key(&k).to_vec())
+            .collect();
+        assert_eq!(user_keys, vec![b"bar".to_vec(), b"baz".to_vec()]);
+    }
+
+    fn scan(db: &DB, options: ReadOptions) -> Vec<(String, String)> {
+        db.new_iterator(options)
+            .unwrap()
+            .entries()
+            .map(|entry| {
+                let (key, value) = entry.unwrap();
+                (
+                    String::from_utf8(key).unwrap(),
+                    String::from_utf8(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_iterator() {
+        let dir = TestDir::new("iterator");
+        let db = DB::open(&dir.0, new_options()).unwrap();
+        assert!(scan(&db, ReadOptions::default()).is_empty());
+
+        // Spread the entries over a deep level, level 0 and the memtable
+        for i in 0..100 {
+            put(&db, &format!("key{:03}", i), "v1");
+        }
+        db.compact_range(None, None).unwrap();
+        for i in (0..100).step_by(2) {
+            put(&db, &format!("key{:03}", i), "v2");
+        }
+        db.inner.flush_mem_table().unwrap();
+        let snapshot = db.get_snapshot();
+        for i in (0..100).step_by(3) {
+            db.delete(WriteOptions::default(), format!("key{:03}", i).as_bytes())
+                .unwrap();
+        }
+
+        let expected: Vec<(String, String)> = (0..100)
+            .filter(|i| i % 3 != 0)
+            .map(|i| {
+                let value = if i % 2 == 0 { "v2" } else { "v1" };
+                (format!("key{:03}", i), value.to_owned())
+            })
+            .collect();
+        assert_eq!(scan(&db, ReadOptions::default()), expected);
+
+        let mut iter = db.new_iterator(ReadOptions::default()).unwrap();
+        let mut reversed = vec![];
+        iter.seek_to_last();
+        while iter.valid() {
+            reversed.push((
+                String::from_utf8(iter.key().to_vec()).unwrap(),
+                String::from_utf8(iter.value().to_vec()).unwrap(),
+            ));
+            iter.prev();
+        }
+        reversed.reverse();
+        assert_eq!(reversed, expected);
+        iter.seek(b"key030");
+        assert_eq!(iter.key(), b"key031");
+        iter.prev();
+        assert_eq!(iter.key(), b"key029");
+        assert_eq!(iter.value(), b"v1");
+
+        // The iterator sees the state of the snapshot
+        let options = ReadOptions {
+            snapshot: Some(&snapshot),
+            ..ReadOptions::default()
+        };
+        assert_eq!(scan(&db, options).len(), 100);
+        db.release_snapshot(snapshot);
+    }
+}
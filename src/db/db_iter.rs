@@ -0,0 +1,423 @@
+use std::cmp::Ordering;
+
+use super::format::{
+    extract_user_key, InternalKey, ParsedInteralKey, ValueType, VALUE_TYPE_FOR_SEEK,
+};
+use super::ldbiterator::LdbIterator;
+use super::SequenceNumber;
+use crate::util::comparator::Comparator;
+use crate::util::status::{Error, ErrorKind, Result};
+
+// Which direction is the iterator currently moving?
+// (1) When moving forward, the internal iterator is positioned at
+//     the exact entry that yields `key()`, `value()`
+// (2) When moving backwards, the internal iterator is positioned
+//     just before all entries whose user key == `key()`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// Memtables and sstables that make the DB representation contain
+/// (userkey,seq,type) => uservalue entries.  `DBIterator`
+/// combines multiple entries for the same userkey found in the DB
+/// representation into a single entry while accounting for sequence
+/// numbers, deletion markers, overwrites, etc.
+///
+/// Only the entries with a sequence number <= `sequence` are visible,
+/// and `key()` is the user key of the entry.
+pub struct DBIterator<I: LdbIterator, C: Comparator> {
+    ucmp: C,
+    iter: I,
+    sequence: SequenceNumber,
+    err: Option<Error>,
+    // Current key when direction is reverse, or the user key to skip
+    // while moving forward
+    saved_key: Vec<u8>,
+    // Current value when direction is reverse
+    saved_value: Vec<u8>,
+    direction: Direction,
+    valid: bool,
+}
+
+impl<I: LdbIterator, C: Comparator> DBIterator<I, C> {
+    pub fn new(iter: I, ucmp: C, sequence: SequenceNumber) -> Self {
+        DBIterator {
+            ucmp,
+            iter,
+            sequence,
+            err: None,
+            saved_key: vec![],
+            saved_value: vec![],
+            direction: Direction::Forward,
+            valid: false,
+        }
+    }
+
+    fn corrupted_key(&mut self) {
+        if self.err.is_none() {
+            self.err = Some(Error::from(ErrorKind::Corruption(
+                "corrupted internal key in DBIterator".to_owned(),
+            )));
+        }
+    }
+
+    fn clear_saved_value(&mut self) {
+        if self.saved_value.capacity() > 1_048_576 {
+            self.saved_value = vec![];
+        } else {
+            self.saved_value.clear();
+        }
+    }
+
+    fn find_next_user_entry(&mut self, mut skipping: bool) {
+        // Loop until we hit an acceptable entry to yield
+        assert!(self.iter.valid());
+        assert!(self.direction == Direction::Forward);
+        loop {
+            match ParsedInteralKey::decode_from(self.iter.key()) {
+                Some(ikey) if ikey.sequence() <= self.sequence => {
+                    let user_key = extract_user_key(self.iter.key());
+                    match ikey.value_type() {
+                        ValueType::KTypeDeletion => {
+                            // Arrange to skip all upcoming entries for this key since
+                            // they are hidden by this deletion.
+                            self.saved_key.clear();
+                            self.saved_key.extend_from_slice(user_key);
+                            skipping = true;
+                        }
+                        ValueType::KTypeValue => {
+                            if !skipping
+                                || self.ucmp.compare(user_key, &self.saved_key) == Ordering::Greater
+                            {
+                                self.valid = true;
+                                self.saved_key.clear();
+                                return;
+                            }
+                            // Entry hidden
+                        }
+                    }
+                }
+                Some(_) => {}
+                None => self.corrupted_key(),
+            }
+            self.iter.next();
+            if !self.iter.valid() {
+                break;
+            }
+        }
+        self.saved_key.clear();
+        self.valid = false;
+    }
+
+    fn find_prev_user_entry(&mut self) {
+        assert!(self.direction == Direction::Reverse);
+        let mut value_type = ValueType::KTypeDeletion;
+        while self.iter.valid() {
+            match ParsedInteralKey::decode_from(self.iter.key()) {
+                Some(ikey) if ikey.sequence() <= self.sequence => {
+                    let user_key = extract_user_key(self.iter.key());
+                    if value_type != ValueType::KTypeDeletion
+                        && self.ucmp.compare(user_key, &self.saved_key) == Ordering::Less
+                    {
+                        // We encountered a non-deleted value in entries for previous keys,
+                        break;
+                    }
+                    value_type = ikey.value_type();
+                    if value_type == ValueType::KTypeDeletion {
+                        self.saved_key.clear();
+                        self.clear_saved_value();
+                    } else {
+                        self.saved_key.clear();
+                        self.saved_key.extend_from_slice(user_key);
+                        self.saved_value.clear();
+                        self.saved_value.extend_from_slice(self.iter.value());
+                    }
+                }
+                Some(_) => {}
+                None => self.corrupted_key(),
+            }
+            self.iter.prev();
+        }
+
+        if value_type == ValueType::KTypeDeletion {
+            // End
+            self.valid = false;
+            self.saved_key.clear();
+            self.clear_saved_value();
+            self.direction = Direction::Forward;
+        } else {
+            self.valid = true;
+        }
+    }
+}
+
+impl<I: LdbIterator, C: Comparator> LdbIterator for DBIterator<I, C> {
+    fn valid(&self) -> bool {
+        self.valid
+    }
+
+    fn seek_to_first(&mut self) {
+        self.direction = Direction::Forward;
+        self.clear_saved_value();
+        self.iter.seek_to_first();
+        if self.iter.valid() {
+            self.find_next_user_entry(false);
+        } else {
+            self.valid = false;
+        }
+    }
+
+    fn seek_to_last(&mut self) {
+        self.direction = Direction::Reverse;
+        self.clear_saved_value();
+        self.iter.seek_to_last();
+        self.find_prev_user_entry();
+    }
+
+    fn seek(&mut self, target: &[u8]) {
+        self.direction = Direction::Forward;
+        self.clear_saved_value();
+        let ikey = InternalKey::new(target, self.sequence, VALUE_TYPE_FOR_SEEK);
+        self.iter.seek(ikey.encode());
+        if self.iter.valid() {
+            self.find_next_user_entry(false);
+        } else {
+            self.valid = false;
+        }
+    }
+
+    fn next(&mut self) {
+        assert!(self.valid);
+        if self.direction == Direction::Reverse {
+            // Switch directions?
+            self.direction = Direction::Forward;
+            // iter is pointing just before the entries for key(),
+            // so advance into the range of entries for key() and then
+            // use the normal skipping code below.
+            if !self.iter.valid() {
+                self.iter.seek_to_first();
+            } else {
+                self.iter.next();
+            }
+            if !self.iter.valid() {
+                self.valid = false;
+                self.saved_key.clear();
+                return;
+            }
+            // saved_key already contains the key to skip past.
+        } else {
+            // Store in saved_key the current key so we skip it below.
+            self.saved_key.clear();
+            self.saved_key
+                .extend_from_slice(extract_user_key(self.iter.key()));
+            self.iter.next();
+            if !self.iter.valid() {
+                self.valid = false;
+                self.saved_key.clear();
+                return;
+            }
+        }
+        self.find_next_user_entry(true);
+    }
+
+    fn key(&self) -> &[u8] {
+        assert!(self.valid);
+        match self.direction {
+            Direction::Forward => extract_user_key(self.iter.key()),
+            Direction::Reverse => &self.saved_key,
+        }
+    }
+
+    fn value(&self) -> &[u8] {
+        assert!(self.valid);
+        match self.direction {
+            Direction::Forward => self.iter.value(),
+            Direction::Reverse => &self.saved_value,
+        }
+    }
+
+    fn prev(&mut self) {
+        assert!(self.valid);
+        if self.direction == Direction::Forward {
+            // Switch directions?
+            // iter is pointing at the current entry.  Scan backwards until
+            // the key changes so we can use the normal reverse scanning code.
+            assert!(self.iter.valid());
+            self.saved_key.clear();
+            self.saved_key
+                .extend_from_slice(extract_user_key(self.iter.key()));
+            loop {
+                self.iter.prev();
+                if !self.iter.valid() {
+                    self.valid = false;
+                    self.saved_key.clear();
+                    self.clear_saved_value();
+                    return;
+                }
+                if self
+                    .ucmp
+                    .compare(extract_user_key(self.iter.key()), &self.saved_key)
+                    == Ordering::Less
+                {
+                    break;
+                }
+            }
+            self.direction = Direction::Reverse;
+        }
+        self.find_prev_user_entry();
+    }
+
+    fn status(&mut self) -> Result<()> {
+        match &self.err {
+            Some(e) => Err(e.clone()),
+            None => self.iter.status(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::format::InternalKeyComparator;
+    use crate::db::memtable::{MemTable, MemTableIterator};
+    use crate::util::comparator::BytewiseComparator;
+
+    fn new_db_iter(
+        entries: &[(&str, u64, ValueType, &str)],
+        sequence: SequenceNumber,
+    ) -> DBIterator<MemTableIterator<BytewiseComparator>, BytewiseComparator> {
+        let icmp = InternalKeyComparator::new(BytewiseComparator::default());
+        let mem = MemTable::new(icmp, 1 << 20);
+        for (key, seq, value_type, value) in entries {
+            mem.add(*seq, *value_type, key.as_bytes(), value.as_bytes());
+        }
+        DBIterator::new(mem.iter(), BytewiseComparator::default(), sequence)
+    }
+
+    fn collect_forward<I: LdbIterator>(iter: &mut I) -> Vec<(String, String)> {
+        let mut result = vec![];
+        iter.seek_to_first();
+        while iter.valid() {
+            result.push(entry(iter));
+            iter.next();
+        }
+        result
+    }
+
+    fn collect_backward<I: LdbIterator>(iter: &mut I) -> Vec<(String, String)> {
+        let mut result = vec![];
+        iter.seek_to_last();
+        while iter.valid() {
+            result.push(entry(iter));
+            iter.prev();
+        }
+        result.reverse();
+        result
+    }
+
+    fn entry<I: LdbIterator>(iter: &I) -> (String, String) {
+        (
+            String::from_utf8(iter.key().to_vec()).unwrap(),
+            String::from_utf8(iter.value().to_vec()).unwrap(),
+        )
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn test_entries() -> Vec<(&'static str, u64, ValueType, &'static str)> {
+        vec![
+            ("a", 1, ValueType::KTypeValue, "a1"),
+            ("b", 2, ValueType::KTypeValue, "b2"),
+            ("b", 5, ValueType::KTypeValue, "b5"),
+            ("c", 3, ValueType::KTypeValue, "c3"),
+            ("c", 6, ValueType::KTypeDeletion, ""),
+            ("d", 4, ValueType::KTypeDeletion, ""),
+            ("d", 7, ValueType::KTypeValue, "d7"),
+            ("e", 8, ValueType::KTypeDeletion, ""),
+        ]
+    }
+
+    #[test]
+    fn test_newest_visible_version() {
+        let expected = vec![
+            (8, pairs(&[("a", "a1"), ("b", "b5"), ("d", "d7")])),
+            (6, pairs(&[("a", "a1"), ("b", "b5")])),
+            (5, pairs(&[("a", "a1"), ("b", "b5"), ("c", "c3")])),
+            (4, pairs(&[("a", "a1"), ("b", "b2"), ("c", "c3")])),
+            (0, vec![]),
+        ];
+        for (sequence, expected) in expected {
+            let mut iter = new_db_iter(&test_entries(), sequence);
+            assert_eq!(collect_forward(&mut iter), expected);
+            assert_eq!(collect_backward(&mut iter), expected);
+            assert!(iter.status().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut iter = new_db_iter(&test_entries(), 8);
+        iter.seek(b"b");
+        assert_eq!(entry(&iter), ("b".to_owned(), "b5".to_owned()));
+        iter.seek(b"bb");
+        assert_eq!(entry(&iter), ("d".to_owned(), "d7".to_owned()));
+        iter.seek(b"e");
+        assert!(!iter.valid());
+
+        // Seek only sees the visible entries
+        let mut iter = new_db_iter(&test_entries(), 4);
+        iter.seek(b"b");
+        assert_eq!(entry(&iter), ("b".to_owned(), "b2".to_owned()));
+        iter.seek(b"d");
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_switch_direction() {
+        let mut iter = new_db_iter(&test_entries(), 8);
+        iter.seek(b"b");
+        iter.next();
+        assert_eq!(entry(&iter), ("d".to_owned(), "d7".to_owned()));
+        iter.prev();
+        assert_eq!(entry(&iter), ("b".to_owned(), "b5".to_owned()));
+        iter.prev();
+        assert_eq!(entry(&iter), ("a".to_owned(), "a1".to_owned()));
+        iter.next();
+        assert_eq!(entry(&iter), ("b".to_owned(), "b5".to_owned()));
+        iter.next();
+        assert_eq!(entry(&iter), ("d".to_owned(), "d7".to_owned()));
+        iter.next();
+        assert!(!iter.valid());
+
+        iter.seek_to_last();
+        assert_eq!(entry(&iter), ("d".to_owned(), "d7".to_owned()));
+        iter.prev();
+        iter.next();
+        assert_eq!(entry(&iter), ("d".to_owned(), "d7".to_owned()));
+        iter.seek_to_first();
+        iter.prev();
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_only_deletions() {
+        let entries = vec![
+            ("a", 1, ValueType::KTypeDeletion, ""),
+            ("b", 2, ValueType::KTypeDeletion, ""),
+        ];
+        let mut iter = new_db_iter(&entries, 10);
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        iter.seek_to_last();
+        assert!(!iter.valid());
+        iter.seek(b"a");
+        assert!(!iter.valid());
+    }
+}
@@ -0,0 +1,238 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::filename::{parse_filename, FileType};
+use super::format::ParsedInteralKey;
+use super::ldbiterator::LdbIterator;
+use super::log::{Reader, Reporter};
+use super::table::Table;
+use super::table_cache::table_options;
+use super::version_edit::VersionEdit;
+use super::write_batch::{Handler, WriteBatch, HEADER as WRITE_BATCH_HEADER};
+use crate::env::Env;
+use crate::options::{Options, ReadOptions};
+use crate::util::comparator::BytewiseComparator;
+use crate::util::logging::escape_string;
+use crate::util::status::{Error, ErrorKind, Result};
+
+/// Dump the contents of the file named by `path` in text format to
+/// `dst`.  Makes a sequence of `write_all()` calls to `dst` passing it
+/// the text, one line at a time.  The type of the file is deduced from
+/// its name: a log, a table or a MANIFEST.
+///
+/// Returns an error if the file could not be dumped.  The corruptions
+/// found in logs and MANIFESTs are written to `dst` instead.
+pub fn dump_file(env: &dyn Env, path: &Path, dst: &mut dyn Write) -> Result<()> {
+    let file_type = path
+        .file_name()
+        .and_then(|name| parse_filename(&name.to_string_lossy()))
+        .map(|(file_type, _)| file_type);
+    match file_type {
+        Some(FileType::Log) => dump_log(env, path, dst),
+        Some(FileType::Descriptor) => dump_descriptor(env, path, dst),
+        Some(FileType::Table) => dump_table(env, path, dst),
+        _ => {
+            Err(ErrorKind::InvalidArgument(format!("{}: unknown file type", path.display())).into())
+        }
+    }
+}
+
+// Prints the corruptions reported by a log reader to the output they
+// are drained to
+struct CorruptionReporter {
+    messages: Rc<RefCell<Vec<String>>>,
+}
+
+impl Reporter for CorruptionReporter {
+    fn corruption(&mut self, bytes: u64, reason: &Error) {
+        self.messages
+            .borrow_mut()
+            .push(format!("corruption: {} bytes; {}\n", bytes, reason));
+    }
+}
+
+// Print contents of a log file.  (`f` is called on every record.)
+fn print_log_contents<F>(env: &dyn Env, path: &Path, dst: &mut dyn Write, mut f: F) -> Result<()>
+where
+    F: FnMut(u64, &[u8], &mut dyn Write) -> Result<()>,
+{
+    let file = env.new_sequential_file(path)?;
+    let messages = Rc::new(RefCell::new(vec![]));
+    let reporter = CorruptionReporter {
+        messages: messages.clone(),
+    };
+    let mut reader = Reader::new(file, Some(Box::new(reporter)), true, 0);
+    let mut record = vec![];
+    loop {
+        let more = reader.read_record(&mut record);
+        for message in messages.borrow_mut().drain(..) {
+            dst.write_all(message.as_bytes())?;
+        }
+        if !more {
+            return Ok(());
+        }
+        f(reader.last_record_offset(), &record, dst)?;
+    }
+}
+
+// Called on every item found in a WriteBatch
+struct WriteBatchItemPrinter<'a> {
+    dst: &'a mut dyn Write,
+    result: Result<()>,
+}
+
+impl<'a> WriteBatchItemPrinter<'a> {
+    fn print(&mut self, line: String) {
+        if self.result.is_ok() {
+            self.result = self.dst.write_all(line.as_bytes()).map_err(Error::from);
+        }
+    }
+}
+
+impl<'a> Handler for WriteBatchItemPrinter<'a> {
+    fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.print(format!(
+            "  put '{}' '{}'\n",
+            escape_string(key),
+            escape_string(value)
+        ));
+    }
+
+    fn delete(&mut self, key: &[u8]) {
+        self.print(format!("  del '{}'\n", escape_string(key)));
+    }
+}
+
+// Called on every log record (each one of which is a WriteBatch)
+// found in a log file
+fn dump_log(env: &dyn Env, path: &Path, dst: &mut dyn Write) -> Result<()> {
+    print_log_contents(env, path, dst, |offset, record, dst| {
+        write!(dst, "--- offset {}; ", offset)?;
+        if record.len() < WRITE_BATCH_HEADER {
+            writeln!(dst, "log record length {} is too small", record.len())?;
+            return Ok(());
+        }
+        let mut batch = WriteBatch::new();
+        batch.set_contents(record);
+        writeln!(dst, "sequence {}", batch.sequence())?;
+        let mut printer = WriteBatchItemPrinter {
+            dst,
+            result: Ok(()),
+        };
+        if let Err(e) = batch.iterate(&mut printer) {
+            printer.result?;
+            writeln!(dst, "  error: {}", e)?;
+            return Ok(());
+        }
+        printer.result
+    })
+}
+
+// Called on every log record (each one of which is a VersionEdit)
+// found in a MANIFEST
+fn dump_descriptor(env: &dyn Env, path: &Path, dst: &mut dyn Write) -> Result<()> {
+    print_log_contents(env, path, dst, |offset, record, dst| {
+        write!(dst, "--- offset {}; ", offset)?;
+        match VersionEdit::decode_from(record) {
+            Ok(edit) => write!(dst, "{:?}", edit)?,
+            Err(e) => writeln!(dst, "{}", e)?,
+        }
+        Ok(())
+    })
+}
+
+fn dump_table(env: &dyn Env, path: &Path, dst: &mut dyn Write) -> Result<()> {
+    let file_size = env.get_file_size(path)?;
+    let file = env.new_random_access_file(path)?;
+    let options = table_options(&Options::<BytewiseComparator>::default());
+    let table = Arc::new(Table::open(options, file, 0, file_size)?);
+    // We use the default comparator, which may or may not match the
+    // comparator used in this database.  However this should not cause
+    // problems since we only use Table operations that do not require
+    // any comparisons.  In particular, we do not call seek().
+    let mut iter = table.new_iterator(ReadOptions {
+        verify_checksums: true,
+        fill_cache: false,
+        snapshot: None,
+    });
+    iter.seek_to_first();
+    while iter.valid() {
+        match ParsedInteralKey::decode_from(iter.key()) {
+            Some(key) => write!(dst, "{:?}", key)?,
+            None => write!(dst, "badkey '{}'", escape_string(iter.key()))?,
+        }
+        writeln!(dst, " => '{}'", escape_string(iter.value()))?;
+        iter.next();
+    }
+    if let Err(e) = iter.status() {
+        writeln!(dst, "iterator error: {}", e)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DB;
+    use crate::env::MemEnv;
+    use crate::options::WriteOptions;
+
+    fn dump(env: &dyn Env, path: &Path) -> String {
+        let mut out = vec![];
+        dump_file(env, path, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_dump_files() {
+        let dbname = Path::new("/dump_files");
+        let env: Arc<dyn Env> = Arc::new(MemEnv::default());
+        let options: Options = Options {
+            create_if_missing: true,
+            env: env.clone(),
+            ..Options::default()
+        };
+        let db = DB::open(dbname, options.clone()).unwrap();
+        db.put(WriteOptions::default(), b"k\xff", b"v1").unwrap();
+        db.delete(WriteOptions::default(), b"k\xff").unwrap();
+        db.close().unwrap();
+        drop(db);
+
+        let mut log = None;
+        let mut manifest = None;
+        for name in env.get_children(dbname).unwrap() {
+            match parse_filename(&name) {
+                Some((FileType::Log, _)) => log = Some(dbname.join(name)),
+                Some((FileType::Descriptor, _)) => manifest = Some(dbname.join(name)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            dump(env.as_ref(), &log.unwrap()),
+            "--- offset 0; sequence 1\n  put 'k\\xff' 'v1'\n\
+             --- offset 26; sequence 2\n  del 'k\\xff'\n"
+        );
+        assert!(dump(env.as_ref(), &manifest.unwrap())
+            .contains("Comparator: leveldb.BytewiseComparator"));
+
+        // Reopening the db flushes the log to a table
+        let db = DB::open(dbname, options).unwrap();
+        db.close().unwrap();
+        drop(db);
+        let tables: Vec<_> = env
+            .get_children(dbname)
+            .unwrap()
+            .into_iter()
+            .filter(|name| matches!(parse_filename(name), Some((FileType::Table, _))))
+            .collect();
+        assert_eq!(
+            dump(env.as_ref(), &dbname.join(&tables[0])),
+            "'k\\xff' @ 2 : KTypeDeletion => ''\n'k\\xff' @ 1 : KTypeValue => 'v1'\n"
+        );
+
+        assert!(dump_file(env.as_ref(), &dbname.join("LOCK"), &mut vec![]).is_err());
+    }
+}
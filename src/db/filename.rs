@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use crate::env::{write_string_to_file_sync, Env};
+use crate::util::status::Result;
+
+/// The kinds of files a database directory may hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Log,
+    Lock,
+    Table,
+    Descriptor,
+    Current,
+    Temp,
+    InfoLog,
+    OldInfoLog,
+}
+
+/// Returns the full path of a file of type `file_type` in the db named `dbname`.
+/// `number` is ignored by the file types that are not numbered.
+pub fn generate_filename(dbname: &Path, file_type: FileType, number: u64) -> PathBuf {
+    match file_type {
+        FileType::Log => dbname.join(format!("{:06}.log", number)),
+        FileType::Lock => dbname.join("LOCK"),
+        FileType::Table => dbname.join(format!("{:06}.ldb", number)),
+        FileType::Descriptor => dbname.join(format!("MANIFEST-{:06}", number)),
+        FileType::Current => dbname.join("CURRENT"),
+        FileType::Temp => dbname.join(format!("{:06}.dbtmp", number)),
+        FileType::InfoLog => dbname.join("LOG"),
+        FileType::OldInfoLog => dbname.join("LOG.old"),
+    }
+}
+
+/// Parses a file name (without its directory) and returns the type and the number
+/// of the file. Returns `None` if the file name is not one the database produces.
+///
+/// Owned filenames have the form:
+///
+/// ```text
+///    dbname/CURRENT
+///    dbname/LOCK
+///    dbname/LOG
+///    dbname/LOG.old
+///    dbname/MANIFEST-[0-9]+
+///    dbname/[0-9]+.(log|sst|ldb|dbtmp)
+/// ```
+pub fn parse_filename(filename: &str) -> Option<(FileType, u64)> {
+    match filename {
+        "CURRENT" => Some((FileType::Current, 0)),
+        "LOCK" => Some((FileType::Lock, 0)),
+        "LOG" => Some((FileType::InfoLog, 0)),
+        "LOG.old" => Some((FileType::OldInfoLog, 0)),
+        _ => {
+            if let Some(rest) = filename.strip_prefix("MANIFEST-") {
+                return parse_number(rest).map(|n| (FileType::Descriptor, n));
+            }
+            let dot = filename.find('.')?;
+            let number = parse_number(&filename[..dot])?;
+            let file_type = match &filename[dot + 1..] {
+                "log" => FileType::Log,
+                "sst" | "ldb" => FileType::Table,
+                "dbtmp" => FileType::Temp,
+                _ => return None,
+            };
+            Some((file_type, number))
+        }
+    }
+}
+
+/// Make the CURRENT file point to the descriptor file with the
+/// specified number.  The new contents are written to a temp file that is
+/// then renamed over CURRENT, so readers never see a partial update.
+/// The directory is synced last, so that both the descriptor file and
+/// the new CURRENT survive a crash once this returns.
+pub fn set_current_file(env: &dyn Env, dbname: &Path, descriptor_number: u64) -> Result<()> {
+    let manifest = generate_filename(dbname, FileType::Descriptor, descriptor_number);
+    let contents = format!("{}\n", manifest.file_name().unwrap().to_str().unwrap());
+    let tmp = generate_filename(dbname, FileType::Temp, descriptor_number);
+    let result = write_string_to_file_sync(env, contents.as_bytes(), &tmp)
+        .and_then(|_| env.rename(&tmp, &generate_filename(dbname, FileType::Current, 0)));
+    if result.is_err() {
+        let _ = env.remove(&tmp);
+    }
+    result.and_then(|_| env.sync_dir(dbname))
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filename() {
+        let cases = vec![
+            ("100.log", 100, FileType::Log),
+            ("0.log", 0, FileType::Log),
+            ("0.sst", 0, FileType::Table),
+            ("0.ldb", 0, FileType::Table),
+            ("CURRENT", 0, FileType::Current),
+            ("LOCK", 0, FileType::Lock),
+            ("MANIFEST-2", 2, FileType::Descriptor),
+            ("MANIFEST-7", 7, FileType::Descriptor),
+            ("LOG", 0, FileType::InfoLog),
+            ("LOG.old", 0, FileType::OldInfoLog),
+            ("18446744073709551615.log", u64::max_value(), FileType::Log),
+        ];
+        for (name, number, file_type) in cases {
+            assert_eq!(
+                parse_filename(name),
+                Some((file_type, number)),
+                "parsing {}",
+                name
+            );
+        }
+
+        let errors = vec![
+            "",
+            "foo",
+            "foo-dx-100.log",
+            ".log",
+            "",
+            "manifest",
+            "CURREN",
+            "CURRENTX",
+            "MANIFES",
+            "MANIFEST",
+            "MANIFEST-",
+            "XMANIFEST-3",
+            "MANIFEST-3x",
+            "LOC",
+            "LOCKx",
+            "LO",
+            "LOGx",
+            "18446744073709551616.log",
+            "184467440737095516150.log",
+            "100",
+            "100.",
+            "100.lop",
+        ];
+        for name in errors {
+            assert_eq!(parse_filename(name), None, "parsing {}", name);
+        }
+    }
+
+    #[test]
+    fn test_generate_filename() {
+        let dbname = Path::new("foo");
+        let cases = vec![
+            (FileType::Log, 192, "foo/000192.log"),
+            (FileType::Table, 200, "foo/000200.ldb"),
+            (FileType::Descriptor, 100, "foo/MANIFEST-000100"),
+            (FileType::Temp, 999, "foo/000999.dbtmp"),
+            (FileType::Current, 0, "foo/CURRENT"),
+            (FileType::Lock, 0, "foo/LOCK"),
+            (FileType::InfoLog, 0, "foo/LOG"),
+            (FileType::OldInfoLog, 0, "foo/LOG.old"),
+        ];
+        for (file_type, number, expect) in cases {
+            let path = generate_filename(dbname, file_type, number);
+            assert_eq!(path, Path::new(expect));
+            let name = path.file_name().unwrap().to_str().unwrap();
+            assert_eq!(parse_filename(name), Some((file_type, number)));
+        }
+    }
+}
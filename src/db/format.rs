@@ -1,11 +1,14 @@
 use crate::util::{
     coding::{self, decode_fixed_64, put_fixed_64, put_varint_32},
     comparator::{self, Comparator},
+    filter_policy::FilterPolicy,
+    logging::escape_string,
 };
 use integer_encoding::{self, FixedInt};
 use std::{
     error::Error,
     fmt::{Debug, Formatter},
+    sync::Arc,
 };
 
 use super::SequenceNumber;
@@ -51,7 +54,7 @@ fn append_Internal_key(dst: &mut Vec<u8>, key: &ParsedInteralKey) {
 }
 
 #[inline]
-fn extract_user_key(key: &[u8]) -> &[u8] {
+pub fn extract_user_key(key: &[u8]) -> &[u8] {
     let size = key.len();
     assert!(
         size >= 8,
@@ -89,8 +92,10 @@ impl<'a> Debug for ParsedInteralKey<'a> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
         write!(
             f,
-            "{:?} @ {} : {:?}",
-            self.user_key, self.sequence, self.value_type
+            "'{}' @ {} : {:?}",
+            escape_string(self.user_key),
+            self.sequence,
+            self.value_type
         )
     }
 }
@@ -110,7 +115,7 @@ impl<'a> ParsedInteralKey<'a> {
         if len < 8 {
             return None;
         }
-        let num = decode_fixed_64(slice);
+        let num = decode_fixed_64(&slice[len - 8..]);
         let c = (num & 0xff) as u8;
 
         Some(ParsedInteralKey {
@@ -122,8 +127,23 @@ impl<'a> ParsedInteralKey<'a> {
     pub fn internal_key_encoding_length(&self) -> usize {
         self.user_key.len() + 8
     }
-    pub fn user_key(&self) -> &str {
-        std::str::from_utf8(&self.user_key).unwrap()
+    pub fn user_key(&self) -> &'a [u8] {
+        self.user_key
+    }
+    pub fn sequence(&self) -> SequenceNumber {
+        self.sequence
+    }
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+}
+
+impl Debug for InternalKey {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self.parse() {
+            Some(parsed) => write!(f, "{:?}", parsed),
+            None => write!(f, "(bad){}", escape_string(&self.rep)),
+        }
     }
 }
 
@@ -209,8 +229,8 @@ impl<C: Comparator + Clone> Comparator for InternalKeyComparator<C> {
             _ => std::cmp::Ordering::Greater,
         }
     }
-    fn name() -> String {
-        String::from("leveldb.InternalKeyComparator")
+    fn name(&self) -> &str {
+        "leveldb.InternalKeyComparator"
     }
 
     // return a string which physically between start and limit
@@ -260,6 +280,35 @@ impl<C: Comparator + Clone> Comparator for InternalKeyComparator<C> {
     }
 }
 
+// Filter policy wrapper that converts from internal keys to user keys
+pub struct InternalFilterPolicy {
+    user_policy: Arc<dyn FilterPolicy>,
+}
+
+impl InternalFilterPolicy {
+    pub fn new(user_policy: Arc<dyn FilterPolicy>) -> Self {
+        InternalFilterPolicy { user_policy }
+    }
+}
+
+impl FilterPolicy for InternalFilterPolicy {
+    fn name(&self) -> &str {
+        self.user_policy.name()
+    }
+
+    fn create_filter(&self, keys: &[&[u8]], dst: &mut Vec<u8>) {
+        // Filters are built and probed on user keys, so every version of
+        // a user key maps to the same bits.
+        let user_keys: Vec<&[u8]> = keys.iter().map(|k| extract_user_key(k)).collect();
+        self.user_policy.create_filter(&user_keys, dst);
+    }
+
+    fn key_may_match(&self, key: &[u8], filter: &[u8]) -> bool {
+        self.user_policy
+            .key_may_match(extract_user_key(key), filter)
+    }
+}
+
 // We construct a char array of the form:
 //    klength  varint32               <-- start_
 //    userkey  char[klength]          <-- kstart_
@@ -319,11 +368,19 @@ mod tests {
         pack_sequence_and_type(1 << 56, ValueType::KTypeValue);
     }
 
+    #[test]
+    fn test_debug_non_utf8_key() {
+        let key = InternalKey::new(b"k\xff\x00", 5, ValueType::KTypeValue);
+        assert_eq!(key.parse().unwrap().user_key(), b"k\xff\x00");
+        assert_eq!(format!("{:?}", key), "'k\\xff\\x00' @ 5 : KTypeValue");
+        assert_eq!(format!("{:?}", InternalKey::decode_from(b"k")), "(bad)k");
+    }
+
     fn assert_encoded_decoded(key: &str, seq: u64, vt: ValueType) {
         let encoded = InternalKey::new(key.as_bytes(), seq, vt);
         assert_eq!(key.as_bytes(), encoded.user_key());
         let decoded = encoded.parse().expect("");
-        assert_eq!(key, decoded.user_key());
+        assert_eq!(key.as_bytes(), decoded.user_key());
         assert_eq!(seq, decoded.sequence);
         assert_eq!(vt, decoded.value_type);
     }
@@ -465,4 +522,28 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_internal_filter_policy() {
+        use crate::util::filter_policy::BloomFilterPolicy;
+        let policy = InternalFilterPolicy::new(Arc::new(BloomFilterPolicy::new(10)));
+        let keys = vec![
+            InternalKey::new(b"hello", 100, ValueType::KTypeValue),
+            InternalKey::new(b"world", 1, ValueType::KTypeDeletion),
+        ];
+        let encoded: Vec<&[u8]> = keys.iter().map(|k| k.encode()).collect();
+        let mut filter = vec![];
+        policy.create_filter(&encoded, &mut filter);
+        assert_eq!(policy.name(), "leveldb.BuiltinBloomFilter2");
+
+        // Any sequence number or type of a user key matches
+        for seq in [1, 50, 100, K_MAX_SEQUENCE_NUMBER].iter() {
+            let hello = InternalKey::new(b"hello", *seq, ValueType::KTypeValue);
+            let world = InternalKey::new(b"world", *seq, ValueType::KTypeValue);
+            assert!(policy.key_may_match(hello.encode(), &filter));
+            assert!(policy.key_may_match(world.encode(), &filter));
+        }
+        let missing = InternalKey::new(b"missing", 100, ValueType::KTypeValue);
+        assert!(!policy.key_may_match(missing.encode(), &filter));
+    }
 }
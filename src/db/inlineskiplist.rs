@@ -1,10 +1,10 @@
-use crate::db::iterator::LevedbIterator;
+use crate::db::ldbiterator::LdbIterator;
 use crate::util::arena::ArenaTrait;
 use crate::util::comparator::Comparator;
+use crate::util::status::Result;
 use bytes::Bytes;
 use rand::random;
 use std::cmp::Ordering as CmpOrdering;
-use std::fmt::Error;
 use std::mem;
 use std::ptr;
 use std::ptr::{null, null_mut, NonNull};
@@ -14,6 +14,10 @@ use std::sync::Arc;
 const MAX_HEIGHT: usize = 20;
 const HEIGHT_INCREASE: u32 = u32::MAX / 3;
 
+/// The most arena memory a single inserted key can take: a node of the
+/// max height plus its alignment padding.
+pub const MAX_NODE_ARENA_SIZE: usize = mem::size_of::<Node>() + mem::align_of::<Node>();
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct Node {
@@ -350,7 +354,7 @@ where
     node: *const Node,
 }
 
-impl<C, A> LevedbIterator for InlineSkiplistIterator<C, A>
+impl<C, A> LdbIterator for InlineSkiplistIterator<C, A>
 where
     C: Comparator + Clone,
     A: ArenaTrait + Clone + Send + Sync,
@@ -393,11 +397,14 @@ where
         unsafe { (*self.node).key() }
     }
 
+    // The nodes only hold a key, in which the users of the list encode
+    // whole entries
     fn value(&self) -> &[u8] {
-        unimplemented!()
+        assert!(self.valid());
+        &[]
     }
 
-    fn status(&mut self) -> Result<(), Error> {
+    fn status(&mut self) -> Result<()> {
         Ok(())
     }
 }
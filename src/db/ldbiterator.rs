@@ -1,19 +1,247 @@
-use super::ldbslice::Slice;
+use crate::util::status::{Error, ErrorKind, Result};
 
+/// An iterator yields a sequence of key/value pairs from a source.
+/// The following class defines the interface.  Multiple implementations
+/// are provided by this library.  In particular, iterators are provided
+/// to access the contents of a Table or a DB.
 pub trait LdbIterator {
+    /// An iterator is either positioned at a key/value pair, or
+    /// not valid.  This method returns true iff the iterator is valid.
     fn valid(&self) -> bool;
-    // An iterator is either positioned at a key/value pair, or
-    // not valid.  This method returns true iff the iterator is valid.
+
+    /// Position at the first key in the source.  The iterator is `valid()`
+    /// after this call iff the source is not empty.
     fn seek_to_first(&mut self);
 
+    /// Position at the last key in the source.  The iterator is
+    /// `valid()` after this call iff the source is not empty.
     fn seek_to_last(&mut self);
+
+    /// Position at the first key in the source that is at or past target.
+    /// The iterator is valid after this call iff the source contains
+    /// an entry that comes at or past target.
     fn seek(&mut self, target: &[u8]);
 
+    /// Moves to the next entry in the source.  After this call, the iterator is
+    /// valid iff the iterator was not positioned at the last entry in the source.
+    /// REQUIRES: `valid()`
     fn next(&mut self);
 
+    /// Moves to the previous entry in the source.  After this call, the iterator
+    /// is valid iff the iterator was not positioned at the first entry in source.
+    /// REQUIRES: `valid()`
+    fn prev(&mut self);
+
+    /// Return the key for the current entry.  The underlying storage for
+    /// the returned slice is valid only until the next modification of
+    /// the iterator.
+    /// REQUIRES: `valid()`
     fn key(&self) -> &[u8];
 
+    /// Return the value for the current entry.  The underlying storage for
+    /// the returned slice is valid only until the next modification of
+    /// the iterator.
+    /// REQUIRES: `valid()`
     fn value(&self) -> &[u8];
-    fn prev(&mut self);
-    fn status(&self);
+
+    /// If an error has occurred, return it.  Else return an ok status.
+    fn status(&mut self) -> Result<()>;
+
+    /// Turns the iterator into a `std::iter::Iterator` over copies of its
+    /// entries, from the first one on.  Iterating from the back with
+    /// `next_back()` starts at the last entry.
+    fn entries(self) -> Entries<Self>
+    where
+        Self: Sized,
+    {
+        Entries {
+            iter: self,
+            front: None,
+            back: None,
+            position: Position::Unset,
+            done: false,
+        }
+    }
+}
+
+// Which of the keys yielded by `Entries` the inner iterator sits at
+#[derive(Clone, Copy, PartialEq)]
+enum Position {
+    Unset,
+    Front,
+    Back,
+}
+
+/// Adapts a `LdbIterator` to `Iterator` and `DoubleEndedIterator`,
+/// yielding the owned key/value pairs.  An error of the underlying
+/// iterator is yielded once, after which the iteration is over.
+///
+/// Since both ends share the cursor of the underlying iterator,
+/// switching between `next()` and `next_back()` costs a seek.
+pub struct Entries<I: LdbIterator> {
+    iter: I,
+    // The last keys yielded from each end
+    front: Option<Vec<u8>>,
+    back: Option<Vec<u8>>,
+    position: Position,
+    done: bool,
+}
+
+impl<I: LdbIterator> Entries<I> {
+    /// Returns the underlying iterator
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+
+    // Takes the entry the inner iterator is positioned at, unless both
+    // ends met or the inner iterator is exhausted
+    fn take(&mut self, position: Position) -> Option<Result<(Vec<u8>, Vec<u8>)>> {
+        let other = match position {
+            Position::Front => &self.back,
+            _ => &self.front,
+        };
+        if !self.iter.valid() || other.as_deref() == Some(self.iter.key()) {
+            self.done = true;
+            return match self.iter.status() {
+                Ok(()) => None,
+                Err(e) => Some(Err(e)),
+            };
+        }
+        let key = self.iter.key().to_vec();
+        let value = self.iter.value().to_vec();
+        match position {
+            Position::Front => self.front = Some(key.clone()),
+            _ => self.back = Some(key.clone()),
+        }
+        self.position = position;
+        Some(Ok((key, value)))
+    }
+}
+
+impl<I: LdbIterator> Iterator for Entries<I> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match &self.front {
+            None => self.iter.seek_to_first(),
+            Some(front) => {
+                if self.position != Position::Front {
+                    self.iter.seek(front);
+                }
+                self.iter.next();
+            }
+        }
+        self.take(Position::Front)
+    }
+}
+
+impl<I: LdbIterator> DoubleEndedIterator for Entries<I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match &self.back {
+            None => self.iter.seek_to_last(),
+            Some(back) => {
+                if self.position != Position::Back {
+                    self.iter.seek(back);
+                }
+                self.iter.prev();
+            }
+        }
+        self.take(Position::Back)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::block::{Block, BlockBuilder, BlockIterator};
+    use crate::util::comparator::BytewiseComparator;
+
+    fn new_block_iter(n: usize) -> BlockIterator<BytewiseComparator> {
+        let mut builder = BlockBuilder::new(2, BytewiseComparator::default());
+        for i in 0..n {
+            builder.add(format!("k{}", i).as_bytes(), format!("v{}", i).as_bytes());
+        }
+        Block::new(Vec::from(builder.finish()))
+            .unwrap()
+            .iter(BytewiseComparator::default())
+    }
+
+    fn entry(i: usize) -> (Vec<u8>, Vec<u8>) {
+        (
+            format!("k{}", i).into_bytes(),
+            format!("v{}", i).into_bytes(),
+        )
+    }
+
+    #[test]
+    fn test_entries_forward_and_backward() {
+        let entries: Vec<_> = new_block_iter(5).entries().map(|e| e.unwrap()).collect();
+        assert_eq!(entries, (0..5).map(entry).collect::<Vec<_>>());
+        let entries: Vec<_> = new_block_iter(5)
+            .entries()
+            .rev()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(entries, (0..5).rev().map(entry).collect::<Vec<_>>());
+        assert_eq!(new_block_iter(0).entries().count(), 0);
+    }
+
+    #[test]
+    fn test_entries_both_ends() {
+        let mut entries = new_block_iter(5).entries();
+        assert_eq!(entries.next().unwrap().unwrap(), entry(0));
+        assert_eq!(entries.next_back().unwrap().unwrap(), entry(4));
+        assert_eq!(entries.next().unwrap().unwrap(), entry(1));
+        assert_eq!(entries.next_back().unwrap().unwrap(), entry(3));
+        assert_eq!(entries.next().unwrap().unwrap(), entry(2));
+        // Both ends met
+        assert!(entries.next_back().is_none());
+        assert!(entries.next().is_none());
+
+        let mut entries = new_block_iter(1).entries();
+        assert_eq!(entries.next_back().unwrap().unwrap(), entry(0));
+        assert!(entries.next().is_none());
+    }
+
+    struct ErrorIterator;
+
+    impl LdbIterator for ErrorIterator {
+        fn valid(&self) -> bool {
+            false
+        }
+        fn seek_to_first(&mut self) {}
+        fn seek_to_last(&mut self) {}
+        fn seek(&mut self, _target: &[u8]) {}
+        fn next(&mut self) {}
+        fn key(&self) -> &[u8] {
+            unreachable!()
+        }
+        fn value(&self) -> &[u8] {
+            unreachable!()
+        }
+        fn prev(&mut self) {}
+        fn status(&mut self) -> Result<()> {
+            Err(ErrorKind::Corruption("bad block".to_owned()).into())
+        }
+    }
+
+    #[test]
+    fn test_entries_error() {
+        let mut entries = ErrorIterator.entries();
+        match entries.next() {
+            Some(Err(e)) => match e.kind() {
+                ErrorKind::Corruption(msg) => assert_eq!(msg, "bad block"),
+                _ => panic!("expect a corruption error"),
+            },
+            _ => panic!("the error should be yielded"),
+        }
+        assert!(entries.next().is_none());
+        assert!(entries.next_back().is_none());
+    }
 }
@@ -0,0 +1,532 @@
+// Log format information shared by reader and writer.
+//
+// The log file contents are a sequence of 32KB blocks.  The only
+// exception is that the tail of the file may contain a partial block.
+//
+// Each block consists of a sequence of records:
+//    block := record* trailer?
+//    record :=
+//      checksum: uint32     // masked crc32c of type and data[] ; little-endian
+//      length: uint16       // little-endian
+//      type: uint8          // One of FULL, FIRST, MIDDLE, LAST
+//      data: uint8[length]
+//
+// A record never starts within the last six bytes of a block (since it
+// won't fit).  Any leftover bytes here form the trailer, which must
+// consist entirely of zero bytes and must be skipped by readers.
+
+mod reader;
+mod writer;
+
+pub use reader::{Reader, Reporter};
+pub use writer::Writer;
+
+pub const BLOCK_SIZE: usize = 32768;
+
+// Header is checksum (4 bytes), length (2 bytes), type (1 byte).
+pub const HEADER_SIZE: usize = 4 + 2 + 1;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RecordType {
+    // Zero is reserved for preallocated files
+    Zero = 0,
+    Full = 1,
+    // For fragments
+    First = 2,
+    Middle = 3,
+    Last = 4,
+}
+
+pub const MAX_RECORD_TYPE: u8 = RecordType::Last as u8;
+
+impl RecordType {
+    // Returns the record type of a non-zero tag, or `None` if it is unknown
+    fn from_u8(t: u8) -> Option<RecordType> {
+        match t {
+            1 => Some(RecordType::Full),
+            2 => Some(RecordType::First),
+            3 => Some(RecordType::Middle),
+            4 => Some(RecordType::Last),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{coding::encode_fixed_32, crc32c, status::Error};
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    // Construct a string of the specified length made out of the supplied
+    // partial string.
+    fn big_string(partial: &str, n: usize) -> String {
+        let mut result = String::with_capacity(n + partial.len());
+        while result.len() < n {
+            result.push_str(partial);
+        }
+        result.truncate(n);
+        result
+    }
+
+    // Construct a string from a number
+    fn number_string(n: usize) -> String {
+        format!("{}.", n)
+    }
+
+    // Return a skewed potentially long string
+    fn random_skewed_string(i: usize) -> String {
+        let n = rand::random::<usize>() % (1 << (rand::random::<usize>() % 17));
+        big_string(&number_string(i), n)
+    }
+
+    #[derive(Default)]
+    struct ReportCollector {
+        dropped_bytes: u64,
+        message: String,
+    }
+
+    struct SharedReporter(Rc<RefCell<ReportCollector>>);
+
+    impl Reporter for SharedReporter {
+        fn corruption(&mut self, bytes: u64, reason: &Error) {
+            let mut r = self.0.borrow_mut();
+            r.dropped_bytes += bytes;
+            r.message.push_str(&reason.to_string());
+        }
+    }
+
+    struct LogTest {
+        writer: Writer<Vec<u8>>,
+        reader: Option<Reader<Cursor<Vec<u8>>>>,
+        report: Rc<RefCell<ReportCollector>>,
+    }
+
+    impl LogTest {
+        fn new() -> Self {
+            LogTest {
+                writer: Writer::new(vec![]),
+                reader: None,
+                report: Rc::new(RefCell::new(ReportCollector::default())),
+            }
+        }
+
+        fn reopen_for_append(&mut self) {
+            let contents = std::mem::take(self.writer.get_mut());
+            let len = contents.len() as u64;
+            self.writer = Writer::with_offset(contents, len);
+        }
+
+        fn write(&mut self, msg: &str) {
+            assert!(self.reader.is_none(), "write() after starting to read");
+            self.writer.add_record(msg.as_bytes()).unwrap();
+        }
+
+        fn written_bytes(&self) -> usize {
+            self.writer.get_ref().len()
+        }
+
+        fn contents(&mut self) -> &mut Vec<u8> {
+            self.writer.get_mut()
+        }
+
+        fn start_reading_at(&mut self, initial_offset: u64) {
+            let reporter = SharedReporter(self.report.clone());
+            let file = Cursor::new(self.writer.get_ref().clone());
+            self.reader = Some(Reader::new(
+                file,
+                Some(Box::new(reporter)),
+                true,
+                initial_offset,
+            ));
+        }
+
+        fn read(&mut self) -> String {
+            if self.reader.is_none() {
+                self.start_reading_at(0);
+            }
+            let mut record = vec![];
+            if self.reader.as_mut().unwrap().read_record(&mut record) {
+                String::from_utf8(record).unwrap()
+            } else {
+                "EOF".to_owned()
+            }
+        }
+
+        fn increment_byte(&mut self, offset: usize, delta: u8) {
+            let b = &mut self.contents()[offset];
+            *b = b.wrapping_add(delta);
+        }
+
+        fn set_byte(&mut self, offset: usize, new_byte: u8) {
+            self.contents()[offset] = new_byte;
+        }
+
+        fn shrink_size(&mut self, bytes: usize) {
+            let len = self.written_bytes();
+            self.contents().truncate(len - bytes);
+        }
+
+        fn fix_checksum(&mut self, header_offset: usize, len: usize) {
+            // Compute crc of type/len/data
+            let contents = self.contents();
+            let crc = crc32c::value(&contents[header_offset + 6..header_offset + 6 + 1 + len]);
+            encode_fixed_32(&mut contents[header_offset..], crc32c::mask(crc));
+        }
+
+        fn dropped_bytes(&self) -> u64 {
+            self.report.borrow().dropped_bytes
+        }
+
+        fn report_message(&self) -> String {
+            self.report.borrow().message.clone()
+        }
+
+        // Returns OK iff recorded error message contains "msg"
+        fn match_error(&self, msg: &str) -> bool {
+            self.report_message().contains(msg)
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut t = LogTest::new();
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_read_write() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.write("bar");
+        t.write("");
+        t.write("xxxx");
+        assert_eq!("foo", t.read());
+        assert_eq!("bar", t.read());
+        assert_eq!("", t.read());
+        assert_eq!("xxxx", t.read());
+        assert_eq!("EOF", t.read());
+        assert_eq!("EOF", t.read()); // Make sure reads at eof work
+    }
+
+    #[test]
+    fn test_many_blocks() {
+        let mut t = LogTest::new();
+        for i in 0..100_000 {
+            t.write(&number_string(i));
+        }
+        for i in 0..100_000 {
+            assert_eq!(number_string(i), t.read());
+        }
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_fragmentation() {
+        let mut t = LogTest::new();
+        t.write("small");
+        t.write(&big_string("medium", 50000));
+        t.write(&big_string("large", 100000));
+        assert_eq!("small", t.read());
+        assert_eq!(big_string("medium", 50000), t.read());
+        assert_eq!(big_string("large", 100000), t.read());
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_marginal_trailer() {
+        // Make a trailer that is exactly the same length as an empty record.
+        let mut t = LogTest::new();
+        let n = BLOCK_SIZE - 2 * HEADER_SIZE;
+        t.write(&big_string("foo", n));
+        assert_eq!(BLOCK_SIZE - HEADER_SIZE, t.written_bytes());
+        t.write("");
+        t.write("bar");
+        assert_eq!(big_string("foo", n), t.read());
+        assert_eq!("", t.read());
+        assert_eq!("bar", t.read());
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_short_trailer() {
+        let mut t = LogTest::new();
+        let n = BLOCK_SIZE - 2 * HEADER_SIZE + 4;
+        t.write(&big_string("foo", n));
+        assert_eq!(BLOCK_SIZE - HEADER_SIZE + 4, t.written_bytes());
+        t.write("");
+        t.write("bar");
+        assert_eq!(big_string("foo", n), t.read());
+        assert_eq!("", t.read());
+        assert_eq!("bar", t.read());
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_aligned_eof() {
+        let mut t = LogTest::new();
+        let n = BLOCK_SIZE - 2 * HEADER_SIZE + 4;
+        t.write(&big_string("foo", n));
+        assert_eq!(BLOCK_SIZE - HEADER_SIZE + 4, t.written_bytes());
+        assert_eq!(big_string("foo", n), t.read());
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_open_for_append() {
+        let mut t = LogTest::new();
+        t.write("hello");
+        t.reopen_for_append();
+        t.write("world");
+        assert_eq!("hello", t.read());
+        assert_eq!("world", t.read());
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_random_read() {
+        let mut t = LogTest::new();
+        let n = 500;
+        let records: Vec<String> = (0..n).map(random_skewed_string).collect();
+        for r in records.iter() {
+            t.write(r);
+        }
+        for r in records.iter() {
+            assert_eq!(*r, t.read());
+        }
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_bad_record_type() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        // Type is stored in header[6]
+        t.increment_byte(6, 100);
+        t.fix_checksum(0, 3);
+        assert_eq!("EOF", t.read());
+        assert_eq!(3, t.dropped_bytes());
+        assert!(t.match_error("unknown record type"));
+    }
+
+    #[test]
+    fn test_truncated_trailing_record_is_ignored() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.shrink_size(4); // Drop all payload as well as a header byte
+        assert_eq!("EOF", t.read());
+        // Truncated last record is ignored, not treated as an error.
+        assert_eq!(0, t.dropped_bytes());
+        assert_eq!("", t.report_message());
+    }
+
+    #[test]
+    fn test_bad_length() {
+        let mut t = LogTest::new();
+        let payload_size = BLOCK_SIZE - HEADER_SIZE;
+        t.write(&big_string("bar", payload_size));
+        t.write("foo");
+        // Least significant size byte is stored in header[4].
+        t.increment_byte(4, 1);
+        assert_eq!("foo", t.read());
+        assert_eq!(BLOCK_SIZE as u64, t.dropped_bytes());
+        assert!(t.match_error("bad record length"));
+    }
+
+    #[test]
+    fn test_bad_length_at_end_is_ignored() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.shrink_size(1);
+        assert_eq!("EOF", t.read());
+        assert_eq!(0, t.dropped_bytes());
+        assert_eq!("", t.report_message());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.increment_byte(0, 10);
+        assert_eq!("EOF", t.read());
+        assert_eq!(10, t.dropped_bytes());
+        assert!(t.match_error("checksum mismatch"));
+    }
+
+    #[test]
+    fn test_unexpected_middle_type() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.set_byte(6, RecordType::Middle as u8);
+        t.fix_checksum(0, 3);
+        assert_eq!("EOF", t.read());
+        assert_eq!(3, t.dropped_bytes());
+        assert!(t.match_error("missing start"));
+    }
+
+    #[test]
+    fn test_unexpected_last_type() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.set_byte(6, RecordType::Last as u8);
+        t.fix_checksum(0, 3);
+        assert_eq!("EOF", t.read());
+        assert_eq!(3, t.dropped_bytes());
+        assert!(t.match_error("missing start"));
+    }
+
+    #[test]
+    fn test_unexpected_full_type() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.write("bar");
+        t.set_byte(6, RecordType::First as u8);
+        t.fix_checksum(0, 3);
+        assert_eq!("bar", t.read());
+        assert_eq!("EOF", t.read());
+        assert_eq!(3, t.dropped_bytes());
+        assert!(t.match_error("partial record without end"));
+    }
+
+    #[test]
+    fn test_unexpected_first_type() {
+        let mut t = LogTest::new();
+        t.write("foo");
+        t.write(&big_string("bar", 100000));
+        t.set_byte(6, RecordType::First as u8);
+        t.fix_checksum(0, 3);
+        assert_eq!(big_string("bar", 100000), t.read());
+        assert_eq!("EOF", t.read());
+        assert_eq!(3, t.dropped_bytes());
+        assert!(t.match_error("partial record without end"));
+    }
+
+    #[test]
+    fn test_missing_last_is_ignored() {
+        let mut t = LogTest::new();
+        t.write(&big_string("bar", BLOCK_SIZE));
+        // Remove the LAST block, including header.
+        t.shrink_size(14);
+        assert_eq!("EOF", t.read());
+        assert_eq!("", t.report_message());
+        assert_eq!(0, t.dropped_bytes());
+    }
+
+    #[test]
+    fn test_partial_last_is_ignored() {
+        let mut t = LogTest::new();
+        t.write(&big_string("bar", BLOCK_SIZE));
+        // Cause a bad record length in the LAST block.
+        t.shrink_size(1);
+        assert_eq!("EOF", t.read());
+        assert_eq!("", t.report_message());
+        assert_eq!(0, t.dropped_bytes());
+    }
+
+    #[test]
+    fn test_error_joins_records() {
+        // Consider two fragmented records:
+        //    first(R1) last(R1) first(R2) last(R2)
+        // where the middle two fragments disappear.  We do not want
+        // first(R1),last(R2) to get joined and returned as a valid record.
+        let mut t = LogTest::new();
+
+        // Write records that span two blocks
+        t.write(&big_string("foo", BLOCK_SIZE));
+        t.write(&big_string("bar", BLOCK_SIZE));
+        t.write("correct");
+
+        // Wipe the middle block
+        for offset in BLOCK_SIZE..2 * BLOCK_SIZE {
+            t.set_byte(offset, b'x');
+        }
+
+        assert_eq!("correct", t.read());
+        assert_eq!("EOF", t.read());
+        let dropped = t.dropped_bytes();
+        assert!(dropped <= 2 * BLOCK_SIZE as u64 + 100);
+        assert!(dropped >= 2 * BLOCK_SIZE as u64);
+    }
+
+    // Writes the records used by the initial offset tests and returns the
+    // offset of every record
+    fn write_initial_offset_log(t: &mut LogTest) -> Vec<(usize, u64)> {
+        let sizes = [
+            10000,                    // Two sizable records in first block
+            10000,                    //
+            2 * BLOCK_SIZE - 1000,    // Span three blocks
+            1,                        //
+            13716,                    // Consume all but two bytes of block 3.
+            BLOCK_SIZE - HEADER_SIZE, // Consume the entirety of block 4.
+        ];
+        let mut offsets = vec![];
+        for (i, size) in sizes.iter().enumerate() {
+            let mut offset = t.written_bytes();
+            if BLOCK_SIZE - offset % BLOCK_SIZE < HEADER_SIZE {
+                // The record starts after the block trailer
+                offset += BLOCK_SIZE - offset % BLOCK_SIZE;
+            }
+            offsets.push((*size, offset as u64));
+            let c = (b'a' + i as u8) as char;
+            t.write(&big_string(&c.to_string(), *size));
+        }
+        offsets
+    }
+
+    #[test]
+    fn test_read_start() {
+        let mut t = LogTest::new();
+        let offsets = write_initial_offset_log(&mut t);
+        t.start_reading_at(0);
+        for (size, offset) in offsets {
+            let record = t.read();
+            assert_eq!(size, record.len());
+            assert_eq!(offset, t.reader.as_ref().unwrap().last_record_offset());
+        }
+        assert_eq!("EOF", t.read());
+    }
+
+    #[test]
+    fn test_read_from_initial_offset() {
+        let offsets = {
+            let mut t = LogTest::new();
+            write_initial_offset_log(&mut t)
+        };
+        for (expected_index, initial_offset) in [
+            (1, 1),
+            (1, 10000),
+            (1, 10007),
+            (2, 10008),
+            (2, 20014),
+            (3, 20015),
+            (3, BLOCK_SIZE as u64 - 4),
+            (3, BLOCK_SIZE as u64 + 1),
+            (3, 2 * BLOCK_SIZE as u64 + 1),
+            (5, 3 * BLOCK_SIZE as u64 - 3),
+        ]
+        .iter()
+        {
+            let mut t = LogTest::new();
+            write_initial_offset_log(&mut t);
+            t.start_reading_at(*initial_offset);
+            for (size, offset) in offsets.iter().skip(*expected_index) {
+                let record = t.read();
+                assert_eq!(*size, record.len());
+                assert_eq!(*offset, t.reader.as_ref().unwrap().last_record_offset());
+            }
+            assert_eq!("EOF", t.read());
+        }
+    }
+
+    #[test]
+    fn test_read_past_end() {
+        let mut t = LogTest::new();
+        write_initial_offset_log(&mut t);
+        let len = t.written_bytes() as u64;
+        t.start_reading_at(len + 5);
+        assert_eq!("EOF", t.read());
+        assert_eq!(0, t.dropped_bytes());
+    }
+}
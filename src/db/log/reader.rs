@@ -0,0 +1,398 @@
+use std::io::{self, Read};
+
+use super::{RecordType, BLOCK_SIZE, HEADER_SIZE};
+use crate::util::{
+    coding::decode_fixed_32,
+    crc32c,
+    status::{Error, ErrorKind},
+};
+
+/// Interface for reporting errors found while reading a log.
+pub trait Reporter {
+    /// Some corruption was detected.  `bytes` is the approximate number
+    /// of bytes dropped due to the corruption.
+    fn corruption(&mut self, bytes: u64, reason: &Error);
+}
+
+// The result of reading one physical record
+enum PhysicalRecord {
+    // A fragment of the given type, stored in `backing_store[start..end]`
+    Fragment(RecordType, usize, usize),
+    // A record of an unknown type (including a non-empty Zero record)
+    // with its fragment length
+    Unknown(u8, usize),
+    Eof,
+    // Returned whenever we find an invalid physical record.
+    // Currently there are three situations in which this happens:
+    // * The record has an invalid CRC (`read_physical_record` reports a drop)
+    // * The record is a 0-length record (No drop is reported)
+    // * The record is below constructor's initial_offset (No drop is reported)
+    BadRecord,
+}
+
+/// `Reader` reads back the records appended by a `Writer`.
+pub struct Reader<R: Read> {
+    file: R,
+    reporter: Option<Box<dyn Reporter>>,
+    checksum: bool,
+    backing_store: Vec<u8>,
+    // The unconsumed part of the last block read is
+    // `backing_store[buf_start..buf_end]`
+    buf_start: usize,
+    buf_end: usize,
+    // Last read() indicated EOF by returning < BLOCK_SIZE
+    eof: bool,
+
+    // Offset of the last record returned by `read_record`.
+    last_record_offset: u64,
+    // Offset of the first location past the end of buffer.
+    end_of_buffer_offset: u64,
+
+    // Offset at which to start looking for the first record to return
+    initial_offset: u64,
+
+    // True if we are resynchronizing after a seek (initial_offset > 0). In
+    // particular, a run of Middle and Last records can be silently skipped in
+    // this mode
+    resyncing: bool,
+}
+
+impl<R: Read> Reader<R> {
+    /// Create a reader that will return log records from `file`.
+    ///
+    /// If `reporter` is given, it is notified whenever some data is
+    /// dropped due to a detected corruption.
+    ///
+    /// If `checksum` is true, verify checksums if available.
+    ///
+    /// The Reader will start reading at the first record located at physical
+    /// position >= `initial_offset` within the file.
+    pub fn new(
+        file: R,
+        reporter: Option<Box<dyn Reporter>>,
+        checksum: bool,
+        initial_offset: u64,
+    ) -> Self {
+        Reader {
+            file,
+            reporter,
+            checksum,
+            backing_store: vec![0; BLOCK_SIZE],
+            buf_start: 0,
+            buf_end: 0,
+            eof: false,
+            last_record_offset: 0,
+            end_of_buffer_offset: 0,
+            initial_offset,
+            resyncing: initial_offset > 0,
+        }
+    }
+
+    /// Read the next record into `record`.  Returns true if read
+    /// successfully, false if we hit end of the input.
+    pub fn read_record(&mut self, record: &mut Vec<u8>) -> bool {
+        if self.last_record_offset < self.initial_offset && !self.skip_to_initial_block() {
+            return false;
+        }
+
+        record.clear();
+        let mut in_fragmented_record = false;
+        // Record offset of the logical record that we're reading
+        let mut prospective_record_offset = 0;
+
+        loop {
+            let physical_record = self.read_physical_record();
+            let fragment_len = match physical_record {
+                PhysicalRecord::Fragment(_, start, end) => end - start,
+                PhysicalRecord::Unknown(_, len) => len,
+                _ => 0,
+            };
+            // `read_physical_record` may have only had an empty trailer remaining in its
+            // internal buffer. Calculate the offset of the next physical record now
+            // that it has returned, properly accounting for its header size.
+            let physical_record_offset = self
+                .end_of_buffer_offset
+                .wrapping_sub(self.buffer_len() as u64)
+                .wrapping_sub(HEADER_SIZE as u64)
+                .wrapping_sub(fragment_len as u64);
+
+            if self.resyncing {
+                match physical_record {
+                    PhysicalRecord::Fragment(RecordType::Middle, ..) => continue,
+                    PhysicalRecord::Fragment(RecordType::Last, ..) => {
+                        self.resyncing = false;
+                        continue;
+                    }
+                    _ => self.resyncing = false,
+                }
+            }
+
+            match physical_record {
+                PhysicalRecord::Fragment(RecordType::Full, start, end) => {
+                    if in_fragmented_record {
+                        // Handle bug in earlier versions of log::Writer where
+                        // it could emit an empty First record at the tail end
+                        // of a block followed by a Full or First record
+                        // at the beginning of the next block.
+                        if !record.is_empty() {
+                            self.report_corruption(
+                                record.len() as u64,
+                                "partial record without end(1)",
+                            );
+                        }
+                    }
+                    record.clear();
+                    record.extend_from_slice(&self.backing_store[start..end]);
+                    self.last_record_offset = physical_record_offset;
+                    return true;
+                }
+                PhysicalRecord::Fragment(RecordType::First, start, end) => {
+                    if in_fragmented_record && !record.is_empty() {
+                        self.report_corruption(
+                            record.len() as u64,
+                            "partial record without end(2)",
+                        );
+                    }
+                    prospective_record_offset = physical_record_offset;
+                    record.clear();
+                    record.extend_from_slice(&self.backing_store[start..end]);
+                    in_fragmented_record = true;
+                }
+                PhysicalRecord::Fragment(RecordType::Middle, start, end) => {
+                    if !in_fragmented_record {
+                        self.report_corruption(
+                            (end - start) as u64,
+                            "missing start of fragmented record(1)",
+                        );
+                    } else {
+                        record.extend_from_slice(&self.backing_store[start..end]);
+                    }
+                }
+                PhysicalRecord::Fragment(RecordType::Last, start, end) => {
+                    if !in_fragmented_record {
+                        self.report_corruption(
+                            (end - start) as u64,
+                            "missing start of fragmented record(2)",
+                        );
+                    } else {
+                        record.extend_from_slice(&self.backing_store[start..end]);
+                        self.last_record_offset = prospective_record_offset;
+                        return true;
+                    }
+                }
+                PhysicalRecord::Eof => {
+                    if in_fragmented_record {
+                        // This can be caused by the writer dying immediately after
+                        // writing a physical record but before completing the next; don't
+                        // treat it as a corruption, just ignore the entire logical record.
+                        record.clear();
+                    }
+                    return false;
+                }
+                PhysicalRecord::BadRecord => {
+                    if in_fragmented_record {
+                        self.report_corruption(record.len() as u64, "error in middle of record");
+                        in_fragmented_record = false;
+                        record.clear();
+                    }
+                }
+                // `RecordType::from_u8` never yields a Zero fragment
+                PhysicalRecord::Fragment(RecordType::Zero, ..) => unreachable!(),
+                PhysicalRecord::Unknown(t, len) => {
+                    let dropped = len
+                        + if in_fragmented_record {
+                            record.len()
+                        } else {
+                            0
+                        };
+                    self.report_corruption(dropped as u64, &format!("unknown record type {}", t));
+                    in_fragmented_record = false;
+                    record.clear();
+                }
+            }
+        }
+    }
+
+    /// Returns the physical offset of the last record returned by `read_record`.
+    ///
+    /// Undefined before the first call to `read_record`.
+    pub fn last_record_offset(&self) -> u64 {
+        self.last_record_offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.file
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        self.buf_end - self.buf_start
+    }
+
+    // Skips all blocks that are completely before `initial_offset`.
+    //
+    // Returns true on success. Handles reporting.
+    fn skip_to_initial_block(&mut self) -> bool {
+        let offset_in_block = self.initial_offset % BLOCK_SIZE as u64;
+        let mut block_start_location = self.initial_offset - offset_in_block;
+
+        // Don't search a block if we'd be in the trailer
+        if offset_in_block > (BLOCK_SIZE - 6) as u64 {
+            block_start_location += BLOCK_SIZE as u64;
+        }
+
+        self.end_of_buffer_offset = block_start_location;
+
+        // Skip to start of first block that can contain the initial record
+        if block_start_location > 0 {
+            let skipped = io::copy(
+                &mut (&mut self.file).take(block_start_location),
+                &mut io::sink(),
+            );
+            match skipped {
+                Ok(n) if n == block_start_location => {}
+                Ok(_) => {
+                    // The file is shorter than `initial_offset`, nothing to read
+                    self.eof = true;
+                }
+                Err(e) => {
+                    self.report_drop(block_start_location, &Error::from(e));
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // Fills the backing store with at most one block. Returns the number of
+    // bytes read, which is only less than BLOCK_SIZE at the end of the file.
+    fn read_block(&mut self) -> io::Result<usize> {
+        let mut n = 0;
+        while n < BLOCK_SIZE {
+            match self.file.read(&mut self.backing_store[n..]) {
+                Ok(0) => break,
+                Ok(m) => n += m,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(n)
+    }
+
+    fn read_physical_record(&mut self) -> PhysicalRecord {
+        loop {
+            if self.buffer_len() < HEADER_SIZE {
+                if !self.eof {
+                    // Last read was a full read, so this is a trailer to skip
+                    self.buf_start = 0;
+                    self.buf_end = 0;
+                    match self.read_block() {
+                        Ok(n) => {
+                            self.buf_end = n;
+                            self.end_of_buffer_offset += n as u64;
+                            if n < BLOCK_SIZE {
+                                self.eof = true;
+                            }
+                        }
+                        Err(e) => {
+                            self.report_drop(BLOCK_SIZE as u64, &Error::from(e));
+                            self.eof = true;
+                            return PhysicalRecord::Eof;
+                        }
+                    }
+                    continue;
+                } else {
+                    // Note that if buffer is non-empty, we have a truncated header at the
+                    // end of the file, which can be caused by the writer crashing in the
+                    // middle of writing the header. Instead of considering this an error,
+                    // just report EOF.
+                    self.buf_start = self.buf_end;
+                    return PhysicalRecord::Eof;
+                }
+            }
+
+            // Parse the header
+            let header = &self.backing_store[self.buf_start..self.buf_start + HEADER_SIZE];
+            let a = header[4] as usize;
+            let b = header[5] as usize;
+            let record_type = header[6];
+            let length = a | (b << 8);
+            if HEADER_SIZE + length > self.buffer_len() {
+                let drop_size = self.buffer_len();
+                self.buf_start = self.buf_end;
+                if !self.eof {
+                    self.report_corruption(drop_size as u64, "bad record length");
+                    return PhysicalRecord::BadRecord;
+                }
+                // If the end of the file has been reached without reading |length| bytes
+                // of payload, assume the writer died in the middle of writing the record.
+                // Don't report a corruption.
+                return PhysicalRecord::Eof;
+            }
+
+            if record_type == RecordType::Zero as u8 && length == 0 {
+                // Skip zero length record without reporting any drops since
+                // such records are produced by the mmap based writing code
+                // that preallocates file regions.
+                self.buf_start = self.buf_end;
+                return PhysicalRecord::BadRecord;
+            }
+
+            // Check crc
+            if self.checksum {
+                let expected_crc = crc32c::unmask(decode_fixed_32(header));
+                let actual_crc = crc32c::value(
+                    &self.backing_store[self.buf_start + 6..self.buf_start + HEADER_SIZE + length],
+                );
+                if actual_crc != expected_crc {
+                    // Drop the rest of the buffer since "length" itself may have
+                    // been corrupted and if we trust it, we could find some
+                    // fragment of a real log record that just happens to look
+                    // like a valid log record.
+                    let drop_size = self.buffer_len();
+                    self.buf_start = self.buf_end;
+                    self.report_corruption(drop_size as u64, "checksum mismatch");
+                    return PhysicalRecord::BadRecord;
+                }
+            }
+
+            let start = self.buf_start + HEADER_SIZE;
+            self.buf_start += HEADER_SIZE + length;
+
+            // Skip physical record that started before initial_offset
+            if self.end_of_buffer_offset
+                - self.buffer_len() as u64
+                - HEADER_SIZE as u64
+                - (length as u64)
+                < self.initial_offset
+            {
+                return PhysicalRecord::BadRecord;
+            }
+
+            return match RecordType::from_u8(record_type) {
+                Some(t) => PhysicalRecord::Fragment(t, start, start + length),
+                None => PhysicalRecord::Unknown(record_type, length),
+            };
+        }
+    }
+
+    // Reports dropped bytes to the reporter.
+    fn report_corruption(&mut self, bytes: u64, reason: &str) {
+        self.report_drop(
+            bytes,
+            &Error::from(ErrorKind::Corruption(reason.to_owned())),
+        );
+    }
+
+    fn report_drop(&mut self, bytes: u64, reason: &Error) {
+        let start = self
+            .end_of_buffer_offset
+            .wrapping_sub(self.buffer_len() as u64)
+            .wrapping_sub(bytes);
+        if let Some(reporter) = self.reporter.as_mut() {
+            if start >= self.initial_offset {
+                reporter.corruption(bytes, reason);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::io::Write;
+
+use super::{RecordType, BLOCK_SIZE, HEADER_SIZE, MAX_RECORD_TYPE};
+use crate::util::{coding::encode_fixed_32, crc32c, status::Result};
+
+/// `Writer` appends records to a log file.
+///
+/// Records larger than the free space in the current block are split into
+/// FIRST/MIDDLE/LAST fragments, and a block trailer too small to hold a
+/// header is padded with zeroes.
+pub struct Writer<W: Write> {
+    dest: W,
+    // Current offset in block
+    block_offset: usize,
+    // crc32c values for all supported record types.  These are
+    // pre-computed to reduce the overhead of computing the crc of the
+    // record type stored in the header.
+    type_crc: [u32; MAX_RECORD_TYPE as usize + 1],
+}
+
+impl<W: Write> Writer<W> {
+    /// Create a writer that will append data to `dest`.
+    /// `dest` must be initially empty.
+    pub fn new(dest: W) -> Self {
+        Self::with_offset(dest, 0)
+    }
+
+    /// Create a writer that will append data to `dest`.
+    /// `dest` must have initial length `dest_length`.
+    pub fn with_offset(dest: W, dest_length: u64) -> Self {
+        let mut type_crc = [0; MAX_RECORD_TYPE as usize + 1];
+        for (t, crc) in type_crc.iter_mut().enumerate() {
+            *crc = crc32c::value(&[t as u8]);
+        }
+        Writer {
+            dest,
+            block_offset: (dest_length % BLOCK_SIZE as u64) as usize,
+            type_crc,
+        }
+    }
+
+    pub fn add_record(&mut self, record: &[u8]) -> Result<()> {
+        let mut left = record.len();
+        let mut ptr = 0;
+        // Fragment the record if necessary and emit it.  Note that if slice
+        // is empty, we still want to iterate once to emit a single
+        // zero-length record
+        let mut begin = true;
+        loop {
+            let leftover = BLOCK_SIZE - self.block_offset;
+            if leftover < HEADER_SIZE {
+                // Switch to a new block
+                if leftover > 0 {
+                    // Fill the trailer (literal below relies on HEADER_SIZE being 7)
+                    self.dest.write_all(&[0; HEADER_SIZE - 1][..leftover])?;
+                }
+                self.block_offset = 0;
+            }
+            // Invariant: we never leave < HEADER_SIZE bytes in a block.
+            assert!(BLOCK_SIZE - self.block_offset >= HEADER_SIZE);
+
+            let avail = BLOCK_SIZE - self.block_offset - HEADER_SIZE;
+            let fragment_length = usize::min(left, avail);
+            let end = left == fragment_length;
+            let record_type = match (begin, end) {
+                (true, true) => RecordType::Full,
+                (true, false) => RecordType::First,
+                (false, true) => RecordType::Last,
+                (false, false) => RecordType::Middle,
+            };
+            self.emit_physical_record(record_type, &record[ptr..ptr + fragment_length])?;
+            ptr += fragment_length;
+            left -= fragment_length;
+            begin = false;
+            if left == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Flush buffered records to the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.dest.flush()?;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.dest
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+
+    fn emit_physical_record(&mut self, t: RecordType, data: &[u8]) -> Result<()> {
+        let length = data.len();
+        assert!(length <= 0xffff, "must fit in two bytes");
+        assert!(self.block_offset + HEADER_SIZE + length <= BLOCK_SIZE);
+
+        // Format the header
+        let mut header = [0; HEADER_SIZE];
+        header[4] = (length & 0xff) as u8;
+        header[5] = (length >> 8) as u8;
+        header[6] = t as u8;
+
+        // Compute the crc of the record type and the payload.
+        let crc = crc32c::extend(self.type_crc[t as usize], data);
+        encode_fixed_32(&mut header, crc32c::mask(crc));
+
+        // Write the header and the payload
+        self.dest.write_all(&header)?;
+        self.dest.write_all(data)?;
+        self.dest.flush()?;
+        self.block_offset += HEADER_SIZE + length;
+        Ok(())
+    }
+}
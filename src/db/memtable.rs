@@ -8,10 +8,9 @@ use crate::db::inlineskiplist::{InlineSkipList, InlineSkiplistIterator};
 use crate::util::arena::{self, ArenaTrait, BlockArena, OffsetArena};
 use crate::util::coding::*;
 use crate::util::comparator::Comparator;
-use crate::util::status::Error;
+use crate::util::status::{Error, ErrorKind};
 
 use super::format::{InternalKey, InternalKeyComparator, LookUpKey, ValueType};
-use super::iterator::{self, LevedbIterator};
 use super::ldbiterator::LdbIterator;
 use super::skiplist::SkipListIterator;
 use super::SequenceNumber;
@@ -42,8 +41,8 @@ impl<C: Comparator + Clone> Comparator for KeyComparator<C> {
         let ib = extract_length_prefixed_slice(other);
         self.icmp.find_shortest_separator(start, other)
     }
-    fn name() -> String {
-        String::from("KeyComparator")
+    fn name(&self) -> &str {
+        "KeyComparator"
     }
 }
 
@@ -52,7 +51,7 @@ impl<C: Comparator + Clone> Comparator for KeyComparator<C> {
 //         KeyComparator { icmp: c }
 //     }
 // }
-struct MemTable<C: Comparator + Clone> {
+pub struct MemTable<C: Comparator + Clone> {
     key_comparator: KeyComparator<C>,
     refs: usize,
     table: InlineSkipList<KeyComparator<C>, OffsetArena>,
@@ -76,20 +75,23 @@ impl<C: Comparator + Clone> MemTable<C> {
     }
 
     pub fn unref(&mut self) {
+        assert!(self.refs > 0, "ref should > 0");
         self.refs -= 1;
-        assert!(self.refs >= 0, "ref should > 0");
-        if self.refs <= 0 {
+        if self.refs == 0 {
             std::mem::drop(self);
         }
     }
     pub fn iter(&self) -> MemTableIterator<C> {
         MemTableIterator::new(self.table.clone())
     }
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
     pub fn approximate_memory_usage(&self) -> usize {
         self.table.total_size()
     }
 
-    pub fn add(&mut self, s: SequenceNumber, valueType: ValueType, key: &[u8], value: &[u8]) {
+    pub fn add(&self, s: SequenceNumber, valueType: ValueType, key: &[u8], value: &[u8]) {
         // todo: use arena to allocate memory
         // Format of an entry is concatenation of:
         //  key_size     : varint32 of internal_key.size()
@@ -150,7 +152,9 @@ impl<C: Comparator + Clone> MemTable<C> {
                 std::cmp::Ordering::Equal => {
                     let tag = decode_fixed_64(&entry[ikey_len - 8..ikey_len]);
                     match ValueType::from(tag) {
-                        ValueType::KTypeDeletion => return Some(Err(Error::NotFound(None))),
+                        ValueType::KTypeDeletion => {
+                            return Some(Err(ErrorKind::NotFound(None).into()))
+                        }
                         ValueType::KTypeValue => {
                             return Some(Ok(get_length_prefixed_slice(val).unwrap().to_vec()))
                         }
@@ -208,8 +212,8 @@ impl<C: Comparator + Clone> LdbIterator for MemTableIterator<C> {
     fn prev(&mut self) {
         self.iter.prev();
     }
-    fn status(&self) {
-        unimplemented!()
+    fn status(&mut self) -> Result<(), Error> {
+        Ok(())
     }
 }
 #[cfg(test)]
@@ -279,7 +283,7 @@ mod tests {
             let pkey = ParsedInteralKey::decode_from(k).unwrap();
             assert_eq!(
                 pkey.user_key(),
-                *key,
+                key.as_bytes(),
                 "expected key: {:?}, but got {:?}",
                 *key,
                 pkey.user_key()
@@ -303,7 +307,7 @@ mod tests {
             let pkey = ParsedInteralKey::decode_from(k).unwrap();
             assert_eq!(
                 pkey.user_key(),
-                *key,
+                key.as_bytes(),
                 "expected key: {:?}, but got {:?}",
                 *key,
                 pkey.user_key()
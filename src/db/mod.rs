@@ -1,10 +1,25 @@
 // mod dbformat;
 mod block;
+mod builder;
+mod db_impl;
+pub mod db_iter;
+pub mod dumpfile;
+mod filename;
 mod format;
 mod inlineskiplist;
-mod iterator;
-mod ldbiterator;
+pub mod ldbiterator;
 mod ldbslice;
+mod log;
 mod memtable;
+pub mod repair;
 mod skiplist;
+pub mod snapshot;
+mod table;
+mod table_cache;
+mod version_edit;
+mod version_set;
+pub mod write_batch;
+pub use db_impl::DB;
+pub use table::BlockCache;
+
 pub type SequenceNumber = u64;
@@ -0,0 +1,618 @@
+use std::cell::Cell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::builder::build_table;
+use super::db_impl::{batch_arena_reserve, mem_arena_capacity};
+use super::filename::{generate_filename, parse_filename, set_current_file, FileType};
+use super::format::{InternalKey, InternalKeyComparator, ParsedInteralKey};
+use super::ldbiterator::LdbIterator;
+use super::log::{Reader, Reporter, Writer};
+use super::memtable::MemTable;
+use super::table::TableBuilder;
+use super::table_cache::{table_options, TableCache, TableOptions};
+use super::version_edit::{FileMetaData, VersionEdit};
+use super::write_batch::{WriteBatch, HEADER as WRITE_BATCH_HEADER};
+use super::SequenceNumber;
+use crate::env::{Env, WritableFile};
+use crate::options::{Options, ReadOptions};
+use crate::util::comparator::Comparator;
+use crate::util::status::{Error, ErrorKind, Result};
+
+/// What `repair_db` recovered, and what it had to drop
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Number of log files converted to tables
+    pub logs: usize,
+    /// Number of tables in the new MANIFEST
+    pub tables: usize,
+    /// Number of entries in those tables
+    pub entries: u64,
+    /// Bytes of log records dropped because they were corrupted
+    pub dropped_log_bytes: u64,
+    /// Entries of the log records and the tables that could not be
+    /// decoded.  The entries of the table blocks that could not be read
+    /// at all are not counted, as there is no telling how many there
+    /// were: the tables holding such blocks are in `lost_files`.
+    pub dropped_entries: u64,
+    /// Names of the files moved to `lost/` because they could not be read,
+    /// entirely or in part
+    pub lost_files: Vec<String>,
+}
+
+/// If a DB cannot be opened, you may attempt to call this method to
+/// resurrect as much of the contents of the database as possible.
+/// Some data may be lost, so be careful when calling this function
+/// on a database that contains important information.
+///
+/// The repair proceeds as follows:
+///
+/// 1. Every log file is converted into tables through a memtable.  The
+///    corrupted log records are dropped.
+/// 2. Every table is scanned to compute its smallest and largest keys
+///    and its largest sequence number.  A table that cannot be read in
+///    full is rewritten with the entries that could be read.
+/// 3. A fresh MANIFEST is written, holding all the tables at level 0.
+///    The compactions that follow the next open sort them out.
+///
+/// The files that cannot be read, the processed logs and the old
+/// MANIFESTs are moved to the `lost/` directory of the database rather
+/// than deleted.
+pub fn repair_db<P: AsRef<Path>, C: Comparator + Clone>(
+    dbname: P,
+    options: Options<C>,
+) -> Result<RepairReport> {
+    let dbname = dbname.as_ref();
+    let env = options.env.clone();
+    let lock = env.lock_file(&generate_filename(dbname, FileType::Lock, 0))?;
+    let result = Repairer::new(dbname, options).run();
+    let unlocked = env.unlock_file(lock);
+    // A failed repair is reported rather than a failed unlock
+    let report = result?;
+    unlocked?;
+    Ok(report)
+}
+
+// A table found or produced by the repair, with the metadata to put
+// into the new MANIFEST
+struct TableInfo {
+    meta: FileMetaData,
+    max_sequence: SequenceNumber,
+}
+
+struct Repairer<C: Comparator + Clone> {
+    dbname: PathBuf,
+    env: Arc<dyn Env>,
+    options: Options<C>,
+    icmp: InternalKeyComparator<C>,
+    table_options: TableOptions<C>,
+    table_cache: TableCache<C>,
+    next_file_number: u64,
+    report: RepairReport,
+}
+
+impl<C: Comparator + Clone> Repairer<C> {
+    fn new(dbname: &Path, options: Options<C>) -> Self {
+        let table_options = table_options(&options);
+        Repairer {
+            dbname: dbname.to_owned(),
+            env: options.env.clone(),
+            icmp: InternalKeyComparator::new(options.comparator.clone()),
+            table_cache: TableCache::new(dbname, table_options.clone()),
+            table_options,
+            options,
+            next_file_number: 2,
+            report: RepairReport::default(),
+        }
+    }
+
+    fn run(mut self) -> Result<RepairReport> {
+        let mut logs = vec![];
+        let mut table_numbers = vec![];
+        let mut manifests = vec![];
+        for name in self.env.get_children(&self.dbname)? {
+            if let Some((file_type, number)) = parse_filename(&name) {
+                match file_type {
+                    FileType::Descriptor => manifests.push(name),
+                    FileType::Log => logs.push(number),
+                    FileType::Table => table_numbers.push(number),
+                    // Ignore other files
+                    _ => continue,
+                }
+                self.next_file_number = self.next_file_number.max(number + 1);
+            }
+        }
+        if logs.is_empty() && table_numbers.is_empty() && manifests.is_empty() {
+            return Err(ErrorKind::InvalidArgument(format!(
+                "{}: repair found no files",
+                self.dbname.display()
+            ))
+            .into());
+        }
+
+        logs.sort_unstable();
+        for log in logs {
+            self.convert_log_to_tables(log, &mut table_numbers);
+        }
+        let mut tables = vec![];
+        for number in table_numbers {
+            if let Some(table) = self.scan_table(number) {
+                tables.push(table);
+            }
+        }
+        self.write_descriptor(&tables, &manifests)?;
+        self.report.tables = tables.len();
+        Ok(self.report)
+    }
+
+    // Replays the log numbered `number` into memtables that are written
+    // to new tables, added to `table_numbers`.  The log is archived
+    // afterwards: its contents are either in the tables or unreadable.
+    fn convert_log_to_tables(&mut self, number: u64, table_numbers: &mut Vec<u64>) {
+        let path = generate_filename(&self.dbname, FileType::Log, number);
+        let dropped_bytes = Rc::new(Cell::new(0));
+        let result = self.env.new_sequential_file(&path).and_then(|file| {
+            let reporter = DropCounter {
+                dropped_bytes: dropped_bytes.clone(),
+            };
+            // We intentionally make the log reader do checksumming so that
+            // corruptions cause entire commits to be skipped instead of
+            // propagating bad information (like overly large sequence
+            // numbers).
+            let mut reader = Reader::new(file, Some(Box::new(reporter)), true, 0);
+            let mut record = vec![];
+            let mut batch = WriteBatch::new();
+            let mut mem = self.new_memtable(0);
+            while reader.read_record(&mut record) {
+                if record.len() < WRITE_BATCH_HEADER {
+                    dropped_bytes.set(dropped_bytes.get() + record.len() as u64);
+                    continue;
+                }
+                batch.set_contents(&record);
+                let reserve = batch_arena_reserve(&batch);
+                if mem.approximate_memory_usage() > self.options.write_buffer_size
+                    || reserve > self.options.write_buffer_size
+                {
+                    let full = std::mem::replace(&mut mem, self.new_memtable(reserve));
+                    table_numbers.extend(self.write_table(&full)?);
+                }
+                if batch.insert_into(&mem).is_err() {
+                    self.report.dropped_entries += u64::from(batch.count());
+                }
+            }
+            table_numbers.extend(self.write_table(&mem)?);
+            Ok(())
+        });
+        self.report.dropped_log_bytes += dropped_bytes.get();
+        match result {
+            Ok(()) => {
+                self.report.logs += 1;
+                self.archive_file(&path);
+            }
+            Err(_) => self.lose_file(&path),
+        }
+    }
+
+    fn new_memtable(&self, reserve: usize) -> MemTable<C> {
+        MemTable::new(
+            self.icmp.clone(),
+            mem_arena_capacity(self.options.write_buffer_size.max(reserve)),
+        )
+    }
+
+    // Writes the contents of `mem` to a new table and returns its number,
+    // or `None` if `mem` is empty
+    fn write_table(&mut self, mem: &MemTable<C>) -> Result<Option<u64>> {
+        let mut meta = FileMetaData {
+            number: self.next_file_number,
+            ..FileMetaData::default()
+        };
+        self.next_file_number += 1;
+        build_table(
+            &self.dbname,
+            &self.table_options,
+            &self.table_cache,
+            mem.iter(),
+            &mut meta,
+        )?;
+        Ok(if meta.file_size > 0 {
+            Some(meta.number)
+        } else {
+            None
+        })
+    }
+
+    // Computes the metadata of the table numbered `number`.  A table that
+    // cannot be read in full is replaced by a copy of its readable entries.
+    fn scan_table(&mut self, number: u64) -> Option<TableInfo> {
+        let path = self.table_path(number);
+        let file_size = match self.env.get_file_size(&path) {
+            Ok(file_size) => file_size,
+            Err(_) => {
+                self.lose_file(&path);
+                return None;
+            }
+        };
+        let mut iter = match self
+            .table_cache
+            .new_iterator(self.scan_options(), number, file_size)
+        {
+            Ok(iter) => iter,
+            Err(_) => {
+                self.lose_file(&path);
+                return None;
+            }
+        };
+
+        let mut table = TableInfo {
+            meta: FileMetaData {
+                number,
+                file_size,
+                ..FileMetaData::default()
+            },
+            max_sequence: 0,
+        };
+        let mut entries = 0;
+        iter.seek_to_first();
+        while iter.valid() {
+            match ParsedInteralKey::decode_from(iter.key()) {
+                Some(parsed) => {
+                    if entries == 0 {
+                        table.meta.smallest = InternalKey::decode_from(iter.key());
+                    }
+                    table.meta.largest = InternalKey::decode_from(iter.key());
+                    table.max_sequence = table.max_sequence.max(parsed.sequence());
+                    entries += 1;
+                }
+                None => self.report.dropped_entries += 1,
+            }
+            iter.next();
+        }
+        if iter.status().is_err() {
+            drop(iter);
+            return self.repair_table(number, &path);
+        }
+        self.report.entries += entries;
+        if entries == 0 {
+            // An empty table holds nothing to recover
+            self.archive_file(&path);
+            return None;
+        }
+        Some(table)
+    }
+
+    // Copies the readable entries of the table numbered `number` into a
+    // new table, and archives the original one
+    fn repair_table(&mut self, number: u64, path: &Path) -> Option<TableInfo> {
+        let copy = TableInfo {
+            meta: FileMetaData {
+                number: self.next_file_number,
+                ..FileMetaData::default()
+            },
+            max_sequence: 0,
+        };
+        self.next_file_number += 1;
+        let copy_path = generate_filename(&self.dbname, FileType::Table, copy.meta.number);
+        let result = self.copy_table(number, &copy_path, copy);
+        self.table_cache.evict(number);
+        self.lose_file(path);
+        match result {
+            Ok(Some(copy)) => Some(copy),
+            _ => {
+                let _ = self.env.remove(&copy_path);
+                None
+            }
+        }
+    }
+
+    fn copy_table(
+        &mut self,
+        number: u64,
+        copy_path: &Path,
+        mut copy: TableInfo,
+    ) -> Result<Option<TableInfo>> {
+        let file_size = self.env.get_file_size(&self.table_path(number))?;
+        let mut iter = self
+            .table_cache
+            .new_iterator(self.scan_options(), number, file_size)?;
+        let file = self.env.new_writable_file(copy_path)?;
+        let mut builder = TableBuilder::new(self.table_options.clone(), file);
+        iter.seek_to_first();
+        while iter.valid() {
+            if let Some(parsed) = ParsedInteralKey::decode_from(iter.key()) {
+                if builder.num_entries() == 0 {
+                    copy.meta.smallest = InternalKey::decode_from(iter.key());
+                }
+                copy.meta.largest = InternalKey::decode_from(iter.key());
+                copy.max_sequence = copy.max_sequence.max(parsed.sequence());
+                builder.add(iter.key(), iter.value())?;
+            }
+            iter.next();
+        }
+        let entries = builder.num_entries();
+        builder.finish()?;
+        copy.meta.file_size = builder.file_size();
+        builder.into_inner().sync()?;
+        self.report.entries += entries;
+        Ok(if entries > 0 { Some(copy) } else { None })
+    }
+
+    // Writes a MANIFEST holding every table at level 0, which replaces
+    // the `manifests` found in the db
+    fn write_descriptor(&mut self, tables: &[TableInfo], manifests: &[String]) -> Result<()> {
+        let mut edit = VersionEdit::new();
+        edit.set_comparator_name(self.options.comparator.name());
+        edit.set_log_number(0);
+        edit.set_next_file(self.next_file_number);
+        edit.set_last_sequence(tables.iter().map(|t| t.max_sequence).max().unwrap_or(0));
+        for table in tables {
+            // TODO(opt): separate out into multiple levels
+            edit.add_file(
+                0,
+                table.meta.number,
+                table.meta.file_size,
+                table.meta.smallest.clone(),
+                table.meta.largest.clone(),
+            );
+        }
+
+        let tmp = generate_filename(&self.dbname, FileType::Temp, 1);
+        let result = self.env.new_writable_file(&tmp).and_then(|file| {
+            let mut log = Writer::new(file);
+            let mut record = vec![];
+            edit.encode_to(&mut record);
+            log.add_record(&record)?;
+            log.flush()?;
+            log.get_mut().sync()
+        });
+        if let Err(e) = result {
+            let _ = self.env.remove(&tmp);
+            return Err(e);
+        }
+
+        // Discard older manifests
+        for manifest in manifests {
+            self.archive_file(&self.dbname.join(manifest));
+        }
+
+        // Install new manifest
+        self.env.rename(
+            &tmp,
+            &generate_filename(&self.dbname, FileType::Descriptor, 1),
+        )?;
+        set_current_file(self.env.as_ref(), &self.dbname, 1)
+    }
+
+    // Tables may still carry the old-fashioned ".sst" name
+    fn table_path(&self, number: u64) -> PathBuf {
+        let path = generate_filename(&self.dbname, FileType::Table, number);
+        if self.env.file_exists(&path) {
+            return path;
+        }
+        self.dbname.join(format!("{:06}.sst", number))
+    }
+
+    // Verify the checksums so that the corrupted blocks are left out
+    fn scan_options(&self) -> ReadOptions<'static> {
+        ReadOptions {
+            verify_checksums: true,
+            fill_cache: false,
+            snapshot: None,
+        }
+    }
+
+    // Archives a file that could not be read in full and reports it
+    fn lose_file(&mut self, path: &Path) {
+        if let Some(name) = path.file_name() {
+            self.report
+                .lost_files
+                .push(name.to_string_lossy().into_owned());
+        }
+        self.archive_file(path);
+    }
+
+    // Moves `path` into the "lost" directory of the db rather than
+    // deleting it, in case its contents are useful to someone
+    fn archive_file(&self, path: &Path) {
+        let lost = self.dbname.join("lost");
+        // Ignore error in case directory already exists
+        let _ = self.env.create_dir(&lost);
+        if let Some(name) = path.file_name() {
+            let _ = self.env.rename(path, &lost.join(name));
+        }
+    }
+}
+
+// Counts the bytes a log reader drops
+struct DropCounter {
+    dropped_bytes: Rc<Cell<u64>>,
+}
+
+impl Reporter for DropCounter {
+    fn corruption(&mut self, bytes: u64, _reason: &Error) {
+        self.dropped_bytes.set(self.dropped_bytes.get() + bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DB;
+    use crate::env::MemEnv;
+    use crate::options::WriteOptions;
+    use crate::util::comparator::BytewiseComparator;
+    use std::io::Read;
+
+    fn new_options() -> Options<BytewiseComparator> {
+        Options {
+            create_if_missing: true,
+            env: Arc::new(MemEnv::default()),
+            write_buffer_size: 10000,
+            ..Options::default()
+        }
+    }
+
+    fn files_of_type(env: &dyn Env, dbname: &Path, file_type: FileType) -> Vec<u64> {
+        let mut files: Vec<u64> = env
+            .get_children(dbname)
+            .unwrap()
+            .iter()
+            .filter_map(|name| match parse_filename(name) {
+                Some((t, number)) if t == file_type => Some(number),
+                _ => None,
+            })
+            .collect();
+        files.sort_unstable();
+        files
+    }
+
+    // Fills a db with flushed tables and a log holding the latest writes
+    fn fill_db(dbname: &Path, options: &Options<BytewiseComparator>, n: usize) {
+        let db = DB::open(dbname, options.clone()).unwrap();
+        let value = "v".repeat(100);
+        for i in 0..n {
+            db.put(
+                WriteOptions::default(),
+                format!("key{:06}", i).as_bytes(),
+                value.as_bytes(),
+            )
+            .unwrap();
+        }
+        db.close().unwrap();
+    }
+
+    fn count_keys(dbname: &Path, options: &Options<BytewiseComparator>) -> usize {
+        let db = DB::open(dbname, options.clone()).unwrap();
+        let count = db
+            .new_iterator(ReadOptions::default())
+            .unwrap()
+            .entries()
+            .map(|entry| entry.unwrap())
+            .count();
+        count
+    }
+
+    fn remove_manifest(env: &dyn Env, dbname: &Path) {
+        for number in files_of_type(env, dbname, FileType::Descriptor) {
+            env.remove(&generate_filename(dbname, FileType::Descriptor, number))
+                .unwrap();
+        }
+        env.remove(&generate_filename(dbname, FileType::Current, 0))
+            .unwrap();
+    }
+
+    // Flips a byte of the file at `offset` from its end
+    fn corrupt_file(env: &dyn Env, path: &Path, offset_from_end: usize) {
+        let mut contents = vec![];
+        env.new_sequential_file(path)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        let pos = contents.len() - offset_from_end;
+        contents[pos] ^= 0xff;
+        let mut file = env.new_writable_file(path).unwrap();
+        file.write_all(&contents).unwrap();
+        file.sync().unwrap();
+    }
+
+    #[test]
+    fn test_repair_lost_manifest() {
+        let dbname = Path::new("/repair_lost_manifest");
+        let options = new_options();
+        let env = options.env.clone();
+        fill_db(dbname, &options, 500);
+        let tables = files_of_type(env.as_ref(), dbname, FileType::Table);
+        assert!(!tables.is_empty());
+        remove_manifest(env.as_ref(), dbname);
+
+        let report = repair_db(dbname, options.clone()).unwrap();
+        assert!(report.logs >= 1);
+        assert!(report.tables >= tables.len());
+        assert_eq!(report.entries, 500);
+        assert_eq!(report.dropped_log_bytes, 0);
+        assert_eq!(report.dropped_entries, 0);
+        assert!(report.lost_files.is_empty());
+        // The converted log is archived
+        assert!(files_of_type(env.as_ref(), dbname, FileType::Log).is_empty());
+        assert_eq!(
+            files_of_type(env.as_ref(), &dbname.join("lost"), FileType::Log).len(),
+            report.logs
+        );
+
+        assert_eq!(count_keys(dbname, &options), 500);
+        // New writes do not reuse the numbers of the recovered files
+        fill_db(dbname, &options, 600);
+        assert_eq!(count_keys(dbname, &options), 600);
+    }
+
+    #[test]
+    fn test_repair_corrupted_table() {
+        let dbname = Path::new("/repair_corrupted_table");
+        let options = new_options();
+        let env = options.env.clone();
+        fill_db(dbname, &options, 500);
+        // Break the first data block of a table, and the footer of another
+        let tables = files_of_type(env.as_ref(), dbname, FileType::Table);
+        assert!(tables.len() >= 2);
+        let broken_block = generate_filename(dbname, FileType::Table, tables[0]);
+        let broken_footer = generate_filename(dbname, FileType::Table, tables[1]);
+        let mut contents = vec![];
+        env.new_sequential_file(&broken_block)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        corrupt_file(env.as_ref(), &broken_block, contents.len() - 20);
+        corrupt_file(env.as_ref(), &broken_footer, 1);
+
+        let report = repair_db(dbname, options.clone()).unwrap();
+        let mut lost = report.lost_files.clone();
+        lost.sort();
+        assert_eq!(
+            lost,
+            vec![
+                format!("{:06}.ldb", tables[0]),
+                format!("{:06}.ldb", tables[1])
+            ]
+        );
+        for number in &tables[..2] {
+            assert!(env.file_exists(&dbname.join("lost").join(format!("{:06}.ldb", number))));
+        }
+        // The entries of the other blocks of the first table are kept
+        let count = count_keys(dbname, &options);
+        assert_eq!(count as u64, report.entries);
+        assert!(count < 500 && count > 0);
+        // The entries of the unreadable blocks are not counted as dropped
+        assert_eq!(report.dropped_entries, 0);
+    }
+
+    #[test]
+    fn test_repair_corrupted_log() {
+        let dbname = Path::new("/repair_corrupted_log");
+        let options = Options {
+            write_buffer_size: 1 << 20,
+            ..new_options()
+        };
+        let env = options.env.clone();
+        fill_db(dbname, &options, 100);
+        let log = files_of_type(env.as_ref(), dbname, FileType::Log)[0];
+        corrupt_file(
+            env.as_ref(),
+            &generate_filename(dbname, FileType::Log, log),
+            10,
+        );
+
+        let report = repair_db(dbname, options.clone()).unwrap();
+        assert!(report.dropped_log_bytes > 0);
+        assert!(report.entries > 0 && report.entries < 100);
+        assert_eq!(count_keys(dbname, &options) as u64, report.entries);
+    }
+
+    #[test]
+    fn test_repair_no_files() {
+        let options = new_options();
+        options.env.create_dir(Path::new("/empty")).unwrap();
+        assert!(repair_db("/empty", options).is_err());
+    }
+}
@@ -13,7 +13,7 @@ use std::ptr;
 use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use super::iterator::LevedbIterator;
+use super::ldbiterator::LdbIterator;
 use crate::util::comparator::Comparator;
 
 pub const MAX_HEIGHT: usize = 12;
@@ -49,35 +49,23 @@ impl Node {
     }
 
     fn next(&self, height: usize) -> *mut Node {
-        unsafe {
-            self.next_nodes
-                .get_unchecked(height - 1)
-                .load(Ordering::Acquire)
-        }
+        unsafe { (*self.next_nodes.as_ptr().add(height - 1)).load(Ordering::Acquire) }
     }
 
     fn set_next(&self, height: usize, node: *mut Node) {
         unsafe {
             // self.next_nodes[height].store(node, Ordering::Release);
-            self.next_nodes
-                .get_unchecked(height - 1)
-                .store(node, Ordering::Release);
+            (*self.next_nodes.as_ptr().add(height - 1)).store(node, Ordering::Release);
         }
     }
 
     fn no_barrier_next(&self, height: usize) -> *mut Node {
         assert!(height > 0);
-        unsafe {
-            self.next_nodes
-                .get_unchecked(height - 1)
-                .load(Ordering::Relaxed)
-        }
+        unsafe { (*self.next_nodes.as_ptr().add(height - 1)).load(Ordering::Relaxed) }
     }
     fn no_barrier_set_next(&self, height: usize, node: *mut Node) {
         unsafe {
-            self.next_nodes
-                .get_unchecked(height - 1)
-                .store(node, Ordering::Relaxed);
+            (*self.next_nodes.as_ptr().add(height - 1)).store(node, Ordering::Relaxed);
         }
     }
     fn key(&self) -> &[u8] {
@@ -217,7 +205,6 @@ impl<C: Comparator, A: ArenaTrait> SkipList<C, A> {
     // Return true if key is greater than the data stored in "n"
     fn key_is_after_node(&self, key: &[u8], node: *mut Node) -> bool {
         unsafe {
-            println!("{:?}", (*node).key());
             !node.is_null()
                 && self.compare.compare((*node).key(), key.as_ref()) == std::cmp::Ordering::Less
         }
@@ -280,7 +267,7 @@ impl<C: Comparator, A: ArenaTrait> SkipListIterator<C, A> {
     }
 }
 
-impl<C: Comparator, A: ArenaTrait> LevedbIterator for SkipListIterator<C, A> {
+impl<C: Comparator, A: ArenaTrait> LdbIterator for SkipListIterator<C, A> {
     #[inline]
     fn valid(&self) -> bool {
         !self.node.is_null()
@@ -297,12 +284,7 @@ impl<C: Comparator, A: ArenaTrait> LevedbIterator for SkipListIterator<C, A> {
     }
     #[inline]
     fn seek_to_first(&mut self) {
-        self.node = unsafe {
-            (*(self.skl.head))
-                .next_nodes
-                .get_unchecked(0)
-                .load(Ordering::Acquire)
-        };
+        self.node = unsafe { (*(*(self.skl.head)).next_nodes.as_ptr()).load(Ordering::Acquire) };
     }
     #[inline]
     fn seek_to_last(&mut self) {
@@ -312,11 +294,7 @@ impl<C: Comparator, A: ArenaTrait> LevedbIterator for SkipListIterator<C, A> {
     fn prev(&mut self) {
         // self.panic_valid();
         let key = self.key();
-        println!("key :{:?}", std::str::from_utf8(key));
         self.node = self.skl.find_less_than(key);
-        unsafe {
-            println!("{:?}", (*self.node).key());
-        }
         if self.node == self.skl.head {
             self.node = ptr::null_mut();
         }
@@ -324,11 +302,12 @@ impl<C: Comparator, A: ArenaTrait> LevedbIterator for SkipListIterator<C, A> {
     fn key(&self) -> &[u8] {
         unsafe { (*self.node).key().as_ref() }
     }
+    // The nodes only hold a key
     fn value(&self) -> &[u8] {
-        unimplemented!()
+        &[]
     }
 
-    fn status(&mut self) -> Result<(), std::fmt::Error> {
+    fn status(&mut self) -> crate::util::status::Result<()> {
         Ok(())
     }
 }
@@ -336,7 +315,7 @@ impl<C: Comparator, A: ArenaTrait> LevedbIterator for SkipListIterator<C, A> {
 #[cfg(test)]
 mod tests {
     use super::{random_height, Bytes, Node, Ordering, SkipList, SkipListIterator, MAX_HEIGHT};
-    use crate::db::iterator::LevedbIterator;
+    use crate::db::ldbiterator::LdbIterator;
     use crate::util::arena::BlockArena;
     use crate::util::comparator::BytewiseComparator;
     use std::borrow::BorrowMut;
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use super::SequenceNumber;
+
+/// Abstract handle to particular state of a DB.
+/// A `Snapshot` is an immutable object and can therefore be safely
+/// accessed from multiple threads without any external synchronization.
+/// It can't be copied: `DB::release_snapshot` takes it back, so a
+/// released snapshot can't be read from or released again.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    sequence: SequenceNumber,
+}
+
+impl Snapshot {
+    /// Return the sequence number of the last write visible to this snapshot
+    pub fn sequence(&self) -> SequenceNumber {
+        self.sequence
+    }
+}
+
+/// The snapshots handed out by a DB and not released yet.  Several
+/// snapshots may share a sequence number, so the list counts them.
+#[derive(Default)]
+pub struct SnapshotList {
+    snapshots: BTreeMap<SequenceNumber, usize>,
+}
+
+impl SnapshotList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Return the sequence number of the oldest live snapshot
+    pub fn oldest(&self) -> Option<SequenceNumber> {
+        self.snapshots.keys().next().cloned()
+    }
+
+    /// Return the sequence number of the newest live snapshot
+    pub fn newest(&self) -> Option<SequenceNumber> {
+        self.snapshots.keys().next_back().cloned()
+    }
+
+    /// Create a snapshot of the writes up to `sequence`.
+    /// REQUIRES: `sequence` is at least as large as the sequence of every
+    /// live snapshot
+    pub fn acquire(&mut self, sequence: SequenceNumber) -> Snapshot {
+        assert!(self.newest().map_or(true, |newest| newest <= sequence));
+        *self.snapshots.entry(sequence).or_insert(0) += 1;
+        Snapshot { sequence }
+    }
+
+    /// Release a snapshot created by `acquire`.
+    /// REQUIRES: `snapshot` has not been released yet
+    pub fn release(&mut self, snapshot: Snapshot) {
+        let count = self
+            .snapshots
+            .get_mut(&snapshot.sequence)
+            .expect("snapshot released twice");
+        *count -= 1;
+        if *count == 0 {
+            self.snapshots.remove(&snapshot.sequence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_list() {
+        let mut list = SnapshotList::new();
+        assert!(list.is_empty());
+        assert_eq!(list.oldest(), None);
+        let s1 = list.acquire(10);
+        let s2 = list.acquire(10);
+        let s3 = list.acquire(20);
+        assert_eq!(s1.sequence(), 10);
+        assert_eq!(list.oldest(), Some(10));
+        assert_eq!(list.newest(), Some(20));
+        list.release(s1);
+        assert_eq!(list.oldest(), Some(10));
+        list.release(s2);
+        assert_eq!(list.oldest(), Some(20));
+        list.release(s3);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "released twice")]
+    fn test_release_twice() {
+        let mut list = SnapshotList::new();
+        let s = list.acquire(1);
+        list.release(s);
+        list.release(Snapshot { sequence: 1 });
+    }
+}
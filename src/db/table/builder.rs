@@ -0,0 +1,505 @@
+use std::cmp::Ordering;
+use std::io::Write;
+
+use super::filter_block::FilterBlockBuilder;
+use super::format::{BlockHandle, Footer, BLOCK_TRAILER_SIZE};
+use crate::db::block::BlockBuilder;
+use crate::options::{CompressionType, Options};
+use crate::util::{
+    coding::put_fixed_32,
+    comparator::{BytewiseComparator, Comparator},
+    compression::CompressorRegistry,
+    crc32c,
+    status::Result,
+};
+
+/// `TableBuilder` provides the interface used to build a Table
+/// (an immutable and sorted map from keys to values).
+///
+/// A table file has the form:
+///
+/// ```text
+/// <beginning_of_file>
+/// [data block 1]
+/// [data block 2]
+/// ...
+/// [data block N]
+/// [meta block 1]
+/// ...
+/// [meta block K]
+/// [metaindex block]
+/// [index block]
+/// [Footer]        (fixed size; starts at file_size - FOOTER_ENCODED_LENGTH)
+/// <end_of_file>
+/// ```
+pub struct TableBuilder<C: Comparator + Clone, W: Write> {
+    options: Options<C>,
+    file: W,
+    offset: u64,
+    data_block: BlockBuilder<C>,
+    index_block: BlockBuilder<C>,
+    last_key: Vec<u8>,
+    num_entries: u64,
+    // Either `finish()` or `abandon()` has been called.
+    closed: bool,
+    filter_block: Option<FilterBlockBuilder>,
+
+    // We do not emit the index entry for a block until we have seen the
+    // first key for the next data block.  This allows us to use shorter
+    // keys in the index block.  For example, consider a block boundary
+    // between the keys "the quick brown fox" and "the who".  We can use
+    // "the r" as the key for the index block entry since it is >= all
+    // entries in the first block and < all entries in subsequent
+    // blocks.
+    //
+    // Invariant: pending_index_entry is true only if data_block is empty.
+    pending_index_entry: bool,
+    // Handle to add to index block
+    pending_handle: BlockHandle,
+}
+
+impl<C: Comparator + Clone, W: Write> TableBuilder<C, W> {
+    /// Create a builder that will store the contents of the table it is
+    /// building in `file`.  Does not close the file.  It is up to the
+    /// caller to close the file after calling `finish()`.
+    pub fn new(options: Options<C>, file: W) -> Self {
+        let data_block =
+            BlockBuilder::new(options.block_restart_interval, options.comparator.clone());
+        // Index blocks are searched by binary search only, so every entry
+        // is a restart point
+        let index_block = BlockBuilder::new(1, options.comparator.clone());
+        let filter_block = options.filter_policy.clone().map(|policy| {
+            let mut builder = FilterBlockBuilder::new(policy);
+            builder.start_block(0);
+            builder
+        });
+        TableBuilder {
+            options,
+            file,
+            offset: 0,
+            data_block,
+            index_block,
+            last_key: vec![],
+            num_entries: 0,
+            closed: false,
+            filter_block,
+            pending_index_entry: false,
+            pending_handle: BlockHandle::default(),
+        }
+    }
+
+    /// Add key,value to the table being constructed.
+    /// REQUIRES: key is after any previously added key according to comparator.
+    /// REQUIRES: `finish()`, `abandon()` have not been called
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        assert!(!self.closed);
+        if self.num_entries > 0 {
+            assert_eq!(
+                self.options.comparator.compare(key, &self.last_key),
+                Ordering::Greater,
+                "[table builder] keys must be added in strictly increasing order"
+            );
+        }
+
+        if self.pending_index_entry {
+            assert!(self.data_block.is_empty());
+            let separator = self
+                .options
+                .comparator
+                .find_shortest_separator(&self.last_key, key);
+            self.index_block
+                .add(&separator, &self.pending_handle.encoded());
+            self.pending_index_entry = false;
+        }
+
+        if let Some(filter_block) = self.filter_block.as_mut() {
+            filter_block.add_key(key);
+        }
+
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.num_entries += 1;
+        self.data_block.add(key, value);
+
+        if self.data_block.current_size_estimate() >= self.options.block_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Advanced operation: flush any buffered key/value pairs to file.
+    /// Can be used to ensure that two adjacent entries never live in
+    /// the same data block.  Most clients should not need to use this method.
+    /// REQUIRES: `finish()`, `abandon()` have not been called
+    pub fn flush(&mut self) -> Result<()> {
+        assert!(!self.closed);
+        if self.data_block.is_empty() {
+            return Ok(());
+        }
+        assert!(!self.pending_index_entry);
+        let contents = self.data_block.finish();
+        self.pending_handle = write_block(
+            &mut self.file,
+            &mut self.offset,
+            contents,
+            self.options.compression,
+            &self.options.compressors,
+        )?;
+        self.data_block.reset();
+        self.pending_index_entry = true;
+        self.file.flush()?;
+        if let Some(filter_block) = self.filter_block.as_mut() {
+            filter_block.start_block(self.offset);
+        }
+        Ok(())
+    }
+
+    /// Finish building the table.  Stops using the file passed to the
+    /// constructor after this function returns.
+    /// REQUIRES: `finish()`, `abandon()` have not been called
+    pub fn finish(&mut self) -> Result<()> {
+        self.flush()?;
+        self.closed = true;
+
+        // Write filter block
+        let mut filter_block_handle = None;
+        if let Some(filter_block) = self.filter_block.as_mut() {
+            filter_block_handle = Some(write_raw_block(
+                &mut self.file,
+                &mut self.offset,
+                filter_block.finish(),
+                CompressionType::NoCompression,
+            )?);
+        }
+
+        // Write metaindex block
+        let mut meta_index_block = BlockBuilder::new(
+            self.options.block_restart_interval,
+            BytewiseComparator::default(),
+        );
+        if let (Some(policy), Some(handle)) = (&self.options.filter_policy, filter_block_handle) {
+            // Add mapping from "filter.Name" to location of filter data
+            let key = format!("filter.{}", policy.name());
+            meta_index_block.add(key.as_bytes(), &handle.encoded());
+        }
+        let metaindex_handle = write_block(
+            &mut self.file,
+            &mut self.offset,
+            meta_index_block.finish(),
+            self.options.compression,
+            &self.options.compressors,
+        )?;
+
+        // Write index block
+        if self.pending_index_entry {
+            let successor = self.options.comparator.find_short_successor(&self.last_key);
+            self.index_block
+                .add(&successor, &self.pending_handle.encoded());
+            self.pending_index_entry = false;
+        }
+        let index_handle = write_block(
+            &mut self.file,
+            &mut self.offset,
+            self.index_block.finish(),
+            self.options.compression,
+            &self.options.compressors,
+        )?;
+
+        // Write footer
+        let mut footer_encoding = vec![];
+        Footer::new(metaindex_handle, index_handle).encode_to(&mut footer_encoding);
+        self.file.write_all(&footer_encoding)?;
+        self.offset += footer_encoding.len() as u64;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Indicate that the contents of this builder should be abandoned.  Stops
+    /// using the file passed to the constructor after this function returns.
+    /// If the caller is not going to call `finish()`, it must call `abandon()`
+    /// before destroying this builder.
+    /// REQUIRES: `finish()`, `abandon()` have not been called
+    pub fn abandon(&mut self) {
+        assert!(!self.closed);
+        self.closed = true;
+    }
+
+    /// Number of calls to `add()` so far.
+    pub fn num_entries(&self) -> u64 {
+        self.num_entries
+    }
+
+    /// Size of the file generated so far.  If invoked after a successful
+    /// `finish()` call, returns the size of the final generated file.
+    pub fn file_size(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.file
+    }
+
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+// Writes the block contents compressed with `compression`.  The
+// contents are stored raw if the compressed form is not at least 12.5%
+// smaller, if the codec fails, or if no codec is registered for
+// `compression`.
+fn write_block<W: Write>(
+    file: &mut W,
+    offset: &mut u64,
+    contents: &[u8],
+    compression: CompressionType,
+    compressors: &CompressorRegistry,
+) -> Result<BlockHandle> {
+    if let Some(compressor) = compressors.get(compression) {
+        if let Ok(compressed) = compressor.compress(contents) {
+            if compressed.len() < contents.len() - contents.len() / 8 {
+                return write_raw_block(file, offset, &compressed, compression);
+            }
+        }
+    }
+    write_raw_block(file, offset, contents, CompressionType::NoCompression)
+}
+
+// Writes the block contents followed by its trailer and returns the
+// handle of the block.  The trailer is the compression type plus the
+// masked crc32c of the contents and the type.
+fn write_raw_block<W: Write>(
+    file: &mut W,
+    offset: &mut u64,
+    contents: &[u8],
+    compression: CompressionType,
+) -> Result<BlockHandle> {
+    let handle = BlockHandle::new(*offset, contents.len() as u64);
+    file.write_all(contents)?;
+    let mut trailer = Vec::with_capacity(BLOCK_TRAILER_SIZE);
+    trailer.push(compression.to_u8());
+    let crc = crc32c::extend(crc32c::value(contents), &trailer);
+    put_fixed_32(&mut trailer, crc32c::mask(crc));
+    file.write_all(&trailer)?;
+    *offset += (contents.len() + BLOCK_TRAILER_SIZE) as u64;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::db::block::Block;
+    use crate::db::ldbiterator::LdbIterator;
+    use crate::db::table::format::{FOOTER_ENCODED_LENGTH, TABLE_MAGIC_NUMBER};
+    use crate::util::coding::{decode_fixed_32, decode_fixed_64};
+    use crate::util::comparator::BytewiseComparator;
+    use crate::util::compression::{Compressor, SnappyCompressor};
+    use crate::util::status::{Error, ErrorKind};
+
+    fn new_options(block_size: usize) -> Options<BytewiseComparator> {
+        Options {
+            block_size,
+            ..Options::default()
+        }
+    }
+
+    // Reads the block pointed by `handle` and checks its trailer
+    fn read_block(data: &[u8], handle: &BlockHandle) -> Block {
+        let start = handle.offset() as usize;
+        let end = start + handle.size() as usize;
+        let trailer = &data[end..end + BLOCK_TRAILER_SIZE];
+        let crc = crc32c::unmask(decode_fixed_32(&trailer[1..]));
+        assert_eq!(crc, crc32c::value(&data[start..end + 1]));
+        let contents = match CompressionType::from_u8(trailer[0]) {
+            CompressionType::NoCompression => data[start..end].to_vec(),
+            CompressionType::SnappyCompression => {
+                SnappyCompressor.decompress(&data[start..end]).unwrap()
+            }
+            CompressionType::Custom(id) => panic!("unexpected compression type {}", id),
+        };
+        Block::new(contents).unwrap()
+    }
+
+    // Returns the compression type of every data block of the table
+    fn data_block_types(data: &[u8]) -> Vec<u8> {
+        let footer = Footer::decode_from(&data[data.len() - FOOTER_ENCODED_LENGTH..]).unwrap();
+        let index_block = read_block(data, &footer.index_handle);
+        let mut iter = index_block.iter(BytewiseComparator::default());
+        iter.seek_to_first();
+        let mut types = vec![];
+        while iter.valid() {
+            let (handle, _) = BlockHandle::decode_from(iter.value()).unwrap();
+            types.push(data[(handle.offset() + handle.size()) as usize]);
+            iter.next();
+        }
+        types
+    }
+
+    #[test]
+    fn test_empty_table() {
+        let mut builder = TableBuilder::new(new_options(4096), vec![]);
+        builder.finish().unwrap();
+        assert_eq!(builder.num_entries(), 0);
+        let data = builder.into_inner();
+        // An empty metaindex block, an empty index block and the footer
+        assert_eq!(
+            data.len(),
+            2 * (8 + BLOCK_TRAILER_SIZE) + FOOTER_ENCODED_LENGTH
+        );
+        assert_eq!(decode_fixed_64(&data[data.len() - 8..]), TABLE_MAGIC_NUMBER);
+    }
+
+    #[test]
+    fn test_build_table() {
+        let mut builder = TableBuilder::new(new_options(128), vec![]);
+        let n = 1000;
+        let entries: Vec<(String, String)> = (0..n)
+            .map(|i| (format!("key{:06}", i), format!("value{}", i)))
+            .collect();
+        for (k, v) in entries.iter() {
+            builder.add(k.as_bytes(), v.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        assert_eq!(builder.num_entries(), n);
+        let size = builder.file_size();
+        let data = builder.into_inner();
+        assert_eq!(size, data.len() as u64);
+
+        let footer = Footer::decode_from(&data[data.len() - FOOTER_ENCODED_LENGTH..]).unwrap();
+        let index_block = read_block(&data, &footer.index_handle);
+        let mut index_iter = index_block.iter(BytewiseComparator::default());
+        index_iter.seek_to_first();
+        let mut expected = entries.iter();
+        let mut last_separator: Option<Vec<u8>> = None;
+        let mut blocks = 0;
+        while index_iter.valid() {
+            let (handle, _) = BlockHandle::decode_from(index_iter.value()).unwrap();
+            let separator = index_iter.key().to_vec();
+            let block = read_block(&data, &handle);
+            let mut iter = block.iter(BytewiseComparator::default());
+            iter.seek_to_first();
+            while iter.valid() {
+                let (k, v) = expected.next().unwrap();
+                assert_eq!(iter.key(), k.as_bytes());
+                assert_eq!(iter.value(), v.as_bytes());
+                // Every key in the block is <= separator and > the previous one
+                assert!(iter.key() <= separator.as_slice());
+                if let Some(last) = &last_separator {
+                    assert!(iter.key() > last.as_slice());
+                }
+                iter.next();
+            }
+            last_separator = Some(separator);
+            blocks += 1;
+            index_iter.next();
+        }
+        assert!(expected.next().is_none());
+        assert!(blocks > 1);
+    }
+
+    #[test]
+    fn test_compression() {
+        let build = |compression, value: &dyn Fn(u32) -> Vec<u8>| {
+            let options = Options {
+                compression,
+                ..new_options(1024)
+            };
+            let mut builder = TableBuilder::new(options, vec![]);
+            for i in 0..100 {
+                builder
+                    .add(format!("key{:06}", i).as_bytes(), &value(i))
+                    .unwrap();
+            }
+            builder.finish().unwrap();
+            builder.into_inner()
+        };
+        let compressible = |_| vec![b'x'; 100];
+        // A xorshift sequence, which snappy cannot shrink
+        let random = |i: u32| {
+            let mut x = i.wrapping_mul(2_654_435_761) | 1;
+            (0..100)
+                .map(|_| {
+                    x ^= x << 13;
+                    x ^= x >> 17;
+                    x ^= x << 5;
+                    x as u8
+                })
+                .collect()
+        };
+
+        let snappy = CompressionType::SnappyCompression.to_u8();
+        let raw = CompressionType::NoCompression.to_u8();
+        let compressed = build(CompressionType::SnappyCompression, &compressible);
+        assert!(data_block_types(&compressed).iter().all(|&t| t == snappy));
+        let uncompressed = build(CompressionType::NoCompression, &compressible);
+        assert!(data_block_types(&uncompressed).iter().all(|&t| t == raw));
+        assert!(compressed.len() < uncompressed.len() / 4);
+        // Blocks that do not shrink enough are stored raw
+        let incompressible = build(CompressionType::SnappyCompression, &random);
+        assert!(data_block_types(&incompressible).iter().all(|&t| t == raw));
+    }
+
+    struct FailingCompressor;
+
+    impl Compressor for FailingCompressor {
+        fn compression_type(&self) -> CompressionType {
+            CompressionType::Custom(9)
+        }
+
+        fn compress(&self, _: &[u8]) -> Result<Vec<u8>> {
+            Err(ErrorKind::Corruption("compress".to_owned()).into())
+        }
+
+        fn decompress(&self, _: &[u8]) -> Result<Vec<u8>> {
+            Err(ErrorKind::Corruption("decompress".to_owned()).into())
+        }
+    }
+
+    #[test]
+    fn test_compression_failure() {
+        let mut options = Options {
+            compression: CompressionType::Custom(9),
+            ..new_options(1024)
+        };
+        options.compressors.register(Arc::new(FailingCompressor));
+        let mut builder = TableBuilder::new(options, vec![]);
+        for i in 0..100 {
+            builder
+                .add(format!("key{:06}", i).as_bytes(), &[b'x'; 100])
+                .unwrap();
+        }
+        builder.finish().unwrap();
+        // The blocks the codec fails on are stored raw
+        let data = builder.into_inner();
+        let raw = CompressionType::NoCompression.to_u8();
+        assert!(data_block_types(&data).iter().all(|&t| t == raw));
+    }
+
+    #[test]
+    fn test_short_separators() {
+        let mut builder = TableBuilder::new(new_options(1), vec![]);
+        builder.add(b"the quick brown fox", b"").unwrap();
+        builder.add(b"the who", b"").unwrap();
+        builder.finish().unwrap();
+        let data = builder.into_inner();
+        let footer = Footer::decode_from(&data[data.len() - FOOTER_ENCODED_LENGTH..]).unwrap();
+        let index_block = read_block(&data, &footer.index_handle);
+        let mut iter = index_block.iter(BytewiseComparator::default());
+        iter.seek_to_first();
+        assert_eq!(iter.key(), b"the r");
+        iter.next();
+        assert_eq!(iter.key(), b"u");
+        iter.next();
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_add_out_of_order() {
+        let mut builder = TableBuilder::new(new_options(4096), vec![]);
+        builder.add(b"b", b"").unwrap();
+        builder.add(b"a", b"").unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+// A filter block is stored near the end of a Table file.  It contains
+// filters (e.g., bloom filters) for all data blocks in the table combined
+// into a single filter block.
+//
+// The filter block has the form:
+//
+//     [filter 0]
+//     [filter 1]
+//     ...
+//     [filter N-1]
+//     [offset of filter 0]                  : 4 bytes
+//     [offset of filter 1]                  : 4 bytes
+//     ...
+//     [offset of filter N-1]                : 4 bytes
+//     [offset of beginning of offset array] : 4 bytes
+//     lg(base)                              : 1 byte
+//
+// Filter i covers the data blocks whose file offset is within
+// [i * base, (i + 1) * base).
+
+use std::sync::Arc;
+
+use crate::util::{
+    coding::{decode_fixed_32, put_fixed_32},
+    filter_policy::FilterPolicy,
+};
+
+// Generate new filter every 2KB of data
+const FILTER_BASE_LG: u8 = 11;
+const FILTER_BASE: u64 = 1 << FILTER_BASE_LG;
+
+/// A `FilterBlockBuilder` is used to construct all of the filters for a
+/// particular Table.  It generates a single string which is stored as
+/// a special block in the Table.
+///
+/// The sequence of calls to `FilterBlockBuilder` must match the regexp:
+///      (start_block add_key*)* finish
+pub struct FilterBlockBuilder {
+    policy: Arc<dyn FilterPolicy>,
+    // Flattened key contents
+    keys: Vec<u8>,
+    // Starting index in keys of each key
+    start: Vec<usize>,
+    // Filter data computed so far
+    result: Vec<u8>,
+    filter_offsets: Vec<u32>,
+}
+
+impl FilterBlockBuilder {
+    pub fn new(policy: Arc<dyn FilterPolicy>) -> Self {
+        FilterBlockBuilder {
+            policy,
+            keys: vec![],
+            start: vec![],
+            result: vec![],
+            filter_offsets: vec![],
+        }
+    }
+
+    pub fn start_block(&mut self, block_offset: u64) {
+        let filter_index = block_offset / FILTER_BASE;
+        assert!(filter_index >= self.filter_offsets.len() as u64);
+        while filter_index > self.filter_offsets.len() as u64 {
+            self.generate_filter();
+        }
+    }
+
+    pub fn add_key(&mut self, key: &[u8]) {
+        self.start.push(self.keys.len());
+        self.keys.extend_from_slice(key);
+    }
+
+    pub fn finish(&mut self) -> &[u8] {
+        if !self.start.is_empty() {
+            self.generate_filter();
+        }
+
+        // Append array of per-filter offsets
+        let array_offset = self.result.len() as u32;
+        for offset in self.filter_offsets.iter() {
+            put_fixed_32(&mut self.result, *offset);
+        }
+        put_fixed_32(&mut self.result, array_offset);
+        self.result.push(FILTER_BASE_LG); // Save encoding parameter in result
+        &self.result
+    }
+
+    fn generate_filter(&mut self) {
+        let num_keys = self.start.len();
+        if num_keys == 0 {
+            // Fast path if there are no keys for this filter
+            self.filter_offsets.push(self.result.len() as u32);
+            return;
+        }
+
+        // Make list of keys from flattened key structure
+        self.start.push(self.keys.len()); // Simplify length computation
+        let keys = &self.keys;
+        let tmp_keys: Vec<&[u8]> = self.start.windows(2).map(|w| &keys[w[0]..w[1]]).collect();
+
+        // Generate filter for current set of keys and append to result.
+        self.filter_offsets.push(self.result.len() as u32);
+        self.policy.create_filter(&tmp_keys, &mut self.result);
+
+        self.keys.clear();
+        self.start.clear();
+    }
+}
+
+/// `FilterBlockReader` checks keys against the filter block of a Table.
+pub struct FilterBlockReader {
+    policy: Arc<dyn FilterPolicy>,
+    // Filter data
+    data: Vec<u8>,
+    // Beginning of offset array (at block-end)
+    offset: usize,
+    // Number of entries in offset array
+    num: usize,
+    // Encoding parameter (see FILTER_BASE_LG)
+    base_lg: u8,
+}
+
+impl FilterBlockReader {
+    /// REQUIRES: `contents` is the result of `FilterBlockBuilder::finish()`
+    pub fn new(policy: Arc<dyn FilterPolicy>, contents: Vec<u8>) -> Self {
+        let mut reader = FilterBlockReader {
+            policy,
+            data: vec![],
+            offset: 0,
+            num: 0,
+            base_lg: 0,
+        };
+        let n = contents.len();
+        if n < 5 {
+            // 1 byte for base_lg and 4 for start of offset array
+            return reader;
+        }
+        let last_word = decode_fixed_32(&contents[n - 5..]) as usize;
+        if last_word > n - 5 {
+            return reader;
+        }
+        reader.base_lg = contents[n - 1];
+        reader.offset = last_word;
+        reader.num = (n - 5 - last_word) / 4;
+        reader.data = contents;
+        reader
+    }
+
+    pub fn key_may_match(&self, block_offset: u64, key: &[u8]) -> bool {
+        let index = (block_offset >> self.base_lg) as usize;
+        if index < self.num {
+            let start = decode_fixed_32(&self.data[self.offset + index * 4..]) as usize;
+            let limit = decode_fixed_32(&self.data[self.offset + index * 4 + 4..]) as usize;
+            if start <= limit && limit <= self.offset {
+                let filter = &self.data[start..limit];
+                return self.policy.key_may_match(key, filter);
+            } else if start == limit {
+                // Empty filters do not match any keys
+                return false;
+            }
+        }
+        true // Errors are treated as potential matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hash::hash;
+
+    // For testing: emit an array with one hash value per key
+    struct TestHashFilter;
+
+    impl FilterPolicy for TestHashFilter {
+        fn name(&self) -> &str {
+            "TestHashFilter"
+        }
+
+        fn create_filter(&self, keys: &[&[u8]], dst: &mut Vec<u8>) {
+            for key in keys {
+                put_fixed_32(dst, hash(key, 1));
+            }
+        }
+
+        fn key_may_match(&self, key: &[u8], filter: &[u8]) -> bool {
+            let h = hash(key, 1);
+            filter
+                .chunks_exact(4)
+                .any(|chunk| decode_fixed_32(chunk) == h)
+        }
+    }
+
+    fn new_policy() -> Arc<dyn FilterPolicy> {
+        Arc::new(TestHashFilter)
+    }
+
+    #[test]
+    fn test_empty_builder() {
+        let mut builder = FilterBlockBuilder::new(new_policy());
+        let block = builder.finish().to_vec();
+        assert_eq!(block, vec![0, 0, 0, 0, FILTER_BASE_LG]);
+        let reader = FilterBlockReader::new(new_policy(), block);
+        assert!(reader.key_may_match(0, b"foo"));
+        assert!(reader.key_may_match(100000, b"foo"));
+    }
+
+    #[test]
+    fn test_single_chunk() {
+        let mut builder = FilterBlockBuilder::new(new_policy());
+        builder.start_block(100);
+        builder.add_key(b"foo");
+        builder.add_key(b"bar");
+        builder.add_key(b"box");
+        builder.start_block(200);
+        builder.add_key(b"box");
+        builder.start_block(300);
+        builder.add_key(b"hello");
+        let block = builder.finish().to_vec();
+        let reader = FilterBlockReader::new(new_policy(), block);
+        assert!(reader.key_may_match(100, b"foo"));
+        assert!(reader.key_may_match(100, b"bar"));
+        assert!(reader.key_may_match(100, b"box"));
+        assert!(reader.key_may_match(100, b"hello"));
+        assert!(reader.key_may_match(100, b"foo"));
+        assert!(!reader.key_may_match(100, b"missing"));
+        assert!(!reader.key_may_match(100, b"other"));
+    }
+
+    #[test]
+    fn test_multi_chunk() {
+        let mut builder = FilterBlockBuilder::new(new_policy());
+
+        // First filter
+        builder.start_block(0);
+        builder.add_key(b"foo");
+        builder.start_block(2000);
+        builder.add_key(b"bar");
+
+        // Second filter
+        builder.start_block(3100);
+        builder.add_key(b"box");
+
+        // Third filter is empty
+
+        // Last filter
+        builder.start_block(9000);
+        builder.add_key(b"box");
+        builder.add_key(b"hello");
+
+        let block = builder.finish().to_vec();
+        let reader = FilterBlockReader::new(new_policy(), block);
+
+        // Check first filter
+        assert!(reader.key_may_match(0, b"foo"));
+        assert!(reader.key_may_match(2000, b"bar"));
+        assert!(!reader.key_may_match(0, b"box"));
+        assert!(!reader.key_may_match(0, b"hello"));
+
+        // Check second filter
+        assert!(reader.key_may_match(3100, b"box"));
+        assert!(!reader.key_may_match(3100, b"foo"));
+        assert!(!reader.key_may_match(3100, b"bar"));
+        assert!(!reader.key_may_match(3100, b"hello"));
+
+        // Check third filter (empty)
+        assert!(!reader.key_may_match(4100, b"foo"));
+        assert!(!reader.key_may_match(4100, b"bar"));
+        assert!(!reader.key_may_match(4100, b"box"));
+        assert!(!reader.key_may_match(4100, b"hello"));
+
+        // Check last filter
+        assert!(reader.key_may_match(9000, b"box"));
+        assert!(reader.key_may_match(9000, b"hello"));
+        assert!(!reader.key_may_match(9000, b"foo"));
+        assert!(!reader.key_may_match(9000, b"bar"));
+    }
+}
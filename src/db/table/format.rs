@@ -0,0 +1,203 @@
+use crate::options::{CompressionType, ReadOptions};
+use crate::util::{
+    coding::{decode_fixed_32, decode_fixed_64, get_varint_64, put_fixed_64, put_varint_64},
+    compression::CompressorRegistry,
+    crc32c,
+    file::RandomAccessFile,
+    status::{Error, ErrorKind, Result},
+};
+
+/// Maximum encoding length of a `BlockHandle`
+pub const MAX_ENCODED_LENGTH: usize = 10 + 10;
+
+/// Encoded length of a `Footer`.  Note that the serialization of a
+/// `Footer` will always occupy exactly this many bytes.  It consists
+/// of two block handles and a magic number.
+pub const FOOTER_ENCODED_LENGTH: usize = 2 * MAX_ENCODED_LENGTH + 8;
+
+// TABLE_MAGIC_NUMBER was picked by running
+//    echo http://code.google.com/p/leveldb/ | sha1sum
+// and taking the leading 64 bits.
+pub const TABLE_MAGIC_NUMBER: u64 = 0xdb47_7524_8b80_fb57;
+
+/// 1-byte type + 32-bit crc
+pub const BLOCK_TRAILER_SIZE: usize = 5;
+
+/// `BlockHandle` is a pointer to the extent of a file that stores a data
+/// block or a meta block.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+impl BlockHandle {
+    pub fn new(offset: u64, size: u64) -> Self {
+        BlockHandle { offset, size }
+    }
+
+    /// The offset of the block in the file.
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The size of the stored block
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    #[inline]
+    pub fn set_offset(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+
+    #[inline]
+    pub fn set_size(&mut self, size: u64) {
+        self.size = size;
+    }
+
+    pub fn encode_to(&self, dst: &mut Vec<u8>) {
+        put_varint_64(dst, self.offset);
+        put_varint_64(dst, self.size);
+    }
+
+    pub fn encoded(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(MAX_ENCODED_LENGTH);
+        self.encode_to(&mut v);
+        v
+    }
+
+    /// Decodes a `BlockHandle` from the start of `src`, returning it with
+    /// the number of bytes consumed.
+    pub fn decode_from(src: &[u8]) -> Result<(Self, usize)> {
+        if let Some((offset, n)) = get_varint_64(src) {
+            if let Some((size, m)) = get_varint_64(&src[n..]) {
+                return Ok((BlockHandle { offset, size }, n + m));
+            }
+        }
+        Err(ErrorKind::Corruption("bad block handle".to_owned()).into())
+    }
+}
+
+/// `Footer` encapsulates the fixed information stored at the tail
+/// end of every table file.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Footer {
+    pub metaindex_handle: BlockHandle,
+    pub index_handle: BlockHandle,
+}
+
+impl Footer {
+    pub fn new(metaindex_handle: BlockHandle, index_handle: BlockHandle) -> Self {
+        Footer {
+            metaindex_handle,
+            index_handle,
+        }
+    }
+
+    pub fn encode_to(&self, dst: &mut Vec<u8>) {
+        let original_size = dst.len();
+        self.metaindex_handle.encode_to(dst);
+        self.index_handle.encode_to(dst);
+        dst.resize(original_size + 2 * MAX_ENCODED_LENGTH, 0); // Padding
+        put_fixed_64(dst, TABLE_MAGIC_NUMBER);
+        assert_eq!(dst.len(), original_size + FOOTER_ENCODED_LENGTH);
+    }
+
+    pub fn decode_from(src: &[u8]) -> Result<Self> {
+        if src.len() < FOOTER_ENCODED_LENGTH {
+            return Err(
+                ErrorKind::Corruption("not an sstable (footer too short)".to_owned()).into(),
+            );
+        }
+        let magic = decode_fixed_64(&src[FOOTER_ENCODED_LENGTH - 8..]);
+        if magic != TABLE_MAGIC_NUMBER {
+            return Err(
+                ErrorKind::Corruption("not an sstable (bad magic number)".to_owned()).into(),
+            );
+        }
+        let (metaindex_handle, n) = BlockHandle::decode_from(src)?;
+        let (index_handle, _) = BlockHandle::decode_from(&src[n..])?;
+        Ok(Footer {
+            metaindex_handle,
+            index_handle,
+        })
+    }
+}
+
+/// Read the block identified by `handle` from `file`.  The checksum
+/// stored in its trailer is verified if `options.verify_checksums` is
+/// set.  Returns the block contents without the trailer, uncompressed
+/// with the codec of `compressors` registered for its type.
+pub fn read_block<F: RandomAccessFile + ?Sized>(
+    file: &F,
+    options: &ReadOptions,
+    compressors: &CompressorRegistry,
+    handle: &BlockHandle,
+) -> Result<Vec<u8>> {
+    let n = handle.size() as usize;
+    let mut buf = vec![0; n + BLOCK_TRAILER_SIZE];
+    if file.read_at(&mut buf, handle.offset())? != buf.len() {
+        return Err(ErrorKind::Corruption("truncated block read".to_owned()).into());
+    }
+
+    // Check the crc of the type and the block contents
+    if options.verify_checksums {
+        let crc = crc32c::unmask(decode_fixed_32(&buf[n + 1..]));
+        let actual = crc32c::value(&buf[..=n]);
+        if actual != crc {
+            return Err(ErrorKind::Corruption("block checksum mismatch".to_owned()).into());
+        }
+    }
+
+    match CompressionType::from_u8(buf[n]) {
+        CompressionType::NoCompression => {
+            buf.truncate(n);
+            Ok(buf)
+        }
+        compression => match compressors.get(compression) {
+            Some(compressor) => compressor.decompress(&buf[..n]).map_err(|_| {
+                Error::from(ErrorKind::Corruption(
+                    "corrupted compressed block contents".to_owned(),
+                ))
+            }),
+            None => Err(ErrorKind::Corruption("bad block type".to_owned()).into()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_handle_encode_decode() {
+        let tests = vec![(0, 0), (1, 1), (300, 4096), (u64::MAX, u64::MAX)];
+        for (offset, size) in tests {
+            let handle = BlockHandle::new(offset, size);
+            let encoded = handle.encoded();
+            assert!(encoded.len() <= MAX_ENCODED_LENGTH);
+            let (decoded, n) = BlockHandle::decode_from(&encoded).unwrap();
+            assert_eq!(n, encoded.len());
+            assert_eq!(decoded, handle);
+        }
+        assert!(BlockHandle::decode_from(&[0x80]).is_err());
+    }
+
+    #[test]
+    fn test_footer_encode_decode() {
+        let footer = Footer::new(BlockHandle::new(300, 100), BlockHandle::new(401, 1000));
+        let mut encoded = vec![];
+        footer.encode_to(&mut encoded);
+        assert_eq!(encoded.len(), FOOTER_ENCODED_LENGTH);
+        assert_eq!(Footer::decode_from(&encoded).unwrap(), footer);
+
+        // Bad magic number
+        encoded[FOOTER_ENCODED_LENGTH - 1] ^= 0xff;
+        assert!(Footer::decode_from(&encoded).is_err());
+        // Too short
+        assert!(Footer::decode_from(&encoded[1..]).is_err());
+    }
+}
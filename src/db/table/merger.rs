@@ -0,0 +1,374 @@
+use std::cmp::Ordering;
+
+use crate::db::ldbiterator::LdbIterator;
+use crate::util::comparator::Comparator;
+use crate::util::status::Result;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// A `MergingIterator` yields the union of the entries of its children in
+/// the order given by the comparator (an `InternalKeyComparator` when
+/// merging the memtables and tables of a db).  No duplicate suppression
+/// is done: when several children hold the same key, the child that comes
+/// first in `children` is yielded first while moving forward.
+///
+/// The valid children are kept in a binary heap keyed by their current
+/// key: a min-heap while moving forward and a max-heap in reverse.
+pub struct MergingIterator<C: Comparator> {
+    cmp: C,
+    children: Vec<Box<dyn LdbIterator>>,
+    // Indices of the valid children ordered as a heap: the root is the
+    // child the iterator is positioned at
+    heap: Vec<usize>,
+    direction: Direction,
+}
+
+impl<C: Comparator> MergingIterator<C> {
+    pub fn new(cmp: C, children: Vec<Box<dyn LdbIterator>>) -> Self {
+        MergingIterator {
+            cmp,
+            children,
+            heap: vec![],
+            direction: Direction::Forward,
+        }
+    }
+
+    fn current(&self) -> usize {
+        assert!(self.valid());
+        self.heap[0]
+    }
+
+    // Returns true if child `a` must be yielded before child `b` in the
+    // current direction
+    fn before(&self, a: usize, b: usize) -> bool {
+        let order = self
+            .cmp
+            .compare(self.children[a].key(), self.children[b].key())
+            .then(a.cmp(&b));
+        match self.direction {
+            Direction::Forward => order == Ordering::Less,
+            Direction::Reverse => order == Ordering::Greater,
+        }
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        let len = self.heap.len();
+        loop {
+            let left = 2 * pos + 1;
+            if left >= len {
+                break;
+            }
+            let right = left + 1;
+            let mut child = left;
+            if right < len && self.before(self.heap[right], self.heap[left]) {
+                child = right;
+            }
+            if !self.before(self.heap[child], self.heap[pos]) {
+                break;
+            }
+            self.heap.swap(pos, child);
+            pos = child;
+        }
+    }
+
+    // Rebuilds the heap from the valid children
+    fn rebuild_heap(&mut self, direction: Direction) {
+        self.direction = direction;
+        self.heap = (0..self.children.len())
+            .filter(|i| self.children[*i].valid())
+            .collect();
+        for pos in (0..self.heap.len() / 2).rev() {
+            self.sift_down(pos);
+        }
+    }
+
+    // Restores the heap after the current child has been moved
+    fn fix_current(&mut self) {
+        if !self.children[self.heap[0]].valid() {
+            let last = self.heap.len() - 1;
+            self.heap.swap(0, last);
+            self.heap.pop();
+        }
+        if !self.heap.is_empty() {
+            self.sift_down(0);
+        }
+    }
+}
+
+impl<C: Comparator> LdbIterator for MergingIterator<C> {
+    fn valid(&self) -> bool {
+        !self.heap.is_empty()
+    }
+
+    fn seek_to_first(&mut self) {
+        for child in self.children.iter_mut() {
+            child.seek_to_first();
+        }
+        self.rebuild_heap(Direction::Forward);
+    }
+
+    fn seek_to_last(&mut self) {
+        for child in self.children.iter_mut() {
+            child.seek_to_last();
+        }
+        self.rebuild_heap(Direction::Reverse);
+    }
+
+    fn seek(&mut self, target: &[u8]) {
+        for child in self.children.iter_mut() {
+            child.seek(target);
+        }
+        self.rebuild_heap(Direction::Forward);
+    }
+
+    fn next(&mut self) {
+        let current = self.current();
+        // Ensure that all children are positioned after key().
+        // If we are moving in the forward direction, it is already
+        // true for all of the non-current children since current is
+        // the smallest child and key() == current.key().  Otherwise,
+        // we explicitly position the non-current children.
+        if self.direction != Direction::Forward {
+            let key = self.key().to_vec();
+            for (i, child) in self.children.iter_mut().enumerate() {
+                if i == current {
+                    continue;
+                }
+                child.seek(&key);
+                if child.valid() && self.cmp.compare(&key, child.key()) == Ordering::Equal {
+                    child.next();
+                }
+            }
+            self.rebuild_heap(Direction::Forward);
+        }
+        self.children[current].next();
+        self.fix_current();
+    }
+
+    fn key(&self) -> &[u8] {
+        self.children[self.current()].key()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.children[self.current()].value()
+    }
+
+    fn prev(&mut self) {
+        let current = self.current();
+        // Ensure that all children are positioned before key().
+        // If we are moving in the reverse direction, it is already
+        // true for all of the non-current children since current is
+        // the largest child and key() == current.key().  Otherwise,
+        // we explicitly position the non-current children.
+        if self.direction != Direction::Reverse {
+            let key = self.key().to_vec();
+            for (i, child) in self.children.iter_mut().enumerate() {
+                if i == current {
+                    continue;
+                }
+                child.seek(&key);
+                if child.valid() {
+                    // Child is at first entry >= key().  Step back one to be < key()
+                    child.prev();
+                } else {
+                    // Child has no entries >= key().  Position at last entry.
+                    child.seek_to_last();
+                }
+            }
+            self.rebuild_heap(Direction::Reverse);
+        }
+        self.children[current].prev();
+        self.fix_current();
+    }
+
+    fn status(&mut self) -> Result<()> {
+        for child in self.children.iter_mut() {
+            child.status()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::block::{Block, BlockBuilder};
+    use crate::db::format::{InternalKey, InternalKeyComparator, ValueType};
+    use crate::db::memtable::MemTable;
+    use crate::util::comparator::BytewiseComparator;
+    use crate::util::status::{Error, ErrorKind};
+
+    fn icmp() -> InternalKeyComparator<BytewiseComparator> {
+        InternalKeyComparator::new(BytewiseComparator::default())
+    }
+
+    fn ikey(key: &str, seq: u64) -> Vec<u8> {
+        InternalKey::new(key.as_bytes(), seq, ValueType::KTypeValue)
+            .encode()
+            .to_vec()
+    }
+
+    fn new_block(entries: &[(&str, u64)]) -> Block {
+        let mut builder = BlockBuilder::new(2, icmp());
+        for (key, seq) in entries {
+            builder.add(&ikey(key, *seq), key.as_bytes());
+        }
+        Block::new(Vec::from(builder.finish())).unwrap()
+    }
+
+    fn new_mem_table(entries: &[(&str, u64)]) -> MemTable<BytewiseComparator> {
+        let mem = MemTable::new(icmp(), 1 << 20);
+        for (key, seq) in entries {
+            mem.add(*seq, ValueType::KTypeValue, key.as_bytes(), key.as_bytes());
+        }
+        mem
+    }
+
+    // Children are a memtable holding the newest entries and two blocks
+    fn new_merging_iter() -> MergingIterator<InternalKeyComparator<BytewiseComparator>> {
+        let mem = new_mem_table(&[("b", 9), ("d", 8), ("f", 7)]);
+        let block1 = new_block(&[("a", 2), ("b", 3), ("e", 1)]);
+        let block2 = new_block(&[("c", 4), ("d", 5), ("g", 6)]);
+        let children: Vec<Box<dyn LdbIterator>> = vec![
+            Box::new(mem.iter()),
+            Box::new(block1.iter(icmp())),
+            Box::new(block2.iter(icmp())),
+        ];
+        MergingIterator::new(icmp(), children)
+    }
+
+    fn expected() -> Vec<Vec<u8>> {
+        vec![
+            ikey("a", 2),
+            ikey("b", 9),
+            ikey("b", 3),
+            ikey("c", 4),
+            ikey("d", 8),
+            ikey("d", 5),
+            ikey("e", 1),
+            ikey("f", 7),
+            ikey("g", 6),
+        ]
+    }
+
+    #[test]
+    fn test_forward_and_backward() {
+        let mut iter = new_merging_iter();
+        let mut keys = vec![];
+        iter.seek_to_first();
+        while iter.valid() {
+            keys.push(iter.key().to_vec());
+            iter.next();
+        }
+        assert_eq!(keys, expected());
+
+        keys.clear();
+        iter.seek_to_last();
+        while iter.valid() {
+            keys.push(iter.key().to_vec());
+            iter.prev();
+        }
+        keys.reverse();
+        assert_eq!(keys, expected());
+        assert!(iter.status().is_ok());
+    }
+
+    #[test]
+    fn test_seek_and_switch_direction() {
+        let expected = expected();
+        let mut iter = new_merging_iter();
+        iter.seek(&ikey("c", 100));
+        assert_eq!(iter.key(), expected[3].as_slice());
+        assert_eq!(iter.value(), b"c");
+        iter.prev();
+        assert_eq!(iter.key(), expected[2].as_slice());
+        iter.prev();
+        assert_eq!(iter.key(), expected[1].as_slice());
+        iter.next();
+        assert_eq!(iter.key(), expected[2].as_slice());
+        iter.next();
+        assert_eq!(iter.key(), expected[3].as_slice());
+        iter.next();
+        assert_eq!(iter.key(), expected[4].as_slice());
+        assert_eq!(iter.value(), b"d");
+        iter.prev();
+        assert_eq!(iter.key(), expected[3].as_slice());
+
+        // Switch direction at both ends
+        iter.seek_to_last();
+        iter.prev();
+        iter.next();
+        assert_eq!(iter.key(), expected[8].as_slice());
+        iter.next();
+        assert!(!iter.valid());
+        iter.seek_to_first();
+        iter.next();
+        iter.prev();
+        assert_eq!(iter.key(), expected[0].as_slice());
+        iter.prev();
+        assert!(!iter.valid());
+
+        iter.seek(&ikey("h", 100));
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn test_empty_children() {
+        let mut iter = MergingIterator::new(icmp(), vec![]);
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        iter.seek_to_last();
+        assert!(!iter.valid());
+
+        let mem = new_mem_table(&[]);
+        let block = new_block(&[("a", 1)]);
+        let children: Vec<Box<dyn LdbIterator>> =
+            vec![Box::new(mem.iter()), Box::new(block.iter(icmp()))];
+        let mut iter = MergingIterator::new(icmp(), children);
+        iter.seek_to_last();
+        assert_eq!(iter.key(), ikey("a", 1).as_slice());
+        iter.prev();
+        assert!(!iter.valid());
+    }
+
+    struct ErrorIterator;
+
+    impl LdbIterator for ErrorIterator {
+        fn valid(&self) -> bool {
+            false
+        }
+        fn seek_to_first(&mut self) {}
+        fn seek_to_last(&mut self) {}
+        fn seek(&mut self, _target: &[u8]) {}
+        fn next(&mut self) {}
+        fn key(&self) -> &[u8] {
+            unreachable!()
+        }
+        fn value(&self) -> &[u8] {
+            unreachable!()
+        }
+        fn prev(&mut self) {}
+        fn status(&mut self) -> Result<()> {
+            Err(ErrorKind::Corruption("bad block".to_owned()).into())
+        }
+    }
+
+    #[test]
+    fn test_child_error() {
+        let block = new_block(&[("a", 1)]);
+        let children: Vec<Box<dyn LdbIterator>> =
+            vec![Box::new(block.iter(icmp())), Box::new(ErrorIterator)];
+        let mut iter = MergingIterator::new(icmp(), children);
+        iter.seek_to_first();
+        assert_eq!(iter.key(), ikey("a", 1).as_slice());
+        let err = iter.status().unwrap_err();
+        match err.kind() {
+            ErrorKind::Corruption(msg) => assert_eq!(msg, "bad block"),
+            _ => panic!("the error of a child should be returned"),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+mod builder;
+pub mod filter_block;
+pub mod format;
+pub mod merger;
+mod reader;
+pub mod two_level_iterator;
+
+pub use builder::TableBuilder;
+pub use merger::MergingIterator;
+pub use reader::{BlockCache, Table, TableIterator};
@@ -0,0 +1,614 @@
+use std::sync::Arc;
+
+use super::filter_block::FilterBlockReader;
+use super::format::{self, BlockHandle, Footer, FOOTER_ENCODED_LENGTH};
+use super::two_level_iterator::{DerivedIterFactory, TwoLevelIterator};
+use crate::db::block::{Block, BlockIterator};
+use crate::db::ldbiterator::LdbIterator;
+use crate::options::{Options, ReadOptions};
+use crate::util::{
+    cache::{Cache, ShardedLRUCache},
+    comparator::{BytewiseComparator, Comparator},
+    compression::CompressorRegistry,
+    file::RandomAccessFile,
+    status::{Error, ErrorKind, Result},
+};
+
+/// A cache of the uncompressed data blocks of tables, shared by all the
+/// tables opened with clones of the same `Options`.  The charge of a
+/// block is its size in bytes.
+#[derive(Clone)]
+pub struct BlockCache {
+    // Blocks are keyed by the id of their table in the cache and by
+    // their offset in the table file
+    cache: Arc<ShardedLRUCache<(u64, u64), Block>>,
+}
+
+impl BlockCache {
+    /// Create a cache holding up to `capacity` bytes of blocks
+    pub fn new(capacity: usize) -> Self {
+        BlockCache {
+            cache: Arc::new(ShardedLRUCache::new(capacity)),
+        }
+    }
+
+    /// Returns the total size in bytes of the cached blocks
+    pub fn total_charge(&self) -> usize {
+        self.cache.total_charge()
+    }
+}
+
+/// A `Table` is a sorted map from strings to strings.  Tables are
+/// immutable and persistent.  A `Table` may be safely accessed from
+/// multiple threads without external synchronization.
+pub struct Table<C: Comparator + Clone, F: RandomAccessFile> {
+    options: Options<C>,
+    file: F,
+    // Number of the file, to locate the corrupted blocks in errors
+    file_number: u64,
+    // Id of the table in the block cache, if any
+    cache_id: u64,
+    metaindex_handle: BlockHandle,
+    index_block: Block,
+    filter: Option<FilterBlockReader>,
+}
+
+/// Iterator over the contents of a `Table`, created by `Table::new_iterator`
+pub type TableIterator<C, F> = TwoLevelIterator<BlockIterator<C>, BlockIterFactory<C, F>>;
+
+impl<C: Comparator + Clone, F: RandomAccessFile> Table<C, F> {
+    /// Attempt to open the table that is stored in bytes `[0..size)`
+    /// of `file`, and read the metadata entries necessary to allow
+    /// retrieving data from the table.  `file_number` identifies the
+    /// file in the errors reporting corrupted blocks.
+    ///
+    /// `file` must remain live while this `Table` is in use.
+    pub fn open(options: Options<C>, file: F, file_number: u64, size: u64) -> Result<Self> {
+        if size < FOOTER_ENCODED_LENGTH as u64 {
+            return Err(
+                ErrorKind::Corruption("file is too short to be an sstable".to_owned()).into(),
+            );
+        }
+
+        let mut footer_space = [0; FOOTER_ENCODED_LENGTH];
+        let n = file.read_at(&mut footer_space, size - FOOTER_ENCODED_LENGTH as u64)?;
+        if n != FOOTER_ENCODED_LENGTH {
+            return Err(ErrorKind::Corruption("truncated footer read".to_owned()).into());
+        }
+        let footer = Footer::decode_from(&footer_space)?;
+
+        // Read the index block
+        let read_options = ReadOptions {
+            verify_checksums: options.paranoid_checks,
+            ..ReadOptions::default()
+        };
+        let index_block = Block::new(read_block(
+            &file,
+            file_number,
+            &read_options,
+            &options.compressors,
+            &footer.index_handle,
+        )?)?;
+        let cache_id = options
+            .block_cache
+            .as_ref()
+            .map_or(0, |block_cache| block_cache.cache.new_id());
+        let mut table = Table {
+            options,
+            file,
+            file_number,
+            cache_id,
+            metaindex_handle: footer.metaindex_handle,
+            index_block,
+            filter: None,
+        };
+        table.read_meta(&footer);
+        Ok(table)
+    }
+
+    fn read_meta(&mut self, footer: &Footer) {
+        let policy = match &self.options.filter_policy {
+            Some(policy) => policy.clone(),
+            None => return, // Do not need any metadata
+        };
+
+        // Errors are ignored here since meta info is not needed for operation
+        let read_options = ReadOptions::default();
+        let meta = match self
+            .read_block(&read_options, &footer.metaindex_handle)
+            .and_then(Block::new)
+        {
+            Ok(meta) => meta,
+            Err(_) => return,
+        };
+        let mut iter = meta.iter(BytewiseComparator::default());
+        let key = format!("filter.{}", policy.name());
+        iter.seek(key.as_bytes());
+        if iter.valid() && iter.key() == key.as_bytes() {
+            if let Ok((handle, _)) = BlockHandle::decode_from(iter.value()) {
+                if let Ok(contents) = self.read_block(&read_options, &handle) {
+                    self.filter = Some(FilterBlockReader::new(policy, contents));
+                }
+            }
+        }
+    }
+
+    /// Returns a new iterator over the table contents.
+    /// The result of `new_iterator()` is initially invalid (caller must
+    /// call one of the seek methods on the iterator before using it).
+    pub fn new_iterator(self: &Arc<Self>, options: ReadOptions<'static>) -> TableIterator<C, F> {
+        let index_iter = self.index_block.iter(self.options.comparator.clone());
+        TwoLevelIterator::new(
+            index_iter,
+            BlockIterFactory {
+                table: self.clone(),
+                options,
+            },
+        )
+    }
+
+    /// Finds the first entry at or past `key` in the data block that may
+    /// contain `key`, and returns it as `(key, value)`.  Returns `None` if
+    /// no such entry exists.
+    pub fn get(&self, options: ReadOptions, key: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let mut index_iter = self.index_block.iter(self.options.comparator.clone());
+        index_iter.seek(key);
+        if index_iter.valid() {
+            let handle_value = index_iter.value();
+            if let Some(filter) = &self.filter {
+                let (handle, _) = BlockHandle::decode_from(handle_value)?;
+                if !filter.key_may_match(handle.offset(), key) {
+                    // Not found
+                    return Ok(None);
+                }
+            }
+            let mut block_iter = self.block_reader(&options, handle_value)?;
+            block_iter.seek(key);
+            if block_iter.valid() {
+                return Ok(Some((
+                    block_iter.key().to_vec(),
+                    block_iter.value().to_vec(),
+                )));
+            }
+            block_iter.status()?;
+        }
+        index_iter.status()?;
+        Ok(None)
+    }
+
+    /// Given a key, return an approximate byte offset in the file where
+    /// the data for that key begins (or would begin if the key were
+    /// present in the file).  The returned value is in terms of file
+    /// bytes, and so includes effects like compression of the underlying data.
+    /// E.g., the approximate offset of the last key in the table will
+    /// be close to the file length.
+    pub fn approximate_offset_of(&self, key: &[u8]) -> u64 {
+        let mut index_iter = self.index_block.iter(self.options.comparator.clone());
+        index_iter.seek(key);
+        if index_iter.valid() {
+            if let Ok((handle, _)) = BlockHandle::decode_from(index_iter.value()) {
+                return handle.offset();
+            }
+        }
+        // key is past the last key in the file, or the index entry is
+        // broken.  Approximate the offset by returning the offset of the
+        // metaindex block (which is right near the end of the file).
+        self.metaindex_handle.offset()
+    }
+
+    // Convert an index iterator value (i.e., an encoded BlockHandle)
+    // into an iterator over the contents of the corresponding block.
+    // The block is looked up in the block cache first, and is added to
+    // it after being read if `options.fill_cache` is set.
+    fn block_reader(&self, options: &ReadOptions, index_value: &[u8]) -> Result<BlockIterator<C>> {
+        let (handle, _) = BlockHandle::decode_from(index_value)?;
+        let block = match &self.options.block_cache {
+            Some(block_cache) => {
+                let key = (self.cache_id, handle.offset());
+                match block_cache.cache.lookup(&key) {
+                    Some(cache_handle) => cache_handle.value().clone(),
+                    None => {
+                        let contents = self.read_block(options, &handle)?;
+                        let charge = contents.len();
+                        let block = Block::new(contents)?;
+                        if options.fill_cache {
+                            block_cache.cache.insert(key, block.clone(), charge);
+                        }
+                        block
+                    }
+                }
+            }
+            None => Block::new(self.read_block(options, &handle)?)?,
+        };
+        Ok(block.iter(self.options.comparator.clone()))
+    }
+
+    // Reads a block of this table.  Paranoid checks verify the checksums
+    // of every block, whatever the read options say.
+    fn read_block(&self, options: &ReadOptions, handle: &BlockHandle) -> Result<Vec<u8>> {
+        let options = ReadOptions {
+            verify_checksums: options.verify_checksums || self.options.paranoid_checks,
+            ..*options
+        };
+        read_block(
+            &self.file,
+            self.file_number,
+            &options,
+            &self.options.compressors,
+            handle,
+        )
+    }
+}
+
+// Reads a block of the table file numbered `file_number`, recording
+// where the block lies in the errors
+fn read_block<F: RandomAccessFile>(
+    file: &F,
+    file_number: u64,
+    options: &ReadOptions,
+    compressors: &CompressorRegistry,
+    handle: &BlockHandle,
+) -> Result<Vec<u8>> {
+    format::read_block(file, options, compressors, handle)
+        .map_err(|e| e.with_file_number(file_number).with_offset(handle.offset()))
+}
+
+/// Opens the data blocks pointed by the index block of a table
+pub struct BlockIterFactory<C: Comparator + Clone, F: RandomAccessFile> {
+    table: Arc<Table<C, F>>,
+    options: ReadOptions<'static>,
+}
+
+impl<C: Comparator + Clone, F: RandomAccessFile> DerivedIterFactory for BlockIterFactory<C, F> {
+    type Iter = BlockIterator<C>;
+
+    fn derive(&self, value: &[u8]) -> Result<Self::Iter> {
+        self.table.block_reader(&self.options, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::table::TableBuilder;
+    use crate::options::CompressionType;
+    use crate::util::compression::{Compressor, SnappyCompressor};
+    use crate::util::filter_policy::{BloomFilterPolicy, FilterPolicy};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn new_options(block_size: usize) -> Options<BytewiseComparator> {
+        Options {
+            block_size,
+            ..Options::default()
+        }
+    }
+
+    fn build_table(entries: &[(String, String)], block_size: usize) -> Vec<u8> {
+        let mut builder = TableBuilder::new(new_options(block_size), vec![]);
+        for (k, v) in entries.iter() {
+            builder.add(k.as_bytes(), v.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        builder.into_inner()
+    }
+
+    fn test_entries(n: usize) -> Vec<(String, String)> {
+        (0..n)
+            .map(|i| (format!("key{:06}", i * 2), format!("value{}", i)))
+            .collect()
+    }
+
+    fn open_table(data: Vec<u8>) -> Arc<Table<BytewiseComparator, Vec<u8>>> {
+        let size = data.len() as u64;
+        Arc::new(Table::open(new_options(4096), data, 1, size).unwrap())
+    }
+
+    #[test]
+    fn test_empty_table() {
+        let table = open_table(build_table(&[], 4096));
+        let mut iter = table.new_iterator(ReadOptions::default());
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        iter.seek_to_last();
+        assert!(!iter.valid());
+        iter.seek(b"foo");
+        assert!(!iter.valid());
+        assert!(iter.status().is_ok());
+        assert!(table.get(ReadOptions::default(), b"foo").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_table_iterate() {
+        let entries = test_entries(1000);
+        for block_size in [1, 128, 4096, 1 << 20].iter() {
+            let table = open_table(build_table(&entries, *block_size));
+            let mut iter = table.new_iterator(ReadOptions::default());
+            assert!(!iter.valid());
+
+            iter.seek_to_first();
+            for (k, v) in entries.iter() {
+                assert!(iter.valid());
+                assert_eq!(iter.key(), k.as_bytes());
+                assert_eq!(iter.value(), v.as_bytes());
+                iter.next();
+            }
+            assert!(!iter.valid());
+
+            iter.seek_to_last();
+            for (k, v) in entries.iter().rev() {
+                assert!(iter.valid());
+                assert_eq!(iter.key(), k.as_bytes());
+                assert_eq!(iter.value(), v.as_bytes());
+                iter.prev();
+            }
+            assert!(!iter.valid());
+            assert!(iter.status().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_table_seek_and_get() {
+        let entries = test_entries(500);
+        let table = open_table(build_table(&entries, 256));
+        let mut iter = table.new_iterator(ReadOptions::default());
+        for (i, (k, v)) in entries.iter().enumerate() {
+            iter.seek(k.as_bytes());
+            assert_eq!(iter.key(), k.as_bytes());
+            assert_eq!(iter.value(), v.as_bytes());
+            let (found_key, found_value) = table
+                .get(ReadOptions::default(), k.as_bytes())
+                .unwrap()
+                .unwrap();
+            assert_eq!(found_key, k.as_bytes());
+            assert_eq!(found_value, v.as_bytes());
+
+            // Seek a missing key between two entries
+            let missing = format!("key{:06}", i * 2 + 1);
+            iter.seek(missing.as_bytes());
+            match entries.get(i + 1) {
+                Some((next, _)) => assert_eq!(iter.key(), next.as_bytes()),
+                None => assert!(!iter.valid()),
+            }
+        }
+        assert!(table.get(ReadOptions::default(), b"zzz").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_with_filter() {
+        let policy: Arc<dyn FilterPolicy> = Arc::new(BloomFilterPolicy::new(10));
+        let options: Options = Options {
+            block_size: 256,
+            filter_policy: Some(policy),
+            ..Options::default()
+        };
+        let entries = test_entries(1000);
+        let mut builder = TableBuilder::new(options.clone(), vec![]);
+        for (k, v) in entries.iter() {
+            builder.add(k.as_bytes(), v.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        let data = builder.into_inner();
+        let size = data.len() as u64;
+        let table = Table::open(options, data, 1, size).unwrap();
+        assert!(table.filter.is_some());
+
+        for (k, v) in entries.iter() {
+            let (found_key, found_value) = table
+                .get(ReadOptions::default(), k.as_bytes())
+                .unwrap()
+                .unwrap();
+            assert_eq!(found_key, k.as_bytes());
+            assert_eq!(found_value, v.as_bytes());
+        }
+        // Most of the missing keys are filtered out before reading the data block
+        let mut found = 0;
+        for i in 0..1000 {
+            let missing = format!("key{:06}", i * 2 + 1);
+            if table
+                .get(ReadOptions::default(), missing.as_bytes())
+                .unwrap()
+                .is_some()
+            {
+                found += 1;
+            }
+        }
+        assert!(found < 50, "{} missing keys passed the filter", found);
+    }
+
+    #[test]
+    fn test_approximate_offset_of() {
+        let entries = test_entries(1000);
+        let data = build_table(&entries, 1024);
+        let size = data.len() as u64;
+        let table = open_table(data);
+        assert_eq!(table.approximate_offset_of(b""), 0);
+        let mid = table.approximate_offset_of(entries[500].0.as_bytes());
+        assert!(mid > 0 && mid < size);
+        assert!(table.approximate_offset_of(b"zzz") < size);
+        assert!(table.approximate_offset_of(b"zzz") > mid);
+    }
+
+    #[test]
+    fn test_open_corrupted_footer() {
+        let mut data = build_table(&test_entries(10), 4096);
+        let size = data.len() as u64;
+        let err = Table::open(new_options(4096), vec![0u8; 10], 1, 10)
+            .err()
+            .expect("expect a corruption error for a short file");
+        assert!(err.is_corruption());
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        let err = Table::open(new_options(4096), data, 1, size)
+            .err()
+            .expect("expect a corruption error for a bad magic number");
+        assert!(err.is_corruption());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let entries = test_entries(1000);
+        let mut data = build_table(&entries, 1024);
+        // Corrupt the first data block
+        data[10] ^= 0xff;
+        let table = open_table(data);
+        let verify = ReadOptions {
+            verify_checksums: true,
+            ..ReadOptions::default()
+        };
+        let err = table.get(verify, entries[0].0.as_bytes()).unwrap_err();
+        match err.kind() {
+            ErrorKind::Corruption(hint) => assert_eq!(hint, "block checksum mismatch"),
+            _ => panic!("expect a corruption error"),
+        }
+        let context = err.context().unwrap();
+        assert_eq!(context.file_number, Some(1));
+        assert_eq!(context.offset, Some(0));
+        let mut iter = table.new_iterator(verify);
+        iter.seek_to_first();
+        // The broken block is skipped and the error is kept
+        assert!(iter.valid());
+        assert!(iter.key() > entries[0].0.as_bytes());
+        assert!(iter.status().unwrap_err().is_corruption());
+    }
+
+    // Snappy under another compression type
+    struct CustomCompressor;
+
+    impl Compressor for CustomCompressor {
+        fn compression_type(&self) -> CompressionType {
+            CompressionType::Custom(42)
+        }
+
+        fn compress(&self, input: &[u8]) -> Result<Vec<u8>> {
+            SnappyCompressor.compress(input)
+        }
+
+        fn decompress(&self, input: &[u8]) -> Result<Vec<u8>> {
+            SnappyCompressor.decompress(input)
+        }
+    }
+
+    #[test]
+    fn test_custom_compressor() {
+        let mut options = Options {
+            compression: CompressionType::Custom(42),
+            ..new_options(4096)
+        };
+        options.compressors.register(Arc::new(CustomCompressor));
+        let mut builder = TableBuilder::new(options.clone(), vec![]);
+        builder.add(b"key", &[b'x'; 1000]).unwrap();
+        builder.finish().unwrap();
+        let data = builder.into_inner();
+        let size = data.len() as u64;
+
+        let table = Table::open(options, data.clone(), 1, size).unwrap();
+        let (_, value) = table.get(ReadOptions::default(), b"key").unwrap().unwrap();
+        assert_eq!(value, vec![b'x'; 1000]);
+
+        // Without the codec, the data block can't be read
+        let table = Table::open(new_options(4096), data, 1, size).unwrap();
+        let err = table.get(ReadOptions::default(), b"key").unwrap_err();
+        assert!(err.is_corruption());
+    }
+
+    #[test]
+    fn test_checksums_verified_on_demand() {
+        let options = Options {
+            compression: CompressionType::NoCompression,
+            ..new_options(4096)
+        };
+        let mut builder = TableBuilder::new(options.clone(), vec![]);
+        builder.add(b"key", b"value").unwrap();
+        builder.finish().unwrap();
+        let mut data = builder.into_inner();
+        // Corrupt the value in the data block
+        let pos = data.windows(5).position(|w| w == b"value").unwrap();
+        data[pos] = b'V';
+        let size = data.len() as u64;
+
+        // The corruption goes unnoticed unless checksums are verified.  The
+        // block is kept out of the cache, where it would not be verified again.
+        let table = Table::open(options.clone(), data.clone(), 1, size).unwrap();
+        let no_cache = ReadOptions {
+            fill_cache: false,
+            ..ReadOptions::default()
+        };
+        let (_, value) = table.get(no_cache, b"key").unwrap().unwrap();
+        assert_eq!(value, b"Value");
+        let verify = ReadOptions {
+            verify_checksums: true,
+            ..ReadOptions::default()
+        };
+        assert!(table.get(verify, b"key").is_err());
+
+        // Paranoid checks verify the index block when opening the table
+        let index_handle = Footer::decode_from(&data[data.len() - FOOTER_ENCODED_LENGTH..])
+            .unwrap()
+            .index_handle;
+        data[index_handle.offset() as usize] ^= 0x01;
+        assert!(Table::open(options.clone(), data.clone(), 1, size).is_ok());
+        let paranoid = Options {
+            paranoid_checks: true,
+            ..options
+        };
+        match Table::open(paranoid, data, 1, size) {
+            Err(e) => assert!(e.is_corruption()),
+            _ => panic!("expect a corruption error"),
+        }
+    }
+
+    // A table file counting the reads of its blocks
+    struct CountingFile {
+        data: Vec<u8>,
+        reads: AtomicUsize,
+    }
+
+    impl RandomAccessFile for CountingFile {
+        fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.data.read_at(buf, offset)
+        }
+    }
+
+    #[test]
+    fn test_block_cache() {
+        let entries = test_entries(1000);
+        let data = build_table(&entries, 1024);
+        let size = data.len() as u64;
+        let options = new_options(4096);
+        let block_cache = options.block_cache.clone().unwrap();
+        let file = CountingFile {
+            data,
+            reads: AtomicUsize::new(0),
+        };
+        let table = Table::open(options.clone(), file, 1, size).unwrap();
+        let reads = || table.file.reads.load(Ordering::SeqCst);
+        let key = entries[0].0.as_bytes();
+
+        // Reads that do not fill the cache always hit the file
+        let no_cache = ReadOptions {
+            fill_cache: false,
+            ..ReadOptions::default()
+        };
+        let before = reads();
+        table.get(no_cache, key).unwrap().unwrap();
+        table.get(no_cache, key).unwrap().unwrap();
+        assert_eq!(reads(), before + 2);
+        assert_eq!(block_cache.total_charge(), 0);
+
+        // Once cached, the block is not read again, even by the reads
+        // that do not fill the cache
+        table.get(ReadOptions::default(), key).unwrap().unwrap();
+        assert_eq!(reads(), before + 3);
+        assert!(block_cache.total_charge() > 0);
+        table.get(ReadOptions::default(), key).unwrap().unwrap();
+        table.get(no_cache, key).unwrap().unwrap();
+        assert_eq!(reads(), before + 3);
+
+        // Tables sharing the cache do not share their blocks
+        let other = Table::open(options, build_table(&entries, 1024), 2, size).unwrap();
+        let charge = block_cache.total_charge();
+        other.get(ReadOptions::default(), key).unwrap().unwrap();
+        assert!(block_cache.total_charge() > charge);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::db::ldbiterator::LdbIterator;
+use crate::util::status::{Error, Result};
+
+/// A factory that turns the value of an index entry into an iterator
+/// over the entries it points to.
+pub trait DerivedIterFactory {
+    type Iter: LdbIterator;
+
+    fn derive(&self, value: &[u8]) -> Result<Self::Iter>;
+}
+
+/// A two-level iterator contains an index iterator whose values point
+/// to a sequence of blocks where each block is itself a sequence of
+/// key,value pairs.  The returned two-level iterator yields the
+/// concatenation of all key/value pairs in the sequence of blocks.
+///
+/// Data iterators are created lazily through the `DerivedIterFactory`
+/// when the index iterator moves onto a new entry.
+pub struct TwoLevelIterator<I: LdbIterator, F: DerivedIterFactory> {
+    index_iter: I,
+    factory: F,
+    data_iter: Option<F::Iter>,
+    // If `data_iter` is not none, then `data_block_handle` holds the
+    // index value passed to the factory to create the `data_iter`.
+    data_block_handle: Vec<u8>,
+    err: Option<Error>,
+}
+
+impl<I: LdbIterator, F: DerivedIterFactory> TwoLevelIterator<I, F> {
+    pub fn new(index_iter: I, factory: F) -> Self {
+        TwoLevelIterator {
+            index_iter,
+            factory,
+            data_iter: None,
+            data_block_handle: vec![],
+            err: None,
+        }
+    }
+
+    fn save_error(&mut self, e: Error) {
+        if self.err.is_none() {
+            self.err = Some(e);
+        }
+    }
+
+    fn set_data_iter(&mut self, data_iter: Option<F::Iter>) {
+        if let Some(mut iter) = self.data_iter.take() {
+            if let Err(e) = iter.status() {
+                self.save_error(e);
+            }
+        }
+        self.data_iter = data_iter;
+    }
+
+    fn init_data_block(&mut self) {
+        if !self.index_iter.valid() {
+            self.set_data_iter(None);
+            return;
+        }
+        let handle = self.index_iter.value();
+        if self.data_iter.is_some() && handle == self.data_block_handle.as_slice() {
+            // data_iter is already constructed with this iterator, so
+            // no need to change anything
+            return;
+        }
+        match self.factory.derive(handle) {
+            Ok(iter) => {
+                self.data_block_handle.clear();
+                self.data_block_handle.extend_from_slice(handle);
+                self.set_data_iter(Some(iter));
+            }
+            Err(e) => {
+                self.set_data_iter(None);
+                self.save_error(e);
+            }
+        }
+    }
+
+    fn data_iter_valid(&self) -> bool {
+        self.data_iter.as_ref().map_or(false, |iter| iter.valid())
+    }
+
+    fn skip_empty_data_blocks_forward(&mut self) {
+        while !self.data_iter_valid() {
+            // Move to next block
+            if !self.index_iter.valid() {
+                self.set_data_iter(None);
+                return;
+            }
+            self.index_iter.next();
+            self.init_data_block();
+            if let Some(iter) = self.data_iter.as_mut() {
+                iter.seek_to_first();
+            }
+        }
+    }
+
+    fn skip_empty_data_blocks_backward(&mut self) {
+        while !self.data_iter_valid() {
+            // Move to previous block
+            if !self.index_iter.valid() {
+                self.set_data_iter(None);
+                return;
+            }
+            self.index_iter.prev();
+            self.init_data_block();
+            if let Some(iter) = self.data_iter.as_mut() {
+                iter.seek_to_last();
+            }
+        }
+    }
+}
+
+impl<I: LdbIterator, F: DerivedIterFactory> LdbIterator for TwoLevelIterator<I, F> {
+    fn valid(&self) -> bool {
+        self.data_iter_valid()
+    }
+
+    fn seek_to_first(&mut self) {
+        self.index_iter.seek_to_first();
+        self.init_data_block();
+        if let Some(iter) = self.data_iter.as_mut() {
+            iter.seek_to_first();
+        }
+        self.skip_empty_data_blocks_forward();
+    }
+
+    fn seek_to_last(&mut self) {
+        self.index_iter.seek_to_last();
+        self.init_data_block();
+        if let Some(iter) = self.data_iter.as_mut() {
+            iter.seek_to_last();
+        }
+        self.skip_empty_data_blocks_backward();
+    }
+
+    fn seek(&mut self, target: &[u8]) {
+        self.index_iter.seek(target);
+        self.init_data_block();
+        if let Some(iter) = self.data_iter.as_mut() {
+            iter.seek(target);
+        }
+        self.skip_empty_data_blocks_forward();
+    }
+
+    fn next(&mut self) {
+        assert!(self.valid());
+        self.data_iter.as_mut().unwrap().next();
+        self.skip_empty_data_blocks_forward();
+    }
+
+    fn key(&self) -> &[u8] {
+        assert!(self.valid());
+        self.data_iter.as_ref().unwrap().key()
+    }
+
+    fn value(&self) -> &[u8] {
+        assert!(self.valid());
+        self.data_iter.as_ref().unwrap().value()
+    }
+
+    fn prev(&mut self) {
+        assert!(self.valid());
+        self.data_iter.as_mut().unwrap().prev();
+        self.skip_empty_data_blocks_backward();
+    }
+
+    fn status(&mut self) -> Result<()> {
+        self.index_iter.status()?;
+        if let Some(iter) = self.data_iter.as_mut() {
+            iter.status()?;
+        }
+        match &self.err {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+}
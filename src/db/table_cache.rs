@@ -0,0 +1,198 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::filename::{generate_filename, FileType};
+use super::format::{InternalFilterPolicy, InternalKeyComparator};
+use super::table::{Table, TableIterator};
+use crate::env::RandomAccessFile;
+use crate::options::{Options, ReadOptions};
+use crate::util::cache::{Cache, CacheHandle, ShardedLRUCache};
+use crate::util::comparator::Comparator;
+use crate::util::filter_policy::FilterPolicy;
+use crate::util::status::{Error, Result};
+
+/// The options every table of a db is opened with: the keys of a table
+/// are internal keys.
+pub type TableOptions<C> = Options<InternalKeyComparator<C>>;
+
+/// Converts the options of a db into the options its tables are built
+/// and read with: keys are compared with an `InternalKeyComparator` and
+/// the filter policy, if any, sees user keys only.
+pub fn table_options<C: Comparator + Clone>(options: &Options<C>) -> TableOptions<C> {
+    Options {
+        comparator: InternalKeyComparator::new(options.comparator.clone()),
+        create_if_missing: options.create_if_missing,
+        error_if_exists: options.error_if_exists,
+        paranoid_checks: options.paranoid_checks,
+        env: options.env.clone(),
+        write_buffer_size: options.write_buffer_size,
+        max_open_files: options.max_open_files,
+        block_size: options.block_size,
+        block_restart_interval: options.block_restart_interval,
+        max_file_size: options.max_file_size,
+        compression: options.compression,
+        compressors: options.compressors.clone(),
+        block_cache: options.block_cache.clone(),
+        filter_policy: options.filter_policy.clone().map(|policy| {
+            let policy: Arc<dyn FilterPolicy> = Arc::new(InternalFilterPolicy::new(policy));
+            policy
+        }),
+    }
+}
+
+// Number of open files the db keeps out of the table cache: the log,
+// the MANIFEST, the lock file...
+const NUM_NON_TABLE_CACHE_FILES: usize = 10;
+
+type TableHandle<C> =
+    CacheHandle<u64, Arc<Table<InternalKeyComparator<C>, Box<dyn RandomAccessFile>>>>;
+
+/// Opens the table files of a database by file number and keeps the
+/// most recently used ones open, so that at most `max_open_files` table
+/// files are open at once (besides those held by live iterators).
+pub struct TableCache<C: Comparator + Clone> {
+    dbname: PathBuf,
+    options: TableOptions<C>,
+    cache: ShardedLRUCache<u64, Arc<Table<InternalKeyComparator<C>, Box<dyn RandomAccessFile>>>>,
+}
+
+impl<C: Comparator + Clone> TableCache<C> {
+    pub fn new(dbname: &Path, options: TableOptions<C>) -> Self {
+        let capacity = options
+            .max_open_files
+            .saturating_sub(NUM_NON_TABLE_CACHE_FILES);
+        TableCache {
+            dbname: dbname.to_owned(),
+            options,
+            cache: ShardedLRUCache::new(capacity),
+        }
+    }
+
+    /// Return an iterator for the specified file number (the corresponding
+    /// file length must be exactly `file_size` bytes).  The iterator keeps
+    /// the table open until it is dropped, even if the table is evicted
+    /// from the cache meanwhile.
+    pub fn new_iterator(
+        &self,
+        options: ReadOptions<'static>,
+        file_number: u64,
+        file_size: u64,
+    ) -> Result<TableIterator<InternalKeyComparator<C>, Box<dyn RandomAccessFile>>> {
+        let handle = self.find_table(file_number, file_size)?;
+        let table = handle.value().clone();
+        Ok(table.new_iterator(options))
+    }
+
+    /// Seeks the first entry at or past the internal key `key` in the
+    /// specified file and returns it.
+    pub fn get(
+        &self,
+        options: ReadOptions,
+        file_number: u64,
+        file_size: u64,
+        key: &[u8],
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let handle = self.find_table(file_number, file_size)?;
+        handle.value().get(options, key)
+    }
+
+    /// Evict any entry for the specified file number
+    pub fn evict(&self, file_number: u64) {
+        self.cache.erase(&file_number);
+    }
+
+    fn find_table(&self, file_number: u64, file_size: u64) -> Result<TableHandle<C>> {
+        if let Some(handle) = self.cache.lookup(&file_number) {
+            return Ok(handle);
+        }
+        let env = &self.options.env;
+        let file = match env.new_random_access_file(&generate_filename(
+            &self.dbname,
+            FileType::Table,
+            file_number,
+        )) {
+            Ok(file) => file,
+            // Fall back to the old-fashioned ".sst" name
+            Err(e) if e.io_error().map(|e| e.kind()) == Some(io::ErrorKind::NotFound) => {
+                env.new_random_access_file(&self.dbname.join(format!("{:06}.sst", file_number)))?
+            }
+            Err(e) => return Err(e),
+        };
+        // We do not cache error results so that if the error is transient,
+        // or somebody repairs the file, we recover automatically.
+        let table = Table::open(self.options.clone(), file, file_number, file_size)?;
+        Ok(self.cache.insert(file_number, Arc::new(table), 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::format::{InternalKey, ValueType};
+    use crate::db::ldbiterator::LdbIterator;
+    use crate::db::table::TableBuilder;
+    use crate::env::MemEnv;
+    use crate::util::comparator::BytewiseComparator;
+
+    fn ikey(key: &str) -> Vec<u8> {
+        InternalKey::new(key.as_bytes(), 1, ValueType::KTypeValue)
+            .encode()
+            .to_vec()
+    }
+
+    // Writes a table holding the single entry `key` and returns its size
+    fn write_table(
+        dbname: &Path,
+        options: &TableOptions<BytewiseComparator>,
+        number: u64,
+        key: &str,
+    ) -> u64 {
+        let path = generate_filename(dbname, FileType::Table, number);
+        let file = options.env.new_writable_file(&path).unwrap();
+        let mut builder = TableBuilder::new(options.clone(), file);
+        builder.add(&ikey(key), key.as_bytes()).unwrap();
+        builder.finish().unwrap();
+        options.env.get_file_size(&path).unwrap()
+    }
+
+    #[test]
+    fn test_bounded_open_tables() {
+        let dbname = Path::new("/table_cache");
+        let options = table_options(&Options {
+            max_open_files: NUM_NON_TABLE_CACHE_FILES + 16,
+            env: Arc::new(MemEnv::default()),
+            ..Options::<BytewiseComparator>::default()
+        });
+        let env = options.env.clone();
+        let sizes: Vec<u64> = (0..100)
+            .map(|i| write_table(dbname, &options, i, &format!("key{}", i)))
+            .collect();
+        let cache = TableCache::new(dbname, options);
+
+        let mut iter = cache
+            .new_iterator(ReadOptions::default(), 0, sizes[0])
+            .unwrap();
+        for (i, size) in sizes.iter().enumerate() {
+            let key = format!("key{}", i);
+            let (found, value) = cache
+                .get(ReadOptions::default(), i as u64, *size, &ikey(&key))
+                .unwrap()
+                .unwrap();
+            assert_eq!(found, ikey(&key));
+            assert_eq!(value, key.as_bytes());
+        }
+        // One open table per shard at most
+        assert!(cache.cache.total_charge() <= 16);
+
+        // The iterator keeps its table alive after eviction and deletion
+        cache.evict(0);
+        env.remove(&generate_filename(dbname, FileType::Table, 0))
+            .unwrap();
+        iter.seek_to_first();
+        assert_eq!(iter.key(), ikey("key0").as_slice());
+        assert!(cache
+            .get(ReadOptions::default(), 0, sizes[0], &ikey("key0"))
+            .is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::BTreeSet;
+use std::fmt::{Debug, Formatter};
+
+use super::format::InternalKey;
+use super::SequenceNumber;
+use crate::util::coding::{
+    put_length_prefixed_slice, put_varint_32, put_varint_64, read_length_prefixed_slice,
+    read_varint_32, read_varint_64,
+};
+use crate::util::status::{Error, ErrorKind, Result};
+
+/// The max number of levels in the LSM tree
+pub const NUM_LEVELS: usize = 7;
+
+// Tag numbers for serialized VersionEdit.  These numbers are written to
+// disk and should not be changed.
+const COMPARATOR: u32 = 1;
+const LOG_NUMBER: u32 = 2;
+const NEXT_FILE_NUMBER: u32 = 3;
+const LAST_SEQUENCE: u32 = 4;
+const COMPACT_POINTER: u32 = 5;
+const DELETED_FILE: u32 = 6;
+const NEW_FILE: u32 = 7;
+// 8 was used for large value refs
+const PREV_LOG_NUMBER: u32 = 9;
+
+/// The metadata of a table file
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct FileMetaData {
+    /// Seeks allowed until compaction
+    pub allowed_seeks: i32,
+    pub number: u64,
+    /// File size in bytes
+    pub file_size: u64,
+    /// Smallest internal key served by table
+    pub smallest: InternalKey,
+    /// Largest internal key served by table
+    pub largest: InternalKey,
+}
+
+impl Debug for FileMetaData {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}[{:?} .. {:?}]",
+            self.number, self.file_size, self.smallest, self.largest
+        )
+    }
+}
+
+/// A `VersionEdit` records the changes between two versions of the
+/// database: the files added and removed and the new values of the
+/// counters kept in the MANIFEST.
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct VersionEdit {
+    pub comparator: Option<String>,
+    pub log_number: Option<u64>,
+    pub prev_log_number: Option<u64>,
+    pub next_file_number: Option<u64>,
+    pub last_sequence: Option<SequenceNumber>,
+
+    pub compact_pointers: Vec<(usize, InternalKey)>,
+    pub deleted_files: BTreeSet<(usize, u64)>,
+    pub new_files: Vec<(usize, FileMetaData)>,
+}
+
+impl VersionEdit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn set_comparator_name(&mut self, name: &str) {
+        self.comparator = Some(name.to_owned());
+    }
+
+    pub fn set_log_number(&mut self, num: u64) {
+        self.log_number = Some(num);
+    }
+
+    pub fn set_prev_log_number(&mut self, num: u64) {
+        self.prev_log_number = Some(num);
+    }
+
+    pub fn set_next_file(&mut self, num: u64) {
+        self.next_file_number = Some(num);
+    }
+
+    pub fn set_last_sequence(&mut self, seq: SequenceNumber) {
+        self.last_sequence = Some(seq);
+    }
+
+    pub fn set_compact_pointer(&mut self, level: usize, key: InternalKey) {
+        self.compact_pointers.push((level, key));
+    }
+
+    /// Add the specified file at the specified level.
+    /// REQUIRES: `smallest` and `largest` are smallest and largest keys in file
+    pub fn add_file(
+        &mut self,
+        level: usize,
+        file: u64,
+        file_size: u64,
+        smallest: InternalKey,
+        largest: InternalKey,
+    ) {
+        self.new_files.push((
+            level,
+            FileMetaData {
+                allowed_seeks: 0,
+                number: file,
+                file_size,
+                smallest,
+                largest,
+            },
+        ));
+    }
+
+    /// Delete the specified "file" from the specified "level".
+    pub fn delete_file(&mut self, level: usize, file: u64) {
+        self.deleted_files.insert((level, file));
+    }
+
+    pub fn encode_to(&self, dst: &mut Vec<u8>) {
+        if let Some(name) = &self.comparator {
+            put_varint_32(dst, COMPARATOR);
+            put_length_prefixed_slice(dst, name.as_bytes());
+        }
+        if let Some(num) = self.log_number {
+            put_varint_32(dst, LOG_NUMBER);
+            put_varint_64(dst, num);
+        }
+        if let Some(num) = self.prev_log_number {
+            put_varint_32(dst, PREV_LOG_NUMBER);
+            put_varint_64(dst, num);
+        }
+        if let Some(num) = self.next_file_number {
+            put_varint_32(dst, NEXT_FILE_NUMBER);
+            put_varint_64(dst, num);
+        }
+        if let Some(seq) = self.last_sequence {
+            put_varint_32(dst, LAST_SEQUENCE);
+            put_varint_64(dst, seq);
+        }
+        for (level, key) in self.compact_pointers.iter() {
+            put_varint_32(dst, COMPACT_POINTER);
+            put_varint_32(dst, *level as u32);
+            put_length_prefixed_slice(dst, key.encode());
+        }
+        for (level, number) in self.deleted_files.iter() {
+            put_varint_32(dst, DELETED_FILE);
+            put_varint_32(dst, *level as u32);
+            put_varint_64(dst, *number);
+        }
+        for (level, f) in self.new_files.iter() {
+            put_varint_32(dst, NEW_FILE);
+            put_varint_32(dst, *level as u32);
+            put_varint_64(dst, f.number);
+            put_varint_64(dst, f.file_size);
+            put_length_prefixed_slice(dst, f.smallest.encode());
+            put_length_prefixed_slice(dst, f.largest.encode());
+        }
+    }
+
+    pub fn decode_from(src: &[u8]) -> Result<Self> {
+        let mut edit = VersionEdit::new();
+        let mut input = src;
+        while !input.is_empty() {
+            let tag = match read_varint_32(&mut input) {
+                Some(tag) => tag,
+                None => return Err(corruption("invalid tag")),
+            };
+            match tag {
+                COMPARATOR => match read_length_prefixed_slice(&mut input) {
+                    Some(name) => {
+                        edit.comparator = Some(String::from_utf8_lossy(name).into_owned())
+                    }
+                    None => return Err(corruption("comparator name")),
+                },
+                LOG_NUMBER => match read_varint_64(&mut input) {
+                    Some(num) => edit.log_number = Some(num),
+                    None => return Err(corruption("log number")),
+                },
+                PREV_LOG_NUMBER => match read_varint_64(&mut input) {
+                    Some(num) => edit.prev_log_number = Some(num),
+                    None => return Err(corruption("previous log number")),
+                },
+                NEXT_FILE_NUMBER => match read_varint_64(&mut input) {
+                    Some(num) => edit.next_file_number = Some(num),
+                    None => return Err(corruption("next file number")),
+                },
+                LAST_SEQUENCE => match read_varint_64(&mut input) {
+                    Some(seq) => edit.last_sequence = Some(seq),
+                    None => return Err(corruption("last sequence number")),
+                },
+                COMPACT_POINTER => match (read_level(&mut input), read_internal_key(&mut input)) {
+                    (Some(level), Some(key)) => edit.compact_pointers.push((level, key)),
+                    _ => return Err(corruption("compaction pointer")),
+                },
+                DELETED_FILE => match (read_level(&mut input), read_varint_64(&mut input)) {
+                    (Some(level), Some(number)) => {
+                        edit.deleted_files.insert((level, number));
+                    }
+                    _ => return Err(corruption("deleted file")),
+                },
+                NEW_FILE => {
+                    match (
+                        read_level(&mut input),
+                        read_varint_64(&mut input),
+                        read_varint_64(&mut input),
+                        read_internal_key(&mut input),
+                        read_internal_key(&mut input),
+                    ) {
+                        (
+                            Some(level),
+                            Some(number),
+                            Some(file_size),
+                            Some(smallest),
+                            Some(largest),
+                        ) => edit.add_file(level, number, file_size, smallest, largest),
+                        _ => return Err(corruption("new-file entry")),
+                    }
+                }
+                _ => return Err(corruption("unknown tag")),
+            }
+        }
+        Ok(edit)
+    }
+}
+
+impl Debug for VersionEdit {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "VersionEdit {{")?;
+        if let Some(name) = &self.comparator {
+            write!(f, "\n  Comparator: {}", name)?;
+        }
+        if let Some(num) = self.log_number {
+            write!(f, "\n  LogNumber: {}", num)?;
+        }
+        if let Some(num) = self.prev_log_number {
+            write!(f, "\n  PrevLogNumber: {}", num)?;
+        }
+        if let Some(num) = self.next_file_number {
+            write!(f, "\n  NextFile: {}", num)?;
+        }
+        if let Some(seq) = self.last_sequence {
+            write!(f, "\n  LastSeq: {}", seq)?;
+        }
+        for (level, key) in self.compact_pointers.iter() {
+            write!(f, "\n  CompactPointer: {} {:?}", level, key)?;
+        }
+        for (level, number) in self.deleted_files.iter() {
+            write!(f, "\n  RemoveFile: {} {}", level, number)?;
+        }
+        for (level, file) in self.new_files.iter() {
+            write!(f, "\n  AddFile: {} {:?}", level, file)?;
+        }
+        write!(f, "\n}}\n")
+    }
+}
+
+fn corruption(msg: &str) -> Error {
+    Error::from(ErrorKind::Corruption(format!("VersionEdit: {}", msg)))
+}
+
+fn read_level(input: &mut &[u8]) -> Option<usize> {
+    let level = read_varint_32(input)? as usize;
+    if level < NUM_LEVELS {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+fn read_internal_key(input: &mut &[u8]) -> Option<InternalKey> {
+    read_length_prefixed_slice(input).map(InternalKey::decode_from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::format::ValueType;
+
+    fn assert_encode_decode(edit: &VersionEdit) {
+        let mut encoded = vec![];
+        edit.encode_to(&mut encoded);
+        let parsed = VersionEdit::decode_from(&encoded).unwrap();
+        let mut encoded2 = vec![];
+        parsed.encode_to(&mut encoded2);
+        assert_eq!(encoded, encoded2);
+        assert_eq!(edit, &parsed);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let big = 1u64 << 50;
+        let mut edit = VersionEdit::new();
+        for i in 0..4 {
+            assert_encode_decode(&edit);
+            edit.add_file(
+                3,
+                big + 300 + i,
+                big + 400 + i,
+                InternalKey::new(b"foo", big + 500 + i, ValueType::KTypeValue),
+                InternalKey::new(b"zoo", big + 600 + i, ValueType::KTypeDeletion),
+            );
+            edit.delete_file(4, big + 700 + i);
+            edit.set_compact_pointer(
+                i as usize,
+                InternalKey::new(b"x", big + 900 + i, ValueType::KTypeValue),
+            );
+        }
+        edit.set_comparator_name("foo");
+        edit.set_log_number(big + 100);
+        edit.set_next_file(big + 200);
+        edit.set_last_sequence(big + 1000);
+        assert_encode_decode(&edit);
+        edit.set_prev_log_number(big + 50);
+        assert_encode_decode(&edit);
+    }
+
+    #[test]
+    fn test_decode_corruption() {
+        let mut edit = VersionEdit::new();
+        edit.set_comparator_name("leveldb.BytewiseComparator");
+        edit.add_file(
+            1,
+            10,
+            100,
+            InternalKey::new(b"a", 1, ValueType::KTypeValue),
+            InternalKey::new(b"b", 2, ValueType::KTypeValue),
+        );
+        let mut encoded = vec![];
+        edit.encode_to(&mut encoded);
+        // Truncated new-file entry
+        let err = VersionEdit::decode_from(&encoded[..encoded.len() - 1]).unwrap_err();
+        match err.kind() {
+            ErrorKind::Corruption(msg) => assert_eq!(msg, "VersionEdit: new-file entry"),
+            _ => panic!("decoding a truncated edit should fail"),
+        }
+        // Unknown tag
+        let mut bad = encoded.clone();
+        put_varint_32(&mut bad, 8);
+        let err = VersionEdit::decode_from(&bad).unwrap_err();
+        match err.kind() {
+            ErrorKind::Corruption(msg) => assert_eq!(msg, "VersionEdit: unknown tag"),
+            _ => panic!("decoding an unknown tag should fail"),
+        }
+        // Level out of range
+        let mut bad = vec![];
+        put_varint_32(&mut bad, DELETED_FILE);
+        put_varint_32(&mut bad, NUM_LEVELS as u32);
+        put_varint_64(&mut bad, 1);
+        assert!(VersionEdit::decode_from(&bad).is_err());
+    }
+}
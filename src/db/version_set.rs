@@ -0,0 +1,1737 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Weak};
+
+use super::filename::{generate_filename, set_current_file, FileType};
+use super::format::{
+    extract_user_key, InternalKey, InternalKeyComparator, LookUpKey, ParsedInteralKey, ValueType,
+    K_MAX_SEQUENCE_NUMBER as MAX_SEQUENCE_NUMBER, VALUE_TYPE_FOR_SEEK,
+};
+use super::ldbiterator::LdbIterator;
+use super::log::{Reader, Reporter, Writer};
+use super::table::two_level_iterator::{DerivedIterFactory, TwoLevelIterator};
+use super::table::{MergingIterator, TableIterator};
+use super::table_cache::TableCache;
+use super::version_edit::{FileMetaData, VersionEdit, NUM_LEVELS};
+use super::SequenceNumber;
+use crate::env::{read_file_to_string, RandomAccessFile, WritableFile};
+use crate::options::{Options, ReadOptions};
+use crate::util::coding::{decode_fixed_64, put_fixed_64};
+use crate::util::comparator::Comparator;
+use crate::util::status::{Error, ErrorKind, Result};
+
+/// A `Version` is the set of table files of every level at some point in
+/// time.  Versions are immutable; they are shared through `Arc`, so an
+/// iterator or a read holding a `Version` keeps its files alive while
+/// compactions install newer versions.
+pub struct Version<C: Comparator + Clone> {
+    options: Arc<Options<C>>,
+    icmp: InternalKeyComparator<C>,
+    table_cache: Arc<TableCache<C>>,
+    // List of files per level
+    files: Vec<Vec<Arc<FileMetaData>>>,
+
+    // Level that should be compacted next and its compaction score.
+    // Score < 1 means compaction is not strictly needed.  These fields
+    // are initialized by `finalize`.
+    compaction_score: f64,
+    compaction_level: usize,
+}
+
+impl<C: Comparator + Clone> Version<C> {
+    fn new(
+        options: Arc<Options<C>>,
+        icmp: InternalKeyComparator<C>,
+        table_cache: Arc<TableCache<C>>,
+    ) -> Self {
+        Version {
+            options,
+            icmp,
+            table_cache,
+            files: vec![vec![]; NUM_LEVELS],
+            compaction_score: -1.0,
+            compaction_level: 0,
+        }
+    }
+
+    /// Return the files of `level`.  Files in levels > 0 are sorted by key
+    /// and do not overlap; level-0 files are sorted by file number.
+    pub fn files(&self, level: usize) -> &[Arc<FileMetaData>] {
+        &self.files[level]
+    }
+
+    /// Return the number of table files at `level`
+    pub fn num_files(&self, level: usize) -> usize {
+        self.files[level].len()
+    }
+
+    /// Lookup the value for `key`.  Returns `Ok(None)` if the newest entry
+    /// for the key is a deletion or if the key is in no table.
+    pub fn get(&self, options: ReadOptions, key: &LookUpKey) -> Result<Option<Vec<u8>>> {
+        let ikey = key.internal_key();
+        let user_key = key.user_key();
+        let ucmp = &self.icmp.user_comparator;
+
+        // We can search level-by-level since entries never hop across
+        // levels.  Therefore we are guaranteed that if we find data
+        // in a smaller level, later levels are irrelevant.
+        for level in 0..NUM_LEVELS {
+            let files = &self.files[level];
+            if files.is_empty() {
+                continue;
+            }
+            let candidates: Vec<&Arc<FileMetaData>> = if level == 0 {
+                // Level-0 files may overlap each other.  Find all files that
+                // overlap user_key and process them in order from newest to oldest.
+                let mut tmp: Vec<&Arc<FileMetaData>> = files
+                    .iter()
+                    .filter(|f| {
+                        ucmp.compare(user_key, f.smallest.user_key()) != Ordering::Less
+                            && ucmp.compare(user_key, f.largest.user_key()) != Ordering::Greater
+                    })
+                    .collect();
+                tmp.sort_by(|a, b| b.number.cmp(&a.number));
+                tmp
+            } else {
+                // Binary search to find earliest index whose largest key >= ikey.
+                let index = find_file(&self.icmp, files, ikey);
+                match files.get(index) {
+                    // All of "f" is past any data for user_key
+                    Some(f) if ucmp.compare(user_key, f.smallest.user_key()) != Ordering::Less => {
+                        vec![f]
+                    }
+                    _ => vec![],
+                }
+            };
+
+            for f in candidates {
+                if let Some((found_key, value)) =
+                    self.table_cache.get(options, f.number, f.file_size, ikey)?
+                {
+                    match ParsedInteralKey::decode_from(&found_key) {
+                        Some(parsed) => {
+                            if ucmp.compare(extract_user_key(&found_key), user_key)
+                                == Ordering::Equal
+                            {
+                                return match parsed.value_type() {
+                                    ValueType::KTypeValue => Ok(Some(value)),
+                                    ValueType::KTypeDeletion => Ok(None),
+                                };
+                            }
+                        }
+                        None => {
+                            return Err(ErrorKind::Corruption(format!(
+                                "corrupted key for {:?} in table {}",
+                                user_key, f.number
+                            ))
+                            .into())
+                        }
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<C: Comparator + Clone> Version<C> {
+    /// Returns true iff some file in the specified level overlaps
+    /// some part of `[smallest_user_key, largest_user_key]`.
+    /// `smallest_user_key == None` represents a key smaller than all keys in the DB.
+    /// `largest_user_key == None` represents a key largest than all keys in the DB.
+    pub fn overlap_in_level(
+        &self,
+        level: usize,
+        smallest_user_key: Option<&[u8]>,
+        largest_user_key: Option<&[u8]>,
+    ) -> bool {
+        some_file_overlaps_range(
+            &self.icmp,
+            level > 0,
+            &self.files[level],
+            smallest_user_key,
+            largest_user_key,
+        )
+    }
+
+    /// Return the level at which we should place a new memtable compaction
+    /// result that covers the range `[smallest_user_key, largest_user_key]`.
+    pub fn pick_level_for_memtable_output(
+        &self,
+        smallest_user_key: &[u8],
+        largest_user_key: &[u8],
+    ) -> usize {
+        let mut level = 0;
+        if !self.overlap_in_level(0, Some(smallest_user_key), Some(largest_user_key)) {
+            // Push to next level if there is no overlap in next level,
+            // and the #bytes overlapping in the level after that are limited.
+            let start =
+                InternalKey::new(smallest_user_key, MAX_SEQUENCE_NUMBER, VALUE_TYPE_FOR_SEEK);
+            let limit = InternalKey::new(largest_user_key, 0, ValueType::KTypeDeletion);
+            while level < MAX_MEM_COMPACT_LEVEL {
+                if self.overlap_in_level(level + 1, Some(smallest_user_key), Some(largest_user_key))
+                {
+                    break;
+                }
+                if level + 2 < NUM_LEVELS {
+                    // Check that file does not overlap too many grandparent bytes.
+                    let overlaps =
+                        self.get_overlapping_inputs(level + 2, Some(&start), Some(&limit));
+                    if total_file_size(&overlaps) > max_grandparent_overlap_bytes(&self.options) {
+                        break;
+                    }
+                }
+                level += 1;
+            }
+        }
+        level
+    }
+
+    /// Return all files in `level` that overlap `[begin, end]`.
+    /// `begin == None` means before all keys and `end == None` means
+    /// after all keys.
+    pub fn get_overlapping_inputs(
+        &self,
+        level: usize,
+        begin: Option<&InternalKey>,
+        end: Option<&InternalKey>,
+    ) -> Vec<Arc<FileMetaData>> {
+        let ucmp = &self.icmp.user_comparator;
+        let mut user_begin = begin.map(|k| k.user_key().to_vec());
+        let mut user_end = end.map(|k| k.user_key().to_vec());
+        let mut inputs = vec![];
+        let mut i = 0;
+        while i < self.files[level].len() {
+            let f = &self.files[level][i];
+            i += 1;
+            let file_start = f.smallest.user_key();
+            let file_limit = f.largest.user_key();
+            if let Some(b) = &user_begin {
+                if ucmp.compare(file_limit, b) == Ordering::Less {
+                    // "f" is completely before specified range; skip it
+                    continue;
+                }
+            }
+            if let Some(e) = &user_end {
+                if ucmp.compare(file_start, e) == Ordering::Greater {
+                    // "f" is completely after specified range; skip it
+                    continue;
+                }
+            }
+            inputs.push(f.clone());
+            if level == 0 {
+                // Level-0 files may overlap each other.  So check if the newly
+                // added file has expanded the range.  If so, restart search.
+                if let Some(b) = &user_begin {
+                    if ucmp.compare(file_start, b) == Ordering::Less {
+                        user_begin = Some(file_start.to_vec());
+                        inputs.clear();
+                        i = 0;
+                        continue;
+                    }
+                }
+                if let Some(e) = &user_end {
+                    if ucmp.compare(file_limit, e) == Ordering::Greater {
+                        user_end = Some(file_limit.to_vec());
+                        inputs.clear();
+                        i = 0;
+                    }
+                }
+            }
+        }
+        inputs
+    }
+}
+
+impl<C: Comparator + Clone> Debug for Version<C> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for (level, files) in self.files.iter().enumerate() {
+            // E.g.,
+            //   --- level 1 ---
+            //   17:123['a' .. 'd']
+            //   20:43['e' .. 'g']
+            writeln!(f, "--- level {} ---", level)?;
+            for file in files {
+                writeln!(f, " {:?}", file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: Comparator + Clone + 'static> Version<C> {
+    /// Append to `iters` a sequence of iterators that will
+    /// yield the contents of this Version when merged together.
+    /// The iterators keep the version, and so its files, alive.
+    pub fn add_iterators(
+        self: &Arc<Self>,
+        options: ReadOptions<'static>,
+        iters: &mut Vec<Box<dyn LdbIterator>>,
+    ) -> Result<()> {
+        // Merge all level zero files together since they may overlap
+        for f in self.files[0].iter() {
+            let iter = self
+                .table_cache
+                .new_iterator(options, f.number, f.file_size)?;
+            iters.push(Box::new(iter));
+        }
+
+        // For levels > 0, we can use a concatenating iterator that sequentially
+        // walks through the non-overlapping files in the level, opening them
+        // lazily.
+        for level in 1..NUM_LEVELS {
+            if !self.files[level].is_empty() {
+                iters.push(Box::new(TwoLevelIterator::new(
+                    LevelFileNumIterator::new(self.clone(), level),
+                    TableIterFactory {
+                        table_cache: self.table_cache.clone(),
+                        options,
+                    },
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// An internal iterator over the files of a level > 0.  For a given
+// entry, key() is the largest key that occurs in the file, and value()
+// is a 16-byte value containing the file number and file size, both
+// encoded using `put_fixed_64`.
+struct LevelFileNumIterator<C: Comparator + Clone> {
+    version: Arc<Version<C>>,
+    level: usize,
+    // Equal to the number of files of the level when the iterator is invalid
+    index: usize,
+    value_buf: Vec<u8>,
+}
+
+impl<C: Comparator + Clone> LevelFileNumIterator<C> {
+    fn new(version: Arc<Version<C>>, level: usize) -> Self {
+        let index = version.files[level].len();
+        LevelFileNumIterator {
+            version,
+            level,
+            index,
+            value_buf: Vec::with_capacity(16),
+        }
+    }
+
+    fn files(&self) -> &[Arc<FileMetaData>] {
+        &self.version.files[self.level]
+    }
+
+    fn fill_value(&mut self) {
+        self.value_buf.clear();
+        if self.valid() {
+            let (number, file_size) = {
+                let f = &self.files()[self.index];
+                (f.number, f.file_size)
+            };
+            put_fixed_64(&mut self.value_buf, number);
+            put_fixed_64(&mut self.value_buf, file_size);
+        }
+    }
+}
+
+impl<C: Comparator + Clone> LdbIterator for LevelFileNumIterator<C> {
+    fn valid(&self) -> bool {
+        self.index < self.files().len()
+    }
+
+    fn seek_to_first(&mut self) {
+        self.index = 0;
+        self.fill_value();
+    }
+
+    fn seek_to_last(&mut self) {
+        let len = self.files().len();
+        self.index = if len == 0 { 0 } else { len - 1 };
+        self.fill_value();
+    }
+
+    fn seek(&mut self, target: &[u8]) {
+        self.index = find_file(&self.version.icmp, self.files(), target);
+        self.fill_value();
+    }
+
+    fn next(&mut self) {
+        assert!(self.valid());
+        self.index += 1;
+        self.fill_value();
+    }
+
+    fn key(&self) -> &[u8] {
+        assert!(self.valid());
+        self.files()[self.index].largest.encode()
+    }
+
+    fn value(&self) -> &[u8] {
+        assert!(self.valid());
+        &self.value_buf
+    }
+
+    fn prev(&mut self) {
+        assert!(self.valid());
+        self.index = if self.index == 0 {
+            // Marks as invalid
+            self.files().len()
+        } else {
+            self.index - 1
+        };
+        self.fill_value();
+    }
+
+    fn status(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Opens the table pointed by an entry of a `LevelFileNumIterator`
+struct TableIterFactory<C: Comparator + Clone> {
+    table_cache: Arc<TableCache<C>>,
+    options: ReadOptions<'static>,
+}
+
+impl<C: Comparator + Clone> DerivedIterFactory for TableIterFactory<C> {
+    type Iter = TableIterator<InternalKeyComparator<C>, Box<dyn RandomAccessFile>>;
+
+    fn derive(&self, value: &[u8]) -> Result<Self::Iter> {
+        if value.len() != 16 {
+            return Err(ErrorKind::Corruption(
+                "FileReader invoked with unexpected value".to_owned(),
+            )
+            .into());
+        }
+        self.table_cache.new_iterator(
+            self.options,
+            decode_fixed_64(value),
+            decode_fixed_64(&value[8..]),
+        )
+    }
+}
+
+/// Return the smallest index `i` such that `files[i].largest >= key`.
+/// Return `files.len()` if there is no such file.
+/// REQUIRES: `files` contains a sorted list of non-overlapping files.
+pub fn find_file<C: Comparator + Clone>(
+    icmp: &InternalKeyComparator<C>,
+    files: &[Arc<FileMetaData>],
+    key: &[u8],
+) -> usize {
+    let mut left = 0;
+    let mut right = files.len();
+    while left < right {
+        let mid = (left + right) / 2;
+        if icmp.compare(files[mid].largest.encode(), key) == Ordering::Less {
+            // Key at "mid.largest" is < "target".  Therefore all
+            // files at or before "mid" are uninteresting.
+            left = mid + 1;
+        } else {
+            // Key at "mid.largest" is >= "target".  Therefore all files
+            // after "mid" are uninteresting.
+            right = mid;
+        }
+    }
+    right
+}
+
+// Maximum level to which a new compacted memtable is pushed if it
+// does not create overlap.  We try to push to level 2 to avoid the
+// relatively expensive level 0=>1 compactions and to avoid some
+// expensive manifest file operations.  We do not push all the way to
+// the largest level since that can generate a lot of wasted disk
+// space if the same key space is being repeatedly overwritten.
+const MAX_MEM_COMPACT_LEVEL: usize = 2;
+
+// Maximum bytes of overlaps in grandparent (i.e., level+2) before we
+// stop building a single file in a level->level+1 compaction.
+fn max_grandparent_overlap_bytes<C: Comparator>(options: &Options<C>) -> u64 {
+    10 * options.max_file_size as u64
+}
+
+// Maximum number of bytes in all compacted files.  We avoid expanding
+// the lower level file set of a compaction if it would make the
+// total compaction cover more than this many bytes.
+fn expanded_compaction_byte_size_limit<C: Comparator>(options: &Options<C>) -> u64 {
+    25 * options.max_file_size as u64
+}
+
+/// Level-0 compaction is started when we hit this many files.
+pub const L0_COMPACTION_TRIGGER: usize = 4;
+
+/// Soft limit on number of level-0 files.  We slow down writes at this point.
+pub const L0_SLOWDOWN_WRITES_TRIGGER: usize = 8;
+
+/// Maximum number of level-0 files.  We stop writes at this point.
+pub const L0_STOP_WRITES_TRIGGER: usize = 12;
+
+// The maximum total size of the files at `level`.  Each level may hold
+// ten times more data than the previous one.
+fn max_bytes_for_level(mut level: usize) -> f64 {
+    // Note: the result for level zero is not really used since we set
+    // the level-0 compaction threshold based on number of files.
+
+    // Result for both level-0 and level-1
+    let mut result = 10. * 1_048_576.0;
+    while level > 1 {
+        result *= 10.0;
+        level -= 1;
+    }
+    result
+}
+
+fn after_file<C: Comparator>(ucmp: &C, user_key: Option<&[u8]>, f: &FileMetaData) -> bool {
+    // None user_key occurs before all keys and is therefore never after `f`
+    user_key.map_or(false, |k| {
+        ucmp.compare(k, f.largest.user_key()) == Ordering::Greater
+    })
+}
+
+fn before_file<C: Comparator>(ucmp: &C, user_key: Option<&[u8]>, f: &FileMetaData) -> bool {
+    // None user_key occurs after all keys and is therefore never before `f`
+    user_key.map_or(false, |k| {
+        ucmp.compare(k, f.smallest.user_key()) == Ordering::Less
+    })
+}
+
+/// Returns true iff some file in `files` overlaps the user key range
+/// `[smallest_user_key, largest_user_key]`.
+/// `None` bounds are treated as unbounded.
+/// REQUIRES: If `disjoint_sorted_files`, `files` contains disjoint ranges
+///           in sorted order.
+pub fn some_file_overlaps_range<C: Comparator + Clone>(
+    icmp: &InternalKeyComparator<C>,
+    disjoint_sorted_files: bool,
+    files: &[Arc<FileMetaData>],
+    smallest_user_key: Option<&[u8]>,
+    largest_user_key: Option<&[u8]>,
+) -> bool {
+    let ucmp = &icmp.user_comparator;
+    if !disjoint_sorted_files {
+        // Need to check against all files
+        return files.iter().any(|f| {
+            !after_file(ucmp, smallest_user_key, f) && !before_file(ucmp, largest_user_key, f)
+        });
+    }
+
+    // Binary search over file list
+    let index = match smallest_user_key {
+        // Find the earliest possible internal key for smallest_user_key
+        Some(k) => {
+            let small = InternalKey::new(k, MAX_SEQUENCE_NUMBER, VALUE_TYPE_FOR_SEEK);
+            find_file(icmp, files, small.encode())
+        }
+        None => 0,
+    };
+    match files.get(index) {
+        // beginning of range is after all files, so no overlap.
+        None => false,
+        Some(f) => !before_file(ucmp, largest_user_key, f),
+    }
+}
+
+/// A helper class so we can efficiently apply a whole sequence
+/// of edits to a particular state without creating intermediate
+/// Versions that contain full copies of the intermediate state.
+pub struct VersionBuilder<C: Comparator + Clone> {
+    base: Arc<Version<C>>,
+    deleted_files: Vec<HashSet<u64>>,
+    added_files: Vec<Vec<Arc<FileMetaData>>>,
+}
+
+impl<C: Comparator + Clone> VersionBuilder<C> {
+    /// Initialize a builder with the files from `base`
+    pub fn new(base: Arc<Version<C>>) -> Self {
+        VersionBuilder {
+            base,
+            deleted_files: vec![HashSet::new(); NUM_LEVELS],
+            added_files: vec![vec![]; NUM_LEVELS],
+        }
+    }
+
+    /// Apply all of the edits in `edit` to the current state.
+    pub fn apply(&mut self, edit: &VersionEdit, compact_pointers: &mut [InternalKey]) {
+        // Update compaction pointers
+        for (level, key) in edit.compact_pointers.iter() {
+            compact_pointers[*level] = key.clone();
+        }
+
+        // Delete files
+        for (level, number) in edit.deleted_files.iter() {
+            self.deleted_files[*level].insert(*number);
+        }
+
+        // Add new files
+        for (level, f) in edit.new_files.iter() {
+            let mut f = f.clone();
+            // We arrange to automatically compact this file after
+            // a certain number of seeks.  Let's assume:
+            //   (1) One seek costs 10ms
+            //   (2) Writing or reading 1MB costs 10ms (100MB/s)
+            //   (3) A compaction of 1MB does 25MB of IO:
+            //         1MB read from this level
+            //         10-12MB read from next level (boundaries may be misaligned)
+            //         10-12MB written to next level
+            // This implies that 25 seeks cost the same as the compaction
+            // of 1MB of data.  I.e., one seek costs approximately the
+            // same as the compaction of 40KB of data.  We are a little
+            // conservative and allow approximately one seek for every 16KB
+            // of data before triggering a compaction.
+            f.allowed_seeks = ((f.file_size / 16384) as i32).max(100);
+            self.deleted_files[*level].remove(&f.number);
+            self.added_files[*level].push(Arc::new(f));
+        }
+    }
+
+    /// Save the current state in a new `Version`.
+    pub fn save_to(&mut self, v: &mut Version<C>) {
+        let icmp = self.base.icmp.clone();
+        for level in 0..NUM_LEVELS {
+            // Merge the set of added files with the set of pre-existing files.
+            // Drop any deleted files.  Store the result in `v`.
+            let added = &mut self.added_files[level];
+            added.sort_by(|a, b| by_smallest_key(&icmp, a, b));
+            let base_files = &self.base.files[level];
+            let mut files = Vec::with_capacity(base_files.len() + added.len());
+            let mut base_iter = base_files.iter().peekable();
+            for f in added.iter() {
+                // Add all smaller files listed in base
+                while let Some(base) = base_iter.peek() {
+                    if by_smallest_key(&icmp, base, f) == Ordering::Less {
+                        files.push(base_iter.next().unwrap().clone());
+                    } else {
+                        break;
+                    }
+                }
+                files.push(f.clone());
+            }
+            // Add remaining base files
+            files.extend(base_iter.cloned());
+
+            let deleted = &self.deleted_files[level];
+            files.retain(|f| !deleted.contains(&f.number));
+
+            if level > 0 {
+                // Make sure there is no overlap in levels > 0
+                for pair in files.windows(2) {
+                    assert!(
+                        icmp.compare(pair[0].largest.encode(), pair[1].smallest.encode())
+                            == Ordering::Less,
+                        "overlapping ranges in same level {:?} vs. {:?}",
+                        pair[0].largest,
+                        pair[1].smallest
+                    );
+                }
+            }
+            v.files[level] = files;
+        }
+    }
+}
+
+// Orders files by their smallest key, breaking ties by file number
+fn by_smallest_key<C: Comparator + Clone>(
+    icmp: &InternalKeyComparator<C>,
+    a: &FileMetaData,
+    b: &FileMetaData,
+) -> Ordering {
+    match icmp.compare(a.smallest.encode(), b.smallest.encode()) {
+        Ordering::Equal => a.number.cmp(&b.number),
+        o => o,
+    }
+}
+
+/// `VersionSet` keeps the persistent state of the database: the current
+/// `Version`, the counters recorded in the MANIFEST and the descriptor log
+/// every `VersionEdit` is appended to.
+pub struct VersionSet<C: Comparator + Clone> {
+    dbname: PathBuf,
+    options: Arc<Options<C>>,
+    icmp: InternalKeyComparator<C>,
+    table_cache: Arc<TableCache<C>>,
+    next_file_number: u64,
+    manifest_file_number: u64,
+    last_sequence: SequenceNumber,
+    // 0 or backing store for memtable being compacted
+    log_number: u64,
+    prev_log_number: u64,
+
+    // Opened lazily
+    descriptor_log: Option<Writer<Box<dyn WritableFile>>>,
+    current: Arc<Version<C>>,
+    // Every version handed out, so that the files they reference
+    // are not deleted while they are alive
+    versions: Vec<Weak<Version<C>>>,
+
+    // Per-level key at which the next compaction at that level should start.
+    // Either an empty key, or a valid InternalKey.
+    compact_pointers: Vec<InternalKey>,
+}
+
+impl<C: Comparator + Clone> VersionSet<C> {
+    pub fn new(dbname: &Path, options: Options<C>, table_cache: Arc<TableCache<C>>) -> Self {
+        let options = Arc::new(options);
+        let icmp = InternalKeyComparator::new(options.comparator.clone());
+        let current = Arc::new(Version::new(
+            options.clone(),
+            icmp.clone(),
+            table_cache.clone(),
+        ));
+        VersionSet {
+            dbname: dbname.to_owned(),
+            options,
+            icmp,
+            table_cache,
+            next_file_number: 2,
+            manifest_file_number: 0, // Filled by recover()
+            last_sequence: 0,
+            log_number: 0,
+            prev_log_number: 0,
+            descriptor_log: None,
+            versions: vec![Arc::downgrade(&current)],
+            current,
+            compact_pointers: vec![InternalKey::default(); NUM_LEVELS],
+        }
+    }
+
+    /// Return the current version.
+    pub fn current(&self) -> Arc<Version<C>> {
+        self.current.clone()
+    }
+
+    /// Apply `edit` to the current state and persist it in the MANIFEST.
+    /// A new MANIFEST is created on the first call after `recover`.
+    pub fn log_and_apply(&mut self, edit: &mut VersionEdit) -> Result<()> {
+        match edit.log_number {
+            Some(num) => assert!(num >= self.log_number && num < self.next_file_number),
+            None => edit.set_log_number(self.log_number),
+        }
+        if edit.prev_log_number.is_none() {
+            edit.set_prev_log_number(self.prev_log_number);
+        }
+        edit.set_next_file(self.next_file_number);
+        edit.set_last_sequence(self.last_sequence);
+
+        let mut v = Version::new(
+            self.options.clone(),
+            self.icmp.clone(),
+            self.table_cache.clone(),
+        );
+        let mut builder = VersionBuilder::new(self.current.clone());
+        builder.apply(edit, &mut self.compact_pointers);
+        builder.save_to(&mut v);
+        finalize(&mut v);
+
+        // Initialize new descriptor log file if necessary by creating
+        // a temporary file that contains a snapshot of the current version.
+        let mut new_manifest_file = None;
+        if self.descriptor_log.is_none() {
+            let path = generate_filename(
+                &self.dbname,
+                FileType::Descriptor,
+                self.manifest_file_number,
+            );
+            let mut log = Writer::new(self.options.env.new_writable_file(&path)?);
+            if let Err(e) = self.write_snapshot(&mut log) {
+                let _ = self.options.env.remove(&path);
+                return Err(e);
+            }
+            self.descriptor_log = Some(log);
+            new_manifest_file = Some(path);
+        }
+
+        let mut record = vec![];
+        edit.encode_to(&mut record);
+        let log = self.descriptor_log.as_mut().unwrap();
+        let mut result = log
+            .add_record(&record)
+            .and_then(|_| log.flush())
+            .and_then(|_| log.get_mut().sync());
+        // If we just created a new descriptor file, install it by writing a
+        // new CURRENT file that points to it.
+        if result.is_ok() && new_manifest_file.is_some() {
+            result = set_current_file(
+                self.options.env.as_ref(),
+                &self.dbname,
+                self.manifest_file_number,
+            );
+        }
+        if let Err(e) = result {
+            if let Some(path) = new_manifest_file {
+                self.descriptor_log = None;
+                let _ = self.options.env.remove(&path);
+            }
+            return Err(e);
+        }
+
+        self.append_version(v);
+        self.log_number = edit.log_number.unwrap();
+        self.prev_log_number = edit.prev_log_number.unwrap();
+        Ok(())
+    }
+
+    /// Recover the last saved descriptor from persistent storage.
+    pub fn recover(&mut self) -> Result<()> {
+        // Read "CURRENT" file, which contains a pointer to the current manifest file
+        let current = read_file_to_string(
+            self.options.env.as_ref(),
+            &generate_filename(&self.dbname, FileType::Current, 0),
+        )?;
+        if current.is_empty() || !current.ends_with('\n') {
+            return Err(
+                ErrorKind::Corruption("CURRENT file does not end with newline".to_owned()).into(),
+            );
+        }
+        let manifest = self.dbname.join(&current[..current.len() - 1]);
+        let file = match self.options.env.new_sequential_file(&manifest) {
+            Ok(file) => file,
+            Err(e) if e.io_error().map(|e| e.kind()) == Some(io::ErrorKind::NotFound) => {
+                return Err(ErrorKind::Corruption(format!(
+                    "CURRENT points to a non-existent file: {}",
+                    manifest.display()
+                ))
+                .into())
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut log_number = None;
+        let mut prev_log_number = None;
+        let mut next_file = None;
+        let mut last_sequence = None;
+        let status = Rc::new(RefCell::new(Ok(())));
+        let reporter = LogReporter {
+            status: status.clone(),
+        };
+        let mut reader = Reader::new(file, Some(Box::new(reporter)), true, 0);
+        let mut record = vec![];
+        let mut builder = VersionBuilder::new(self.current.clone());
+        while reader.read_record(&mut record) {
+            let edit = VersionEdit::decode_from(&record)?;
+            if let Some(name) = &edit.comparator {
+                if name.as_str() != self.options.comparator.name() {
+                    return Err(ErrorKind::InvalidArgument(format!(
+                        "{} does not match existing comparator {}",
+                        self.options.comparator.name(),
+                        name
+                    ))
+                    .into());
+                }
+            }
+            builder.apply(&edit, &mut self.compact_pointers);
+
+            if edit.log_number.is_some() {
+                log_number = edit.log_number;
+            }
+            if edit.prev_log_number.is_some() {
+                prev_log_number = edit.prev_log_number;
+            }
+            if edit.next_file_number.is_some() {
+                next_file = edit.next_file_number;
+            }
+            if edit.last_sequence.is_some() {
+                last_sequence = edit.last_sequence;
+            }
+        }
+        status.replace(Ok(()))?;
+
+        let next_file = next_file.ok_or_else(|| {
+            Error::from(ErrorKind::Corruption(
+                "no meta-nextfile entry in descriptor".to_owned(),
+            ))
+        })?;
+        let log_number = log_number.ok_or_else(|| {
+            Error::from(ErrorKind::Corruption(
+                "no meta-lognumber entry in descriptor".to_owned(),
+            ))
+        })?;
+        let last_sequence = last_sequence.ok_or_else(|| {
+            Error::from(ErrorKind::Corruption(
+                "no last-sequence-number entry in descriptor".to_owned(),
+            ))
+        })?;
+        let prev_log_number = prev_log_number.unwrap_or(0);
+
+        let mut v = Version::new(
+            self.options.clone(),
+            self.icmp.clone(),
+            self.table_cache.clone(),
+        );
+        builder.save_to(&mut v);
+        // Install recovered version
+        finalize(&mut v);
+        self.append_version(v);
+        self.manifest_file_number = next_file;
+        self.next_file_number = next_file + 1;
+        self.last_sequence = last_sequence;
+        self.log_number = log_number;
+        self.prev_log_number = prev_log_number;
+        self.mark_file_number_used(prev_log_number);
+        self.mark_file_number_used(log_number);
+        Ok(())
+    }
+
+    /// Allocate and return a new file number
+    pub fn new_file_number(&mut self) -> u64 {
+        let num = self.next_file_number;
+        self.next_file_number += 1;
+        num
+    }
+
+    /// Arrange to reuse `file_number` unless a newer file number has
+    /// already been allocated.
+    /// REQUIRES: `file_number` was returned by a call to `new_file_number()`.
+    pub fn reuse_file_number(&mut self, file_number: u64) {
+        if self.next_file_number == file_number + 1 {
+            self.next_file_number = file_number;
+        }
+    }
+
+    /// Mark the specified file number as used.
+    pub fn mark_file_number_used(&mut self, number: u64) {
+        if self.next_file_number <= number {
+            self.next_file_number = number + 1;
+        }
+    }
+
+    /// Return the current manifest file number
+    pub fn manifest_file_number(&self) -> u64 {
+        self.manifest_file_number
+    }
+
+    /// Return the last sequence number.
+    pub fn last_sequence(&self) -> SequenceNumber {
+        self.last_sequence
+    }
+
+    /// Set the last sequence number to `s`.
+    pub fn set_last_sequence(&mut self, s: SequenceNumber) {
+        assert!(s >= self.last_sequence);
+        self.last_sequence = s;
+    }
+
+    /// Return the current log file number.
+    pub fn log_number(&self) -> u64 {
+        self.log_number
+    }
+
+    /// Return the log file number for the log file that is currently
+    /// being compacted, or zero if there is no such log file.
+    pub fn prev_log_number(&self) -> u64 {
+        self.prev_log_number
+    }
+
+    /// Return the number of table files at `level` in the current version
+    pub fn num_level_files(&self, level: usize) -> usize {
+        self.current.num_files(level)
+    }
+
+    /// Return the combined file size of all files at `level`
+    pub fn num_level_bytes(&self, level: usize) -> u64 {
+        total_file_size(self.current.files(level))
+    }
+
+    /// Return the numbers of the files referenced by any live version.
+    pub fn live_files(&mut self) -> HashSet<u64> {
+        self.versions.retain(|v| v.strong_count() > 0);
+        let mut live = HashSet::new();
+        for v in self.versions.iter().filter_map(Weak::upgrade) {
+            for files in v.files.iter() {
+                live.extend(files.iter().map(|f| f.number));
+            }
+        }
+        live
+    }
+
+    /// Returns true iff some level needs a compaction.
+    pub fn needs_compaction(&self) -> bool {
+        self.current.compaction_score >= 1.0
+    }
+
+    /// Pick level and inputs for a new compaction.
+    /// Returns `None` if there is no compaction to be done.
+    pub fn pick_compaction(&mut self) -> Option<Compaction<C>> {
+        // We prefer compactions triggered by too much data in a level over
+        // the compactions triggered by seeks.
+        if !self.needs_compaction() {
+            return None;
+        }
+        let level = self.current.compaction_level;
+        assert!(level + 1 < NUM_LEVELS);
+        let mut c = Compaction::new(&self.options, level, self.current.clone());
+
+        // Pick the first file that comes after compact_pointer[level]
+        let compact_pointer = &self.compact_pointers[level];
+        if let Some(f) = self.current.files[level].iter().find(|f| {
+            compact_pointer.data().is_empty()
+                || self
+                    .icmp
+                    .compare(f.largest.encode(), compact_pointer.encode())
+                    == Ordering::Greater
+        }) {
+            c.inputs[0].push(f.clone());
+        }
+        if c.inputs[0].is_empty() {
+            // Wrap-around to the beginning of the key space
+            c.inputs[0].push(self.current.files[level][0].clone());
+        }
+
+        // Files in level 0 may overlap each other, so pick up all overlapping ones
+        if level == 0 {
+            let (smallest, largest) = self.get_range(&c.inputs[0]);
+            // Note that the next call will discard the file we placed in
+            // c.inputs[0] earlier and replace it with an overlapping set
+            // which will include the picked file.
+            c.inputs[0] = self
+                .current
+                .get_overlapping_inputs(0, Some(&smallest), Some(&largest));
+            assert!(!c.inputs[0].is_empty());
+        }
+
+        self.setup_other_inputs(&mut c);
+        Some(c)
+    }
+
+    /// Return a compaction for the range `[begin, end]` in the specified
+    /// level.  Returns `None` if there is nothing in that level that
+    /// overlaps the specified range.
+    pub fn compact_range(
+        &mut self,
+        level: usize,
+        begin: Option<&InternalKey>,
+        end: Option<&InternalKey>,
+    ) -> Option<Compaction<C>> {
+        let mut inputs = self.current.get_overlapping_inputs(level, begin, end);
+        if inputs.is_empty() {
+            return None;
+        }
+
+        // Avoid compacting too much in one shot in case the range is large.
+        // But we cannot do this for level-0 since level-0 files can overlap
+        // and we must not pick one file and drop another older file if the
+        // two files overlap.
+        if level > 0 {
+            let limit = self.options.max_file_size as u64;
+            let mut total = 0;
+            for i in 0..inputs.len() {
+                total += inputs[i].file_size;
+                if total >= limit {
+                    inputs.truncate(i + 1);
+                    break;
+                }
+            }
+        }
+
+        let mut c = Compaction::new(&self.options, level, self.current.clone());
+        c.inputs[0] = inputs;
+        self.setup_other_inputs(&mut c);
+        Some(c)
+    }
+
+    /// Create an iterator that reads over the compaction inputs for `c`.
+    pub fn make_input_iterator(
+        &self,
+        c: &Compaction<C>,
+    ) -> Result<MergingIterator<InternalKeyComparator<C>>>
+    where
+        C: 'static,
+    {
+        let options = ReadOptions {
+            verify_checksums: self.options.paranoid_checks,
+            fill_cache: false,
+            snapshot: None,
+        };
+        let mut children: Vec<Box<dyn LdbIterator>> =
+            Vec::with_capacity(c.inputs[0].len() + c.inputs[1].len());
+        for f in c.inputs.iter().flatten() {
+            let iter = self
+                .table_cache
+                .new_iterator(options, f.number, f.file_size)?;
+            children.push(Box::new(iter));
+        }
+        Ok(MergingIterator::new(self.icmp.clone(), children))
+    }
+
+    // Adds the files of level+1 that overlap the inputs of `c` and grows
+    // the level inputs when that does not pull in more level+1 files
+    fn setup_other_inputs(&mut self, c: &mut Compaction<C>) {
+        let level = c.level;
+        let current = &self.current;
+        add_boundary_inputs(&self.icmp, &current.files[level], &mut c.inputs[0]);
+        let (smallest, mut largest) = self.get_range(&c.inputs[0]);
+
+        c.inputs[1] = current.get_overlapping_inputs(level + 1, Some(&smallest), Some(&largest));
+        add_boundary_inputs(&self.icmp, &current.files[level + 1], &mut c.inputs[1]);
+
+        // Get entire range covered by compaction
+        let (mut all_start, mut all_limit) = self.get_range2(&c.inputs[0], &c.inputs[1]);
+
+        // See if we can grow the number of inputs in "level" without
+        // changing the number of "level+1" files we pick up.
+        if !c.inputs[1].is_empty() {
+            let mut expanded0 =
+                current.get_overlapping_inputs(level, Some(&all_start), Some(&all_limit));
+            add_boundary_inputs(&self.icmp, &current.files[level], &mut expanded0);
+            let inputs1_size = total_file_size(&c.inputs[1]);
+            let expanded0_size = total_file_size(&expanded0);
+            if expanded0.len() > c.inputs[0].len()
+                && inputs1_size + expanded0_size
+                    < expanded_compaction_byte_size_limit(&self.options)
+            {
+                let (new_start, new_limit) = self.get_range(&expanded0);
+                let mut expanded1 =
+                    current.get_overlapping_inputs(level + 1, Some(&new_start), Some(&new_limit));
+                add_boundary_inputs(&self.icmp, &current.files[level + 1], &mut expanded1);
+                if expanded1.len() == c.inputs[1].len() {
+                    largest = new_limit;
+                    c.inputs[0] = expanded0;
+                    c.inputs[1] = expanded1;
+                    let (start, limit) = self.get_range2(&c.inputs[0], &c.inputs[1]);
+                    all_start = start;
+                    all_limit = limit;
+                }
+            }
+        }
+
+        // Compute the set of grandparent files that overlap this compaction
+        // (parent == level+1; grandparent == level+2)
+        if level + 2 < NUM_LEVELS {
+            c.grandparents =
+                current.get_overlapping_inputs(level + 2, Some(&all_start), Some(&all_limit));
+        }
+
+        // Update the place where we will do the next compaction for this level.
+        // We update this immediately instead of waiting for the VersionEdit
+        // to be applied so that if the compaction fails, we will try a different
+        // key range next time.
+        self.compact_pointers[level] = largest.clone();
+        c.edit.set_compact_pointer(level, largest);
+    }
+
+    // Returns the smallest and the largest key covered by `inputs`.
+    // REQUIRES: `inputs` is not empty
+    fn get_range(&self, inputs: &[Arc<FileMetaData>]) -> (InternalKey, InternalKey) {
+        assert!(!inputs.is_empty());
+        let mut smallest = &inputs[0].smallest;
+        let mut largest = &inputs[0].largest;
+        for f in inputs.iter().skip(1) {
+            if self.icmp.compare(f.smallest.encode(), smallest.encode()) == Ordering::Less {
+                smallest = &f.smallest;
+            }
+            if self.icmp.compare(f.largest.encode(), largest.encode()) == Ordering::Greater {
+                largest = &f.largest;
+            }
+        }
+        (smallest.clone(), largest.clone())
+    }
+
+    // Returns the smallest and the largest key covered by `inputs1`
+    // and `inputs2` together.
+    // REQUIRES: `inputs1` and `inputs2` are not both empty
+    fn get_range2(
+        &self,
+        inputs1: &[Arc<FileMetaData>],
+        inputs2: &[Arc<FileMetaData>],
+    ) -> (InternalKey, InternalKey) {
+        let all: Vec<Arc<FileMetaData>> = inputs1.iter().chain(inputs2).cloned().collect();
+        self.get_range(&all)
+    }
+
+    fn append_version(&mut self, v: Version<C>) {
+        let v = Arc::new(v);
+        self.versions.retain(|v| v.strong_count() > 0);
+        self.versions.push(Arc::downgrade(&v));
+        self.current = v;
+    }
+
+    // Save current contents to `log`
+    fn write_snapshot(&self, log: &mut Writer<Box<dyn WritableFile>>) -> Result<()> {
+        let mut edit = VersionEdit::new();
+        // Save metadata
+        edit.set_comparator_name(self.options.comparator.name());
+        // Save compaction pointers
+        for (level, key) in self.compact_pointers.iter().enumerate() {
+            if !key.data().is_empty() {
+                edit.set_compact_pointer(level, key.clone());
+            }
+        }
+        // Save files
+        for (level, files) in self.current.files.iter().enumerate() {
+            for f in files {
+                edit.add_file(
+                    level,
+                    f.number,
+                    f.file_size,
+                    f.smallest.clone(),
+                    f.largest.clone(),
+                );
+            }
+        }
+        let mut record = vec![];
+        edit.encode_to(&mut record);
+        log.add_record(&record)
+    }
+}
+
+/// Return the combined file size of `files`
+pub fn total_file_size(files: &[Arc<FileMetaData>]) -> u64 {
+    files.iter().map(|f| f.file_size).sum()
+}
+
+// Precomputes the best level for the next compaction of `v`
+fn finalize<C: Comparator + Clone>(v: &mut Version<C>) {
+    let mut best_level = 0;
+    let mut best_score = -1.0;
+    for level in 0..NUM_LEVELS - 1 {
+        let score = if level == 0 {
+            // We treat level-0 specially by bounding the number of files
+            // instead of number of bytes for two reasons:
+            //
+            // (1) With larger write-buffer sizes, it is nice not to do too
+            // many level-0 compactions.
+            //
+            // (2) The files in level-0 are merged on every read and
+            // therefore we wish to avoid too many files when the individual
+            // file size is small (perhaps because of a small write-buffer
+            // setting, or very high compression ratios, or lots of
+            // overwrites/deletions).
+            v.files[level].len() as f64 / L0_COMPACTION_TRIGGER as f64
+        } else {
+            // Compute the ratio of current size to size limit.
+            total_file_size(&v.files[level]) as f64 / max_bytes_for_level(level)
+        };
+        if score > best_score {
+            best_level = level;
+            best_score = score;
+        }
+    }
+    v.compaction_level = best_level;
+    v.compaction_score = best_score;
+}
+
+// Returns the largest key of `files`, or `None` if `files` is empty
+fn find_largest_key<C: Comparator + Clone>(
+    icmp: &InternalKeyComparator<C>,
+    files: &[Arc<FileMetaData>],
+) -> Option<InternalKey> {
+    files
+        .iter()
+        .map(|f| &f.largest)
+        .max_by(|a, b| icmp.compare(a.encode(), b.encode()))
+        .cloned()
+}
+
+// Finds the file `b2 = (l2, u2)` in `level_files` with the smallest `l2`
+// such that `l2 > largest_key` and `user_key(l2) == user_key(largest_key)`
+fn find_smallest_boundary_file<C: Comparator + Clone>(
+    icmp: &InternalKeyComparator<C>,
+    level_files: &[Arc<FileMetaData>],
+    largest_key: &InternalKey,
+) -> Option<Arc<FileMetaData>> {
+    let ucmp = &icmp.user_comparator;
+    let mut smallest_boundary_file: Option<&Arc<FileMetaData>> = None;
+    for f in level_files {
+        if icmp.compare(f.smallest.encode(), largest_key.encode()) == Ordering::Greater
+            && ucmp.compare(f.smallest.user_key(), largest_key.user_key()) == Ordering::Equal
+        {
+            match smallest_boundary_file {
+                Some(b)
+                    if icmp.compare(f.smallest.encode(), b.smallest.encode()) != Ordering::Less => {
+                }
+                _ => smallest_boundary_file = Some(f),
+            }
+        }
+    }
+    smallest_boundary_file.cloned()
+}
+
+// Extracts the largest file `b1` from `compaction_files` and then searches
+// for a `b2` in `level_files` for which `user_key(u1) == user_key(l2)`.  If
+// it finds such a file `b2` (known as a boundary file) it adds it to
+// `compaction_files` and then searches again using this new upper bound.
+//
+// If there are two blocks, `b1 = (l1, u1)` and `b2 = (l2, u2)` and
+// `user_key(u1) == user_key(l2)`, and if we compact `b1` but not `b2` then
+// a subsequent get operation will yield an incorrect result because it
+// will return the record from `b2` in level i rather than from `b1`
+// because it searches level by level for records matching the supplied
+// user key.
+fn add_boundary_inputs<C: Comparator + Clone>(
+    icmp: &InternalKeyComparator<C>,
+    level_files: &[Arc<FileMetaData>],
+    compaction_files: &mut Vec<Arc<FileMetaData>>,
+) {
+    let mut largest_key = match find_largest_key(icmp, compaction_files) {
+        Some(key) => key,
+        // Quick return if compaction_files is empty
+        None => return,
+    };
+    while let Some(f) = find_smallest_boundary_file(icmp, level_files, &largest_key) {
+        // If a boundary file was found advance largest_key, otherwise we're done
+        largest_key = f.largest.clone();
+        compaction_files.push(f);
+    }
+}
+
+/// A `Compaction` encapsulates information about a compaction: the
+/// input files of `level` and `level + 1` and the edit that installs
+/// its result.
+pub struct Compaction<C: Comparator + Clone> {
+    level: usize,
+    max_output_file_size: u64,
+    max_grandparent_overlap_bytes: u64,
+    input_version: Arc<Version<C>>,
+    edit: VersionEdit,
+
+    /// Each compaction reads inputs from `level` and `level + 1`
+    pub inputs: [Vec<Arc<FileMetaData>>; 2],
+
+    // State used to check for number of overlapping grandparent files
+    // (parent == level + 1, grandparent == level + 2)
+    grandparents: Vec<Arc<FileMetaData>>,
+    // Index in grandparents
+    grandparent_index: usize,
+    // Some output key has been seen
+    seen_key: bool,
+    // Bytes of overlap between current output and grandparent files
+    overlapped_bytes: u64,
+
+    // State for implementing `is_base_level_for_key`
+
+    // level_ptrs holds indices into input_version.files: our state
+    // is that we are positioned at one of the file ranges for each
+    // higher level than the ones involved in this compaction (i.e. for
+    // all L >= level + 2).
+    level_ptrs: [usize; NUM_LEVELS],
+}
+
+impl<C: Comparator + Clone> Compaction<C> {
+    fn new(options: &Options<C>, level: usize, input_version: Arc<Version<C>>) -> Self {
+        Compaction {
+            level,
+            max_output_file_size: options.max_file_size as u64,
+            max_grandparent_overlap_bytes: max_grandparent_overlap_bytes(options),
+            input_version,
+            edit: VersionEdit::new(),
+            inputs: [vec![], vec![]],
+            grandparents: vec![],
+            grandparent_index: 0,
+            seen_key: false,
+            overlapped_bytes: 0,
+            level_ptrs: [0; NUM_LEVELS],
+        }
+    }
+
+    /// Return the level that is being compacted.  Inputs from `level`
+    /// and `level + 1` will be merged to produce a set of `level + 1` files.
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Return the object that holds the edits to the descriptor done
+    /// by this compaction.
+    pub fn edit(&mut self) -> &mut VersionEdit {
+        &mut self.edit
+    }
+
+    /// Maximum size of files to build during this compaction.
+    pub fn max_output_file_size(&self) -> u64 {
+        self.max_output_file_size
+    }
+
+    /// Is this a trivial compaction that can be implemented by just
+    /// moving a single input file to the next level (no merging or splitting)
+    pub fn is_trivial_move(&self) -> bool {
+        // Avoid a move if there is lots of overlapping grandparent data.
+        // Otherwise, the move could create a parent file that will require
+        // a very expensive merge later on.
+        self.inputs[0].len() == 1
+            && self.inputs[1].is_empty()
+            && total_file_size(&self.grandparents) <= self.max_grandparent_overlap_bytes
+    }
+
+    /// Add all inputs to this compaction as delete operations to its edit.
+    pub fn add_input_deletions(&mut self) {
+        for (which, files) in self.inputs.iter().enumerate() {
+            for f in files {
+                self.edit.delete_file(self.level + which, f.number);
+            }
+        }
+    }
+
+    /// Returns true if the information we have available guarantees that
+    /// the compaction is producing data in `level + 1` for which no data
+    /// exists in levels greater than `level + 1`.
+    pub fn is_base_level_for_key(&mut self, user_key: &[u8]) -> bool {
+        // Maybe use binary search to find right entry instead of linear search?
+        let ucmp = &self.input_version.icmp.user_comparator;
+        for level in self.level + 2..NUM_LEVELS {
+            let files = &self.input_version.files[level];
+            while self.level_ptrs[level] < files.len() {
+                let f = &files[self.level_ptrs[level]];
+                if ucmp.compare(user_key, f.largest.user_key()) != Ordering::Greater {
+                    // We've advanced far enough
+                    if ucmp.compare(user_key, f.smallest.user_key()) != Ordering::Less {
+                        // Key falls in this file's range, so definitely not base level
+                        return false;
+                    }
+                    break;
+                }
+                self.level_ptrs[level] += 1;
+            }
+        }
+        true
+    }
+
+    /// Returns true iff we should stop building the current output
+    /// before processing `internal_key`.
+    pub fn should_stop_before(&mut self, internal_key: &[u8]) -> bool {
+        let icmp = &self.input_version.icmp;
+        // Scan to find earliest grandparent file that contains key.
+        while self.grandparent_index < self.grandparents.len()
+            && icmp.compare(
+                internal_key,
+                self.grandparents[self.grandparent_index].largest.encode(),
+            ) == Ordering::Greater
+        {
+            if self.seen_key {
+                self.overlapped_bytes += self.grandparents[self.grandparent_index].file_size;
+            }
+            self.grandparent_index += 1;
+        }
+        self.seen_key = true;
+
+        if self.overlapped_bytes > self.max_grandparent_overlap_bytes {
+            // Too much overlap for current output; start new output
+            self.overlapped_bytes = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Keeps the first corruption reported by a log reader
+pub(crate) struct LogReporter {
+    pub(crate) status: Rc<RefCell<Result<()>>>,
+}
+
+impl Reporter for LogReporter {
+    fn corruption(&mut self, _bytes: u64, reason: &Error) {
+        let mut status = self.status.borrow_mut();
+        if status.is_ok() {
+            *status = Err(reason.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::table_cache::table_options;
+    use crate::env::{Env, MemEnv};
+    use crate::options::Options;
+    use crate::util::comparator::BytewiseComparator;
+
+    fn new_icmp() -> InternalKeyComparator<BytewiseComparator> {
+        InternalKeyComparator::new(BytewiseComparator::default())
+    }
+
+    fn new_file(number: u64, smallest: &str, largest: &str) -> FileMetaData {
+        FileMetaData {
+            allowed_seeks: 0,
+            number,
+            file_size: 0,
+            smallest: InternalKey::new(smallest.as_bytes(), 100, ValueType::KTypeValue),
+            largest: InternalKey::new(largest.as_bytes(), 100, ValueType::KTypeValue),
+        }
+    }
+
+    fn find(files: &[Arc<FileMetaData>], key: &str) -> usize {
+        let target = InternalKey::new(key.as_bytes(), 100, ValueType::KTypeValue);
+        find_file(&new_icmp(), files, target.encode())
+    }
+
+    #[test]
+    fn test_find_file_empty() {
+        assert_eq!(find(&[], "foo"), 0);
+    }
+
+    #[test]
+    fn test_find_file_single() {
+        let files = vec![Arc::new(new_file(1, "p", "q"))];
+        assert_eq!(find(&files, "a"), 0);
+        assert_eq!(find(&files, "p"), 0);
+        assert_eq!(find(&files, "p1"), 0);
+        assert_eq!(find(&files, "q"), 0);
+        assert_eq!(find(&files, "q1"), 1);
+        assert_eq!(find(&files, "z"), 1);
+    }
+
+    #[test]
+    fn test_find_file_multiple() {
+        let files: Vec<Arc<FileMetaData>> = vec![
+            new_file(1, "150", "200"),
+            new_file(2, "200", "250"),
+            new_file(3, "300", "350"),
+            new_file(4, "400", "450"),
+        ]
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+        assert_eq!(find(&files, "100"), 0);
+        assert_eq!(find(&files, "150"), 0);
+        assert_eq!(find(&files, "151"), 0);
+        assert_eq!(find(&files, "199"), 0);
+        assert_eq!(find(&files, "200"), 0);
+        assert_eq!(find(&files, "201"), 1);
+        assert_eq!(find(&files, "249"), 1);
+        assert_eq!(find(&files, "250"), 1);
+        assert_eq!(find(&files, "251"), 2);
+        assert_eq!(find(&files, "299"), 2);
+        assert_eq!(find(&files, "300"), 2);
+        assert_eq!(find(&files, "349"), 2);
+        assert_eq!(find(&files, "350"), 2);
+        assert_eq!(find(&files, "351"), 3);
+        assert_eq!(find(&files, "400"), 3);
+        assert_eq!(find(&files, "450"), 3);
+        assert_eq!(find(&files, "451"), 4);
+    }
+
+    fn new_version_set(dbname: &Path) -> VersionSet<BytewiseComparator> {
+        new_version_set_in(dbname, Arc::new(MemEnv::default()))
+    }
+
+    fn new_version_set_in(dbname: &Path, env: Arc<dyn Env>) -> VersionSet<BytewiseComparator> {
+        let options: Options = Options {
+            env,
+            ..Options::default()
+        };
+        let table_cache = Arc::new(TableCache::new(dbname, table_options(&options)));
+        VersionSet::new(dbname, options, table_cache)
+    }
+
+    fn numbers(files: &[Arc<FileMetaData>]) -> Vec<u64> {
+        files.iter().map(|f| f.number).collect()
+    }
+
+    #[test]
+    fn test_version_builder() {
+        let dbname = Path::new("builder");
+        let vset = new_version_set(dbname);
+        let mut compact_pointers = vec![InternalKey::default(); NUM_LEVELS];
+
+        let mut edit = VersionEdit::new();
+        edit.add_file(
+            0,
+            5,
+            10,
+            new_file(0, "a", "z").smallest,
+            new_file(0, "a", "z").largest,
+        );
+        edit.add_file(
+            0,
+            4,
+            10,
+            new_file(0, "a", "c").smallest,
+            new_file(0, "a", "c").largest,
+        );
+        for (number, smallest, largest) in [(7, "m", "p"), (6, "a", "c"), (8, "x", "z")].iter() {
+            let f = new_file(*number, smallest, largest);
+            edit.add_file(1, f.number, 10, f.smallest, f.largest);
+        }
+        edit.set_compact_pointer(1, new_file(0, "m", "m").smallest);
+        let mut builder = VersionBuilder::new(vset.current());
+        builder.apply(&edit, &mut compact_pointers);
+        let mut v = Version::new(vset.options.clone(), new_icmp(), vset.table_cache.clone());
+        builder.save_to(&mut v);
+        assert_eq!(numbers(v.files(0)), vec![4, 5]);
+        assert_eq!(numbers(v.files(1)), vec![6, 7, 8]);
+        assert_eq!(v.files(1)[0].allowed_seeks, 100);
+        assert_eq!(compact_pointers[1].user_key(), b"m");
+
+        let mut edit = VersionEdit::new();
+        edit.delete_file(1, 7);
+        edit.delete_file(0, 4);
+        let f = new_file(9, "d", "e");
+        edit.add_file(1, f.number, 10, f.smallest, f.largest);
+        let mut builder = VersionBuilder::new(Arc::new(v));
+        builder.apply(&edit, &mut compact_pointers);
+        let mut v2 = Version::new(vset.options.clone(), new_icmp(), vset.table_cache.clone());
+        builder.save_to(&mut v2);
+        assert_eq!(numbers(v2.files(0)), vec![5]);
+        assert_eq!(numbers(v2.files(1)), vec![6, 9, 8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "overlapping ranges")]
+    fn test_version_builder_overlap() {
+        let vset = new_version_set(Path::new("overlap"));
+        let mut edit = VersionEdit::new();
+        for (number, smallest, largest) in [(1, "a", "m"), (2, "k", "z")].iter() {
+            let f = new_file(*number, smallest, largest);
+            edit.add_file(2, f.number, 10, f.smallest, f.largest);
+        }
+        let mut builder = VersionBuilder::new(vset.current());
+        builder.apply(&edit, &mut vec![InternalKey::default(); NUM_LEVELS]);
+        builder.save_to(&mut Version::new(
+            vset.options.clone(),
+            new_icmp(),
+            vset.table_cache.clone(),
+        ));
+    }
+
+    #[test]
+    fn test_log_and_apply_and_recover() {
+        let dbname = Path::new("/vset");
+        let env: Arc<dyn Env> = Arc::new(MemEnv::default());
+        let mut vset = new_version_set_in(dbname, env.clone());
+        vset.manifest_file_number = vset.new_file_number();
+        let old = vset.current();
+        let mut edit = VersionEdit::new();
+        let f = new_file(10, "a", "c");
+        edit.add_file(0, f.number, 100, f.smallest, f.largest);
+        let f = new_file(11, "d", "f");
+        edit.add_file(2, f.number, 200, f.smallest, f.largest);
+        vset.mark_file_number_used(11);
+        vset.set_last_sequence(42);
+        vset.log_and_apply(&mut edit).unwrap();
+        assert_eq!(vset.num_level_files(0), 1);
+        assert_eq!(vset.num_level_bytes(2), 200);
+        // The old version is still referenced
+        assert_eq!(old.num_files(0), 0);
+
+        let held = vset.current();
+        let mut edit = VersionEdit::new();
+        edit.delete_file(0, 10);
+        vset.log_and_apply(&mut edit).unwrap();
+        assert_eq!(vset.num_level_files(0), 0);
+        // File 10 stays live while `held` is alive
+        assert!(vset.live_files().contains(&10));
+        drop(held);
+        assert!(!vset.live_files().contains(&10));
+        assert!(vset.live_files().contains(&11));
+
+        let mut recovered = new_version_set_in(dbname, env);
+        recovered.recover().unwrap();
+        assert_eq!(recovered.last_sequence(), 42);
+        assert_eq!(recovered.num_level_files(0), 0);
+        assert_eq!(numbers(recovered.current().files(2)), vec![11]);
+        assert!(recovered.new_file_number() > 11);
+    }
+
+    // Installs the result of applying `edit` as the current version
+    // without writing a MANIFEST
+    fn install(vset: &mut VersionSet<BytewiseComparator>, edit: &VersionEdit) {
+        let mut builder = VersionBuilder::new(vset.current());
+        builder.apply(edit, &mut vset.compact_pointers);
+        let mut v = Version::new(vset.options.clone(), new_icmp(), vset.table_cache.clone());
+        builder.save_to(&mut v);
+        finalize(&mut v);
+        vset.append_version(v);
+    }
+
+    fn add_file(edit: &mut VersionEdit, level: usize, f: FileMetaData, file_size: u64) {
+        edit.add_file(level, f.number, file_size, f.smallest, f.largest);
+    }
+
+    #[test]
+    fn test_pick_level0_compaction() {
+        let mut vset = new_version_set(Path::new("pick_level0"));
+        let mut edit = VersionEdit::new();
+        for (number, smallest, largest) in [(1, "c", "e"), (2, "d", "g"), (3, "a", "b")].iter() {
+            add_file(&mut edit, 0, new_file(*number, smallest, largest), 10);
+        }
+        add_file(&mut edit, 1, new_file(5, "a", "c"), 10);
+        add_file(&mut edit, 1, new_file(6, "f", "h"), 10);
+        add_file(&mut edit, 1, new_file(7, "x", "z"), 10);
+        add_file(&mut edit, 2, new_file(8, "g", "k"), 10);
+        install(&mut vset, &edit);
+        // Three level-0 files do not trigger a compaction
+        assert!(!vset.needs_compaction());
+        assert!(vset.pick_compaction().is_none());
+
+        let mut edit = VersionEdit::new();
+        add_file(&mut edit, 0, new_file(4, "i", "j"), 10);
+        install(&mut vset, &edit);
+        assert!(vset.needs_compaction());
+        let c = vset.pick_compaction().unwrap();
+        assert_eq!(c.level(), 0);
+        // Level-0 files are ordered by smallest key, so file 3 is picked
+        // first.  Growing it would pull in another level-1 file.
+        assert_eq!(numbers(&c.inputs[0]), vec![3]);
+        assert_eq!(numbers(&c.inputs[1]), vec![5]);
+        assert!(c.grandparents.is_empty());
+        assert!(!c.is_trivial_move());
+        assert_eq!(vset.compact_pointers[0].user_key(), b"b");
+
+        // The next compaction starts after the compact pointer.  The file
+        // picked ("c" .. "e") pulls in the level-0 file it overlaps, and
+        // the level-0 inputs grow as long as the level-1 inputs stay the same.
+        let mut c = vset.pick_compaction().unwrap();
+        assert_eq!(numbers(&c.inputs[0]), vec![3, 1, 2]);
+        assert_eq!(numbers(&c.inputs[1]), vec![5, 6]);
+        assert_eq!(numbers(&c.grandparents), vec![8]);
+        assert!(c.is_base_level_for_key(b"b"));
+        assert!(!c.is_base_level_for_key(b"h"));
+        assert!(c.is_base_level_for_key(b"l"));
+        assert_eq!(vset.compact_pointers[0].user_key(), b"g");
+
+        let c = vset.pick_compaction().unwrap();
+        assert_eq!(numbers(&c.inputs[0]), vec![4]);
+        assert!(c.inputs[1].is_empty());
+        assert!(c.is_trivial_move());
+        // Wrap around to the beginning of the key space
+        let c = vset.pick_compaction().unwrap();
+        assert_eq!(numbers(&c.inputs[0]), vec![3]);
+    }
+
+    #[test]
+    fn test_pick_compaction_boundary_inputs() {
+        let mut vset = new_version_set(Path::new("boundary_inputs"));
+        let mut edit = VersionEdit::new();
+        // Files 1 and 2 both hold entries for user key "c", so they must
+        // be compacted together
+        let mut f1 = new_file(1, "a", "c");
+        f1.largest = InternalKey::new(b"c", 200, ValueType::KTypeValue);
+        add_file(&mut edit, 1, f1, 8 * 1_048_576);
+        let mut f2 = new_file(2, "c", "e");
+        f2.smallest = InternalKey::new(b"c", 100, ValueType::KTypeValue);
+        add_file(&mut edit, 1, f2, 8 * 1_048_576);
+        add_file(&mut edit, 1, new_file(3, "f", "g"), 8 * 1_048_576);
+        install(&mut vset, &edit);
+        assert!(vset.needs_compaction());
+        let c = vset.pick_compaction().unwrap();
+        assert_eq!(c.level(), 1);
+        assert_eq!(numbers(&c.inputs[0]), vec![1, 2]);
+        assert_eq!(vset.compact_pointers[1].user_key(), b"e");
+    }
+
+    #[test]
+    fn test_should_stop_before() {
+        let mut vset = new_version_set(Path::new("stop_before"));
+        let mut edit = VersionEdit::new();
+        for i in 0..L0_COMPACTION_TRIGGER as u64 {
+            add_file(&mut edit, 0, new_file(i + 1, "a", "z"), 10);
+        }
+        // Each grandparent file is larger than the allowed overlap
+        let size = max_grandparent_overlap_bytes(&vset.options) + 1;
+        add_file(&mut edit, 2, new_file(10, "b", "c"), size);
+        add_file(&mut edit, 2, new_file(11, "d", "e"), size);
+        install(&mut vset, &edit);
+        let mut c = vset.pick_compaction().unwrap();
+        assert_eq!(numbers(&c.grandparents), vec![10, 11]);
+        let key = |k: &str| InternalKey::new(k.as_bytes(), 1, ValueType::KTypeValue);
+        assert!(!c.should_stop_before(key("a").encode()));
+        assert!(!c.should_stop_before(key("b").encode()));
+        // Moving past file 10 overlaps too much of the grandparent level
+        assert!(c.should_stop_before(key("d").encode()));
+        assert!(!c.should_stop_before(key("dd").encode()));
+        assert!(c.should_stop_before(key("f").encode()));
+    }
+}
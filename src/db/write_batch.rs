@@ -0,0 +1,343 @@
+// WriteBatch::rep :=
+//    sequence: fixed64
+//    count: fixed32
+//    data: record[count]
+// record :=
+//    KTypeValue varstring varstring         |
+//    KTypeDeletion varstring
+// varstring :=
+//    len: varint32
+//    data: uint8[len]
+
+use super::format::ValueType;
+use super::memtable::MemTable;
+use super::SequenceNumber;
+use crate::util::{
+    coding::{
+        decode_fixed_32, decode_fixed_64, encode_fixed_32, encode_fixed_64,
+        put_length_prefixed_slice, read_length_prefixed_slice,
+    },
+    comparator::Comparator,
+    status::{Error, ErrorKind, Result},
+};
+
+// WriteBatch header has an 8-byte sequence number followed by a 4-byte count.
+pub const HEADER: usize = 12;
+
+/// `WriteBatch` holds a collection of updates to apply atomically to a DB.
+///
+/// The updates are applied in the order in which they are added
+/// to the `WriteBatch`.  For example, the value of "key" will be "v3"
+/// after the following batch is written:
+///
+/// ```text
+///    batch.put("key", "v1");
+///    batch.delete("key");
+///    batch.put("key", "v2");
+///    batch.put("key", "v3");
+/// ```
+#[derive(Clone)]
+pub struct WriteBatch {
+    rep: Vec<u8>,
+}
+
+/// Receives the records of a `WriteBatch` in order through `WriteBatch::iterate`
+pub trait Handler {
+    fn put(&mut self, key: &[u8], value: &[u8]);
+    fn delete(&mut self, key: &[u8]);
+}
+
+impl Default for WriteBatch {
+    fn default() -> Self {
+        WriteBatch {
+            rep: vec![0; HEADER],
+        }
+    }
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the mapping "key->value" in the database.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.set_count(self.count() + 1);
+        self.rep.push(ValueType::KTypeValue as u8);
+        put_length_prefixed_slice(&mut self.rep, key);
+        put_length_prefixed_slice(&mut self.rep, value);
+    }
+
+    /// If the database contains a mapping for "key", erase it.  Else do nothing.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.set_count(self.count() + 1);
+        self.rep.push(ValueType::KTypeDeletion as u8);
+        put_length_prefixed_slice(&mut self.rep, key);
+    }
+
+    /// Clear all updates buffered in this batch.
+    pub fn clear(&mut self) {
+        self.rep.clear();
+        self.rep.resize(HEADER, 0);
+    }
+
+    /// The size of the database changes caused by this batch.
+    ///
+    /// This number is tied to implementation details, and may change across
+    /// releases. It is intended for LevelDB usage metrics.
+    pub fn approximate_size(&self) -> usize {
+        self.rep.len()
+    }
+
+    /// Copies the operations in `source` to this batch.
+    ///
+    /// This runs in O(source size) time. However, the constant factor is better
+    /// than calling `iterate()` over the source batch with a `Handler` that
+    /// replicates the operations into this batch.
+    pub fn append(&mut self, source: &WriteBatch) {
+        self.set_count(self.count() + source.count());
+        assert!(source.rep.len() >= HEADER);
+        self.rep.extend_from_slice(&source.rep[HEADER..]);
+    }
+
+    /// Support for iterating over the contents of a batch.
+    pub fn iterate(&self, handler: &mut dyn Handler) -> Result<()> {
+        if self.rep.len() < HEADER {
+            return Err(
+                ErrorKind::Corruption("malformed WriteBatch (too small)".to_owned()).into(),
+            );
+        }
+        let mut input = &self.rep[HEADER..];
+        let mut found = 0;
+        while !input.is_empty() {
+            found += 1;
+            let tag = input[0];
+            input = &input[1..];
+            if tag == ValueType::KTypeValue as u8 {
+                match (
+                    read_length_prefixed_slice(&mut input),
+                    read_length_prefixed_slice(&mut input),
+                ) {
+                    (Some(key), Some(value)) => handler.put(key, value),
+                    _ => return Err(ErrorKind::Corruption("bad WriteBatch Put".to_owned()).into()),
+                }
+            } else if tag == ValueType::KTypeDeletion as u8 {
+                match read_length_prefixed_slice(&mut input) {
+                    Some(key) => handler.delete(key),
+                    None => {
+                        return Err(ErrorKind::Corruption("bad WriteBatch Delete".to_owned()).into())
+                    }
+                }
+            } else {
+                return Err(ErrorKind::Corruption("unknown WriteBatch tag".to_owned()).into());
+            }
+        }
+        if found != self.count() {
+            Err(ErrorKind::Corruption("WriteBatch has wrong count".to_owned()).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Inserts every record of the batch into `mem`.  Records are given
+    /// consecutive sequence numbers starting at `self.sequence()`.
+    pub fn insert_into<C: Comparator + Clone>(&self, mem: &MemTable<C>) -> Result<()> {
+        let mut inserter = MemTableInserter {
+            sequence: self.sequence(),
+            mem,
+        };
+        self.iterate(&mut inserter)
+    }
+
+    /// Return the number of entries in the batch.
+    pub fn count(&self) -> u32 {
+        decode_fixed_32(&self.rep[8..])
+    }
+
+    /// Set the count for the number of entries in the batch.
+    pub fn set_count(&mut self, n: u32) {
+        encode_fixed_32(&mut self.rep[8..], n);
+    }
+
+    /// Return the sequence number for the start of this batch.
+    pub fn sequence(&self) -> SequenceNumber {
+        decode_fixed_64(&self.rep)
+    }
+
+    /// Store the specified number as the sequence number for the start of
+    /// this batch.
+    pub fn set_sequence(&mut self, seq: SequenceNumber) {
+        encode_fixed_64(&mut self.rep, seq);
+    }
+
+    /// The encoded batch, which is what gets written to the log.
+    pub fn contents(&self) -> &[u8] {
+        &self.rep
+    }
+
+    /// Replaces the batch with an encoded batch read back from the log.
+    pub fn set_contents(&mut self, contents: &[u8]) {
+        assert!(contents.len() >= HEADER);
+        self.rep.clear();
+        self.rep.extend_from_slice(contents);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+}
+
+struct MemTableInserter<'a, C: Comparator + Clone> {
+    sequence: SequenceNumber,
+    mem: &'a MemTable<C>,
+}
+
+impl<'a, C: Comparator + Clone> Handler for MemTableInserter<'a, C> {
+    fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.mem
+            .add(self.sequence, ValueType::KTypeValue, key, value);
+        self.sequence += 1;
+    }
+
+    fn delete(&mut self, key: &[u8]) {
+        self.mem
+            .add(self.sequence, ValueType::KTypeDeletion, key, b"");
+        self.sequence += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::format::{InternalKeyComparator, ParsedInteralKey};
+    use crate::db::ldbiterator::LdbIterator;
+    use crate::util::comparator::BytewiseComparator;
+
+    fn print_contents(b: &WriteBatch) -> String {
+        let icmp = InternalKeyComparator::new(BytewiseComparator::default());
+        let mem = MemTable::new(icmp, 1 << 20);
+        let mut state = String::new();
+        let result = b.insert_into(&mem);
+        let mut count = 0;
+        let mut iter = mem.iter();
+        iter.seek_to_first();
+        while iter.valid() {
+            let ikey = ParsedInteralKey::decode_from(iter.key()).unwrap();
+            match ikey.value_type() {
+                ValueType::KTypeValue => {
+                    state.push_str(&format!(
+                        "Put({}, {})",
+                        String::from_utf8_lossy(ikey.user_key()),
+                        String::from_utf8_lossy(iter.value())
+                    ));
+                    count += 1;
+                }
+                ValueType::KTypeDeletion => {
+                    state.push_str(&format!(
+                        "Delete({})",
+                        String::from_utf8_lossy(ikey.user_key())
+                    ));
+                    count += 1;
+                }
+            }
+            state.push_str(&format!("@{}", ikey.sequence()));
+            iter.next();
+        }
+        if result.is_err() {
+            state.push_str("ParseError()");
+        } else if count != b.count() {
+            state.push_str("CountMismatch()");
+        }
+        state
+    }
+
+    #[test]
+    fn test_empty() {
+        let batch = WriteBatch::new();
+        assert_eq!("", print_contents(&batch));
+        assert_eq!(0, batch.count());
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_multiple() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"foo", b"bar");
+        batch.delete(b"box");
+        batch.put(b"baz", b"boo");
+        batch.set_sequence(100);
+        assert_eq!(100, batch.sequence());
+        assert_eq!(3, batch.count());
+        assert_eq!(
+            "Put(baz, boo)@102Delete(box)@101Put(foo, bar)@100",
+            print_contents(&batch)
+        );
+    }
+
+    #[test]
+    fn test_corruption() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"foo", b"bar");
+        batch.delete(b"box");
+        batch.set_sequence(200);
+        let contents = batch.contents().to_vec();
+        batch.set_contents(&contents[..contents.len() - 1]);
+        assert_eq!("Put(foo, bar)@200ParseError()", print_contents(&batch));
+    }
+
+    #[test]
+    fn test_append() {
+        let mut b1 = WriteBatch::new();
+        let mut b2 = WriteBatch::new();
+        b1.set_sequence(200);
+        b2.set_sequence(300);
+        b1.append(&b2);
+        assert_eq!("", print_contents(&b1));
+        b2.put(b"a", b"va");
+        b1.append(&b2);
+        assert_eq!("Put(a, va)@200", print_contents(&b1));
+        b2.clear();
+        b2.put(b"b", b"vb");
+        b1.append(&b2);
+        assert_eq!("Put(a, va)@200Put(b, vb)@201", print_contents(&b1));
+        b2.delete(b"foo");
+        b1.append(&b2);
+        assert_eq!(
+            "Put(a, va)@200Put(b, vb)@202Put(b, vb)@201Delete(foo)@203",
+            print_contents(&b1)
+        );
+    }
+
+    #[test]
+    fn test_approximate_size() {
+        let mut batch = WriteBatch::new();
+        let empty_size = batch.approximate_size();
+
+        batch.put(b"foo", b"bar");
+        let one_key_size = batch.approximate_size();
+        assert!(empty_size < one_key_size);
+
+        batch.put(b"baz", b"boo");
+        let two_keys_size = batch.approximate_size();
+        assert!(one_key_size < two_keys_size);
+
+        batch.delete(b"box");
+        let post_delete_size = batch.approximate_size();
+        assert!(two_keys_size < post_delete_size);
+    }
+
+    #[test]
+    fn test_large_values() {
+        let mut batch = WriteBatch::new();
+        let value = vec![b'v'; 1000];
+        batch.put(b"large", &value);
+        batch.set_sequence(1);
+        let icmp = InternalKeyComparator::new(BytewiseComparator::default());
+        let mem = MemTable::new(icmp, 1 << 20);
+        batch.insert_into(&mem).unwrap();
+        let mut iter = mem.iter();
+        iter.seek_to_first();
+        assert!(iter.valid());
+        assert_eq!(iter.value(), value.as_slice());
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::{
+    default_env, not_found, Env, FileLock, RandomAccessFile, SequentialFile, WritableFile,
+};
+use crate::util::status::{Error, ErrorKind, Result};
+
+// The contents of a file, shared by the handles opened on it
+type FileState = Arc<RwLock<Vec<u8>>>;
+
+struct SequentialFileImpl {
+    file: FileState,
+    pos: usize,
+}
+
+impl Read for SequentialFileImpl {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.file.read().unwrap();
+        let n = usize::min(buf.len(), data.len().saturating_sub(self.pos));
+        buf[..n].copy_from_slice(&data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+struct RandomAccessFileImpl {
+    file: FileState,
+}
+
+impl RandomAccessFile for RandomAccessFileImpl {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.file.read().unwrap().read_at(buf, offset)
+    }
+}
+
+struct WritableFileImpl {
+    file: FileState,
+}
+
+impl Write for WritableFileImpl {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WritableFile for WritableFileImpl {
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An `Env` that keeps every file in memory, for tests and fully
+/// in-memory databases.  Directories are implicit: a directory holds
+/// the files whose path starts with it.  Background work and time are
+/// delegated to the base `Env`.
+pub struct MemEnv {
+    base: Arc<dyn Env>,
+    files: Mutex<HashMap<PathBuf, FileState>>,
+    locked_files: Mutex<HashSet<PathBuf>>,
+}
+
+impl MemEnv {
+    pub fn new(base: Arc<dyn Env>) -> Self {
+        MemEnv {
+            base,
+            files: Mutex::new(HashMap::new()),
+            locked_files: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn file(&self, path: &Path) -> Result<FileState> {
+        match self.files.lock().unwrap().get(path) {
+            Some(file) => Ok(file.clone()),
+            None => Err(not_found(path)),
+        }
+    }
+}
+
+impl Default for MemEnv {
+    fn default() -> Self {
+        MemEnv::new(default_env())
+    }
+}
+
+impl Env for MemEnv {
+    fn new_sequential_file(&self, path: &Path) -> Result<Box<dyn SequentialFile>> {
+        let file = self.file(path)?;
+        Ok(Box::new(SequentialFileImpl { file, pos: 0 }))
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>> {
+        let file = self.file(path)?;
+        Ok(Box::new(RandomAccessFileImpl { file }))
+    }
+
+    fn new_writable_file(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        // The handles opened on a previous file of that name keep
+        // seeing its old contents
+        let file = FileState::default();
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), file.clone());
+        Ok(Box::new(WritableFileImpl { file }))
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains_key(path)
+    }
+
+    fn get_children(&self, dir: &Path) -> Result<Vec<String>> {
+        Ok(self
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        match self.files.lock().unwrap().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn create_dir(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove_dir(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_file_size(&self, path: &Path) -> Result<u64> {
+        Ok(self.file(path)?.read().unwrap().len() as u64)
+    }
+
+    fn rename(&self, src: &Path, target: &Path) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        match files.remove(src) {
+            Some(file) => {
+                files.insert(target.to_owned(), file);
+                Ok(())
+            }
+            None => Err(not_found(src).with_operation("rename").with_target(target)),
+        }
+    }
+
+    fn sync_dir(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn lock_file(&self, path: &Path) -> Result<FileLock> {
+        if !self.locked_files.lock().unwrap().insert(path.to_owned()) {
+            return Err(
+                Error::from(ErrorKind::Busy("lock already held by process".to_owned()))
+                    .with_path(path),
+            );
+        }
+        self.files
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default();
+        Ok(FileLock {
+            path: path.to_owned(),
+            file: None,
+        })
+    }
+
+    fn unlock_file(&self, lock: FileLock) -> Result<()> {
+        self.locked_files.lock().unwrap().remove(&lock.path);
+        Ok(())
+    }
+
+    fn schedule(&self, f: Box<dyn FnOnce() + Send>) {
+        self.base.schedule(f)
+    }
+
+    fn now_micros(&self) -> u64 {
+        self.base.now_micros()
+    }
+
+    fn sleep_for_microseconds(&self, micros: u64) {
+        self.base.sleep_for_microseconds(micros)
+    }
+}
@@ -0,0 +1,258 @@
+mod mem;
+mod posix;
+
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+pub use self::mem::MemEnv;
+pub use self::posix::PosixEnv;
+pub use crate::util::file::RandomAccessFile;
+use crate::util::status::{Error, ErrorKind, Result};
+
+/// A file abstraction for reading sequentially through a file
+pub trait SequentialFile: Read + Send {}
+
+impl<R: Read + Send> SequentialFile for R {}
+
+/// A file abstraction for sequential writing.  The implementation
+/// must provide buffering since callers may append small fragments
+/// at a time to the file.
+pub trait WritableFile: Write + Send {
+    /// Flush the buffered data and make sure it reaches stable storage
+    fn sync(&mut self) -> Result<()>;
+}
+
+impl<W: WritableFile + ?Sized> WritableFile for Box<W> {
+    fn sync(&mut self) -> Result<()> {
+        (**self).sync()
+    }
+}
+
+/// Identifies a locked file, returned by `Env::lock_file`
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    // The open file holding the lock, for the environments relying on
+    // the locks of the operating system
+    file: Option<std::fs::File>,
+}
+
+impl FileLock {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// An `Env` is an interface used by the leveldb implementation to access
+/// operating system functionality like the filesystem etc.  Callers
+/// may wish to provide a custom `Env` object when opening a database to
+/// get fine grain control; e.g., to rate limit file system operations.
+///
+/// All `Env` implementations are safe for concurrent access from
+/// multiple threads without any external synchronization.
+pub trait Env: Send + Sync {
+    /// Create an object that sequentially reads the file with the specified name.
+    /// The returned file will only be accessed by one thread at a time.
+    fn new_sequential_file(&self, path: &Path) -> Result<Box<dyn SequentialFile>>;
+
+    /// Create an object supporting random-access reads from the file with the
+    /// specified name.  The returned file may be concurrently accessed by
+    /// multiple threads.
+    fn new_random_access_file(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>>;
+
+    /// Create an object that writes to a new file with the specified
+    /// name.  Deletes any existing file with the same name and creates a
+    /// new file.  The returned file will only be accessed by one thread
+    /// at a time.
+    fn new_writable_file(&self, path: &Path) -> Result<Box<dyn WritableFile>>;
+
+    /// Returns true iff the named file exists.
+    fn file_exists(&self, path: &Path) -> bool;
+
+    /// Return the names of the children of the specified directory.
+    /// The names are relative to `dir`.
+    fn get_children(&self, dir: &Path) -> Result<Vec<String>>;
+
+    /// Delete the named file.
+    fn remove(&self, path: &Path) -> Result<()>;
+
+    /// Create the specified directory and its missing parents.
+    fn create_dir(&self, dir: &Path) -> Result<()>;
+
+    /// Delete the specified directory.
+    fn remove_dir(&self, dir: &Path) -> Result<()>;
+
+    /// Return the size of the named file.
+    fn get_file_size(&self, path: &Path) -> Result<u64>;
+
+    /// Rename file `src` to `target`, replacing `target` if it exists.
+    fn rename(&self, src: &Path, target: &Path) -> Result<()>;
+
+    /// Make the creations, renames and deletions of the files of the
+    /// specified directory reach stable storage.
+    fn sync_dir(&self, dir: &Path) -> Result<()>;
+
+    /// Lock the specified file.  Used to prevent concurrent access to
+    /// the same db by multiple processes.  On failure, returns an error.
+    ///
+    /// On success, returns the lock that the caller must release with
+    /// `unlock_file` to release the lock.  If somebody else already holds
+    /// the lock, finishes immediately with a failure.  I.e., this call
+    /// does not wait for existing locks to go away.
+    ///
+    /// May create the named file if it does not already exist.
+    fn lock_file(&self, path: &Path) -> Result<FileLock>;
+
+    /// Release the lock acquired by a previous successful call to `lock_file`.
+    fn unlock_file(&self, lock: FileLock) -> Result<()>;
+
+    /// Arrange to run `f` once in a background thread.
+    ///
+    /// `f` may run in an unspecified thread.  Multiple functions
+    /// added to the same `Env` may run concurrently in different threads.
+    /// I.e., the caller may not assume that background work items are
+    /// serialized.
+    fn schedule(&self, f: Box<dyn FnOnce() + Send>);
+
+    /// Returns the number of micro-seconds since some fixed point in time.
+    /// Only useful for computing deltas of time.
+    fn now_micros(&self) -> u64;
+
+    /// Sleep/delay the thread for the prescribed number of micro-seconds.
+    fn sleep_for_microseconds(&self, micros: u64);
+}
+
+/// Return a default environment suitable for the current operating
+/// system.  The result of `default_env()` is shared by every caller.
+pub fn default_env() -> Arc<dyn Env> {
+    static DEFAULT: OnceLock<Arc<dyn Env>> = OnceLock::new();
+    DEFAULT.get_or_init(|| Arc::new(PosixEnv::new())).clone()
+}
+
+/// A utility routine: write `data` to the named file and sync it.
+pub fn write_string_to_file_sync(env: &dyn Env, data: &[u8], path: &Path) -> Result<()> {
+    let result = env.new_writable_file(path).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync()
+    });
+    if result.is_err() {
+        let _ = env.remove(path);
+    }
+    result
+}
+
+/// A utility routine: read contents of named file into a string.
+pub fn read_file_to_string(env: &dyn Env, path: &Path) -> Result<String> {
+    let mut file = env.new_sequential_file(path)?;
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+    Ok(data)
+}
+
+// Builds the error returned for a missing file, with the kind callers
+// check to tell a missing file from other failures
+fn not_found(path: &Path) -> Error {
+    Error::from(io::Error::new(io::ErrorKind::NotFound, "no such file")).with_path(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    // Runs the same checks against every `Env`
+    fn check_file_operations(env: &dyn Env, dir: &Path) {
+        env.create_dir(dir).unwrap();
+        let a = dir.join("a");
+        let b = dir.join("b");
+        assert!(!env.file_exists(&a));
+        assert!(env.get_file_size(&a).is_err());
+        assert!(env.get_children(dir).unwrap().is_empty());
+
+        // Create a file
+        let mut file = env.new_writable_file(&a).unwrap();
+        file.write_all(b"hello ").unwrap();
+        file.write_all(b"world").unwrap();
+        file.sync().unwrap();
+        drop(file);
+        assert!(env.file_exists(&a));
+        assert_eq!(env.get_children(dir).unwrap(), vec!["a".to_owned()]);
+        assert_eq!(env.get_file_size(&a).unwrap(), 11);
+
+        // Read it back
+        assert_eq!(read_file_to_string(env, &a).unwrap(), "hello world");
+        let file = env.new_random_access_file(&a).unwrap();
+        let mut buf = [0; 5];
+        assert_eq!(file.read_at(&mut buf, 6).unwrap(), 5);
+        assert_eq!(&buf, b"world");
+        assert_eq!(file.read_at(&mut buf, 9).unwrap(), 2);
+        assert_eq!(file.read_at(&mut buf, 20).unwrap(), 0);
+
+        // Rename it over another file
+        write_string_to_file_sync(env, b"other", &b).unwrap();
+        env.rename(&a, &b).unwrap();
+        assert!(!env.file_exists(&a));
+        assert_eq!(read_file_to_string(env, &b).unwrap(), "hello world");
+        let err = env.new_sequential_file(&a).err().unwrap();
+        assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.context().unwrap().path.as_deref(), Some(a.as_path()));
+        let context = env.rename(&a, &b).unwrap_err().context().cloned().unwrap();
+        assert_eq!(context.path.as_deref(), Some(a.as_path()));
+        assert_eq!(context.target.as_deref(), Some(b.as_path()));
+        env.sync_dir(dir).unwrap();
+
+        // Truncate it
+        drop(env.new_writable_file(&b).unwrap());
+        assert_eq!(env.get_file_size(&b).unwrap(), 0);
+
+        env.remove(&b).unwrap();
+        assert!(env.remove(&b).is_err());
+        assert!(env.get_children(dir).unwrap().is_empty());
+
+        // Locks are exclusive until released
+        let lock = env.lock_file(&dir.join("LOCK")).unwrap();
+        assert!(env.lock_file(&dir.join("LOCK")).unwrap_err().is_retryable());
+        env.unlock_file(lock).unwrap();
+        let lock = env.lock_file(&dir.join("LOCK")).unwrap();
+        env.unlock_file(lock).unwrap();
+        env.remove(&dir.join("LOCK")).unwrap();
+        env.remove_dir(dir).unwrap();
+    }
+
+    fn check_schedule(env: &dyn Env) {
+        let (tx, rx) = mpsc::channel();
+        for i in 0..3 {
+            let tx = tx.clone();
+            env.schedule(Box::new(move || tx.send(i).unwrap()));
+        }
+        let mut received: Vec<i32> = (0..3)
+            .map(|_| rx.recv_timeout(Duration::from_secs(10)).unwrap())
+            .collect();
+        received.sort_unstable();
+        assert_eq!(received, vec![0, 1, 2]);
+
+        let start = env.now_micros();
+        env.sleep_for_microseconds(1000);
+        assert!(env.now_micros() >= start + 1000);
+    }
+
+    #[test]
+    fn test_posix_env() {
+        let env = PosixEnv::new();
+        let dir = std::env::temp_dir().join(format!("leveldb-env-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        check_file_operations(&env, &dir);
+        check_schedule(&env);
+    }
+
+    #[test]
+    fn test_mem_env() {
+        let env = MemEnv::default();
+        check_file_operations(&env, Path::new("/dir"));
+        check_schedule(&env);
+        // Nothing touched the disk
+        assert!(!Path::new("/dir").exists());
+    }
+}
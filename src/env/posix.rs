@@ -0,0 +1,199 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Env, FileLock, RandomAccessFile, SequentialFile, WritableFile};
+use crate::util::status::{map_io_res, Error, ErrorKind, Result};
+
+// Size of the buffer of a `PosixWritableFile`
+const WRITABLE_FILE_BUFFER_SIZE: usize = 65536;
+
+struct PosixWritableFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Write for PosixWritableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl WritableFile for PosixWritableFile {
+    fn sync(&mut self) -> Result<()> {
+        map_io_res!(self.file.flush(), "flush", &self.path)?;
+        map_io_res!(self.file.get_ref().sync_data(), "sync", &self.path)
+    }
+}
+
+type BackgroundWork = Box<dyn FnOnce() + Send>;
+
+// The queue of the work scheduled on the background thread
+#[derive(Default)]
+struct BackgroundQueue {
+    state: Mutex<BackgroundQueueState>,
+    work_available: Condvar,
+}
+
+#[derive(Default)]
+struct BackgroundQueueState {
+    started: bool,
+    queue: VecDeque<BackgroundWork>,
+}
+
+impl BackgroundQueue {
+    fn run(&self) {
+        loop {
+            let work = {
+                let mut state = self.state.lock().unwrap();
+                // Wait until there is work to be done.
+                while state.queue.is_empty() {
+                    state = self.work_available.wait(state).unwrap();
+                }
+                state.queue.pop_front().unwrap()
+            };
+            work();
+        }
+    }
+}
+
+/// The `Env` of POSIX systems.  The background work runs on a single
+/// thread started on the first call to `schedule`.
+#[derive(Default)]
+pub struct PosixEnv {
+    // The paths locked by this process.  The locks of the operating
+    // system do not exclude the threads of the process holding them.
+    locked_files: Mutex<HashSet<PathBuf>>,
+    background: Arc<BackgroundQueue>,
+}
+
+impl PosixEnv {
+    pub fn new() -> Self {
+        PosixEnv::default()
+    }
+}
+
+impl Env for PosixEnv {
+    fn new_sequential_file(&self, path: &Path) -> Result<Box<dyn SequentialFile>> {
+        Ok(Box::new(map_io_res!(File::open(path), "open", path)?))
+    }
+
+    fn new_random_access_file(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>> {
+        Ok(Box::new(map_io_res!(File::open(path), "open", path)?))
+    }
+
+    fn new_writable_file(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let file = map_io_res!(File::create(path), "create", path)?;
+        Ok(Box::new(PosixWritableFile {
+            path: path.to_owned(),
+            file: BufWriter::with_capacity(WRITABLE_FILE_BUFFER_SIZE, file),
+        }))
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn get_children(&self, dir: &Path) -> Result<Vec<String>> {
+        let mut children = vec![];
+        for entry in map_io_res!(fs::read_dir(dir), "read dir", dir)? {
+            let entry = map_io_res!(entry, "read dir", dir)?;
+            children.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        Ok(children)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        map_io_res!(fs::remove_file(path), "remove", path)
+    }
+
+    fn create_dir(&self, dir: &Path) -> Result<()> {
+        map_io_res!(fs::create_dir_all(dir), "create dir", dir)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> Result<()> {
+        map_io_res!(fs::remove_dir(dir), "remove dir", dir)
+    }
+
+    fn get_file_size(&self, path: &Path) -> Result<u64> {
+        Ok(map_io_res!(fs::metadata(path), "stat", path)?.len())
+    }
+
+    fn rename(&self, src: &Path, target: &Path) -> Result<()> {
+        map_io_res!(fs::rename(src, target), "rename", src).map_err(|e| e.with_target(target))
+    }
+
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        let dir_file = map_io_res!(File::open(dir), "open dir", dir)?;
+        map_io_res!(dir_file.sync_all(), "sync dir", dir)
+    }
+
+    fn lock_file(&self, path: &Path) -> Result<FileLock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path);
+        let file = map_io_res!(file, "lock", path)?;
+        if !self.locked_files.lock().unwrap().insert(path.to_owned()) {
+            return Err(
+                Error::from(ErrorKind::Busy("lock already held by process".to_owned()))
+                    .with_path(path),
+            );
+        }
+        // SAFETY: the descriptor belongs to `file`, which is open
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            self.locked_files.lock().unwrap().remove(path);
+            return map_io_res!(Err(io::Error::last_os_error()), "lock", path);
+        }
+        Ok(FileLock {
+            path: path.to_owned(),
+            file: Some(file),
+        })
+    }
+
+    fn unlock_file(&self, lock: FileLock) -> Result<()> {
+        if let Some(file) = &lock.file {
+            // SAFETY: the descriptor belongs to `file`, which is open
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) } != 0 {
+                return map_io_res!(Err(io::Error::last_os_error()), "unlock", &lock.path);
+            }
+        }
+        self.locked_files.lock().unwrap().remove(&lock.path);
+        Ok(())
+    }
+
+    fn schedule(&self, f: Box<dyn FnOnce() + Send>) {
+        let mut state = self.background.state.lock().unwrap();
+        // Start the background thread, if we haven't done so already.
+        if !state.started {
+            state.started = true;
+            let background = self.background.clone();
+            thread::Builder::new()
+                .name("leveldb-bg".to_owned())
+                .spawn(move || background.run())
+                .expect("failed to start the background thread");
+        }
+        state.queue.push_back(f);
+        self.background.work_available.notify_one();
+    }
+
+    fn now_micros(&self) -> u64 {
+        // Monotonic, counted from the first call in the process
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed().as_micros() as u64
+    }
+
+    fn sleep_for_microseconds(&self, micros: u64) {
+        thread::sleep(Duration::from_micros(micros));
+    }
+}
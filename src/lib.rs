@@ -1,6 +1,32 @@
 #![allow(warnings, unused)]
 mod db;
+pub mod env;
+mod options;
 mod util;
+
+pub use db::db_iter::DBIterator;
+pub use db::dumpfile::dump_file;
+pub use db::ldbiterator::{Entries, LdbIterator};
+pub use db::repair::{repair_db, RepairReport};
+pub use db::snapshot::Snapshot;
+pub use db::write_batch::WriteBatch;
+pub use db::{BlockCache, DB};
+pub use env::{Env, MemEnv, PosixEnv};
+pub use options::{CompressionType, Options, ReadOptions, WriteOptions};
+pub use util::cache::{Cache, CacheHandle, ShardedLRUCache};
+pub use util::coding::{
+    decode_memcomparable_tuple, put_memcomparable_bytes, put_memcomparable_f64,
+    put_memcomparable_i64, put_memcomparable_tuple, put_memcomparable_u64,
+    read_memcomparable_bytes, read_memcomparable_f64, read_memcomparable_i64,
+    read_memcomparable_u64, KeyPart,
+};
+pub use util::comparator::{
+    BytewiseComparator, Comparator, ReverseBytewiseComparator, U64Comparator,
+};
+pub use util::compression::{Compressor, CompressorRegistry, SnappyCompressor};
+pub use util::filter_policy::{BloomFilterPolicy, FilterPolicy};
+pub use util::logging::escape_string;
+pub use util::status::{Error, ErrorContext, ErrorKind, Result};
 #[cfg(test)]
 mod tests {
     #[test]
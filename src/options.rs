@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use crate::db::snapshot::Snapshot;
+use crate::db::BlockCache;
+use crate::env::{default_env, Env};
+use crate::util::comparator::{BytewiseComparator, Comparator};
+use crate::util::compression::CompressorRegistry;
+use crate::util::filter_policy::FilterPolicy;
+
+/// DB contents are stored in a set of blocks, each of which holds a
+/// sequence of key,value pairs.  Each block may be compressed before
+/// being stored in a file.  The following enum describes which
+/// compression method (if any) is used to compress a block.
+///
+/// NOTE: do not change the values of existing entries, as these are
+/// part of the persistent format on disk.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CompressionType {
+    NoCompression,
+    SnappyCompression,
+    /// A codec registered in `Options::compressors` under this id.  Ids
+    /// 0 and 1 stand for the types above and can't be registered.
+    Custom(u8),
+}
+
+impl CompressionType {
+    /// Returns the type stored as `value` in a block trailer
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => CompressionType::NoCompression,
+            1 => CompressionType::SnappyCompression,
+            id => CompressionType::Custom(id),
+        }
+    }
+
+    /// Returns the value stored in the trailer of the blocks of this type
+    pub fn to_u8(self) -> u8 {
+        match self {
+            CompressionType::NoCompression => 0,
+            CompressionType::SnappyCompression => 1,
+            CompressionType::Custom(id) => id,
+        }
+    }
+}
+
+/// Options to control the behavior of a database (passed to `DB::open`)
+#[derive(Clone)]
+pub struct Options<C: Comparator = BytewiseComparator> {
+    /// Comparator used to define the order of keys in the table.
+    /// Default: a comparator that uses lexicographic byte-wise ordering
+    ///
+    /// REQUIRES: The client must ensure that the comparator supplied
+    /// here has the same name and orders keys *exactly* the same as the
+    /// comparator provided to previous open calls on the same DB.  The
+    /// name is recorded in the MANIFEST: opening the DB with a comparator
+    /// of another name fails with `ErrorKind::InvalidArgument`.
+    pub comparator: C,
+
+    /// If true, the database will be created if it is missing.
+    pub create_if_missing: bool,
+
+    /// If true, an error is raised if the database already exists.
+    pub error_if_exists: bool,
+
+    /// If true, the implementation will do aggressive checking of the
+    /// data it is processing and will stop early if it detects any
+    /// errors.  This may have unforeseen ramifications: for example, a
+    /// corruption of one DB entry may cause a large number of entries to
+    /// become unreadable or for the entire DB to become unopenable.
+    pub paranoid_checks: bool,
+
+    /// Use the specified object to interact with the environment,
+    /// e.g. to read/write files, schedule background work, etc.
+    /// Default: `default_env()`
+    pub env: Arc<dyn Env>,
+
+    /// Amount of data to build up in memory (backed by an unsorted log
+    /// on disk) before converting to a sorted on-disk file.
+    ///
+    /// Larger values increase performance, especially during bulk loads.
+    /// Up to two write buffers may be held in memory at the same time,
+    /// so you may wish to adjust this parameter to control memory usage.
+    /// Also, a larger write buffer will result in a longer recovery time
+    /// the next time the database is opened.
+    pub write_buffer_size: usize,
+
+    /// Number of open files that can be used by the DB.  You may need to
+    /// increase this if your database has a large working set (budget
+    /// one open file per 2MB of working set).
+    pub max_open_files: usize,
+
+    /// Approximate size of user data packed per block.  Note that the
+    /// block size specified here corresponds to uncompressed data.  The
+    /// actual size of the unit read from disk may be smaller if
+    /// compression is enabled.  This parameter can be changed dynamically.
+    pub block_size: usize,
+
+    /// Number of keys between restart points for delta encoding of keys.
+    /// This parameter can be changed dynamically.  Most clients should
+    /// leave this parameter alone.
+    pub block_restart_interval: usize,
+
+    /// Leveldb will write up to this amount of bytes to a file before
+    /// switching to a new one.
+    /// Most clients should leave this parameter alone.  However if your
+    /// filesystem is more efficient with larger files, you could
+    /// consider increasing the value.  The downside will be longer
+    /// compactions and hence longer latency/performance hiccups.
+    /// Another reason to increase this parameter might be when you are
+    /// initially populating a large database.
+    pub max_file_size: usize,
+
+    /// Compress blocks using the specified compression algorithm.  This
+    /// parameter can be changed dynamically.
+    ///
+    /// Default: `SnappyCompression`, which gives lightweight but fast
+    /// compression.  Blocks that shrink by less than 12.5% are stored
+    /// uncompressed.
+    pub compression: CompressionType,
+
+    /// The codecs blocks are compressed and decompressed with, by
+    /// compression type.  `compression` must be registered here or
+    /// `DB::open` fails with `ErrorKind::InvalidArgument`, and so must the
+    /// types of the blocks of existing tables or reading them fails with
+    /// `ErrorKind::Corruption`.
+    /// Default: Snappy only
+    pub compressors: CompressorRegistry,
+
+    /// If non-None, keep the uncompressed data blocks read from the
+    /// tables in this cache, so that reads of recently used blocks do
+    /// not touch the disk.  If None, every block is read from its file.
+    /// Default: an 8MB cache of its own
+    pub block_cache: Option<BlockCache>,
+
+    /// If non-None, use the specified filter policy to reduce disk reads.
+    /// Many applications will benefit from passing a `BloomFilterPolicy`
+    /// here.
+    pub filter_policy: Option<Arc<dyn FilterPolicy>>,
+}
+
+impl<C: Comparator + Default> Default for Options<C> {
+    fn default() -> Self {
+        Options {
+            comparator: C::default(),
+            create_if_missing: false,
+            error_if_exists: false,
+            paranoid_checks: false,
+            env: default_env(),
+            write_buffer_size: 4 * 1024 * 1024,
+            max_open_files: 1000,
+            block_size: 4 * 1024,
+            block_restart_interval: 16,
+            max_file_size: 2 * 1024 * 1024,
+            compression: CompressionType::SnappyCompression,
+            compressors: CompressorRegistry::default(),
+            block_cache: Some(BlockCache::new(8 << 20)),
+            filter_policy: None,
+        }
+    }
+}
+
+impl<C: Comparator> Options<C> {
+    /// Returns the same options with keys ordered by `comparator`.  This
+    /// is how a comparator picked at runtime is given as an
+    /// `Arc<dyn Comparator>`.
+    pub fn with_comparator<D: Comparator>(self, comparator: D) -> Options<D> {
+        Options {
+            comparator,
+            create_if_missing: self.create_if_missing,
+            error_if_exists: self.error_if_exists,
+            paranoid_checks: self.paranoid_checks,
+            env: self.env,
+            write_buffer_size: self.write_buffer_size,
+            max_open_files: self.max_open_files,
+            block_size: self.block_size,
+            block_restart_interval: self.block_restart_interval,
+            max_file_size: self.max_file_size,
+            compression: self.compression,
+            compressors: self.compressors,
+            block_cache: self.block_cache,
+            filter_policy: self.filter_policy,
+        }
+    }
+}
+
+/// Options that control read operations
+#[derive(Clone, Copy)]
+pub struct ReadOptions<'a> {
+    /// If true, all data read from underlying storage will be
+    /// verified against corresponding checksums.
+    pub verify_checksums: bool,
+
+    /// Should the data read for this iteration be cached in memory?
+    /// Callers may wish to set this field to false for bulk scans.
+    pub fill_cache: bool,
+
+    /// If `Some`, read as of the supplied snapshot (which must belong
+    /// to the DB that is being read and which must not have been
+    /// released).  If `None`, use an implicit snapshot of the state at
+    /// the beginning of this read operation.
+    pub snapshot: Option<&'a Snapshot>,
+}
+
+impl ReadOptions<'_> {
+    // The options of the reads below the memtables, which have already
+    // resolved the snapshot to a sequence number
+    pub(crate) fn without_snapshot(&self) -> ReadOptions<'static> {
+        ReadOptions {
+            verify_checksums: self.verify_checksums,
+            fill_cache: self.fill_cache,
+            snapshot: None,
+        }
+    }
+}
+
+impl Default for ReadOptions<'_> {
+    fn default() -> Self {
+        ReadOptions {
+            verify_checksums: false,
+            fill_cache: true,
+            snapshot: None,
+        }
+    }
+}
+
+/// Options that control write operations
+#[derive(Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// If true, the write will be flushed from the operating system
+    /// buffer cache (by calling `WritableFile::sync()`) before the write
+    /// is considered complete.  If this flag is true, writes will be
+    /// slower.
+    ///
+    /// If this flag is false, and the machine crashes, some recent
+    /// writes may be lost.  Note that if it is just the process that
+    /// crashes (i.e., the machine does not reboot), no writes will be
+    /// lost even if sync==false.
+    ///
+    /// In other words, a DB write with sync==false has similar
+    /// crash semantics as the "write()" system call.  A DB write
+    /// with sync==true has similar crash semantics to a "write()"
+    /// system call followed by "fsync()".
+    pub sync: bool,
+}
@@ -0,0 +1,510 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A `Cache` is an interface that maps keys to values.  It has internal
+/// synchronization and may be safely accessed concurrently from
+/// multiple threads.  It may automatically evict entries to make room
+/// for new entries.  Values have a specified charge against the cache
+/// capacity.  For example, a cache where the values are variable
+/// length strings, may use the length of the string as the charge for
+/// the string.
+///
+/// Entries are handed out through refcounted `CacheHandle`s: an entry
+/// is never evicted while a handle to it is alive, and its value is
+/// dropped once it is out of the cache and every handle is gone.
+pub trait Cache<K: Hash + Eq + Clone, V> {
+    /// Insert a mapping from `key`->`value` into the cache and assign it
+    /// the specified charge against the total cache capacity.  A mapping
+    /// already present for `key` is replaced.
+    ///
+    /// Returns a handle that corresponds to the mapping.  The mapping is
+    /// released when the handle is dropped.
+    fn insert(&self, key: K, value: V, charge: usize) -> CacheHandle<K, V>;
+
+    /// If the cache has no mapping for `key`, returns `None`.
+    ///
+    /// Else return a handle that corresponds to the mapping.  The mapping
+    /// is released when the handle is dropped.
+    fn lookup(&self, key: &K) -> Option<CacheHandle<K, V>>;
+
+    /// If the cache contains entry for `key`, erase it.  Note that the
+    /// underlying entry will be kept around until all existing handles
+    /// to it have been dropped.
+    fn erase(&self, key: &K);
+
+    /// Return a new numeric id.  May be used by multiple clients who are
+    /// sharing the same cache to partition the key space.  Typically the
+    /// client will allocate a new id at startup and prepend the id to
+    /// its cache keys.
+    fn new_id(&self) -> u64;
+
+    /// Remove all cache entries that are not actively in use.  Memory-
+    /// constrained applications may wish to call this method to reduce
+    /// memory usage.
+    fn prune(&self);
+
+    /// Return an estimate of the combined charges of all elements stored
+    /// in the cache.
+    fn total_charge(&self) -> usize;
+}
+
+/// A reference to an entry of a `Cache`.  Dropping the handle releases
+/// the entry: once no handle to it is left, it may be evicted.
+pub struct CacheHandle<K: Hash + Eq + Clone, V> {
+    entry: Arc<CacheEntry<K, V>>,
+    // The shard holding the entry
+    shard: Arc<Mutex<LRUShard<K, V>>>,
+}
+
+struct CacheEntry<K, V> {
+    key: K,
+    value: V,
+    charge: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> CacheHandle<K, V> {
+    pub fn key(&self) -> &K {
+        &self.entry.key
+    }
+
+    pub fn value(&self) -> &V {
+        &self.entry.value
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Drop for CacheHandle<K, V> {
+    fn drop(&mut self) {
+        self.shard.lock().unwrap().release(&self.entry);
+    }
+}
+
+// The state kept by a shard for each entry it holds
+struct LRUEntry<K, V> {
+    entry: Arc<CacheEntry<K, V>>,
+    // Number of handles not released yet
+    refs: usize,
+    // Position in the lru list.  None while the entry is in use.
+    lru_tick: Option<u64>,
+}
+
+// A single shard of sharded cache.
+//
+// The entries of `table` are either in use by clients (`refs > 0`) or
+// in `lru`, ordered by their last release, and only the latter may be
+// evicted.  An erased entry that is still in use lives on in its handles.
+struct LRUShard<K, V> {
+    capacity: usize,
+    usage: usize,
+    table: HashMap<K, LRUEntry<K, V>>,
+    lru: BTreeMap<u64, K>,
+    // Increases on every release, the oldest entry of `lru` has the
+    // smallest tick
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LRUShard<K, V> {
+    fn new(capacity: usize) -> Self {
+        LRUShard {
+            capacity,
+            usage: 0,
+            table: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn insert(&mut self, key: K, value: V, charge: usize) -> Arc<CacheEntry<K, V>> {
+        let entry = Arc::new(CacheEntry {
+            key: key.clone(),
+            value,
+            charge,
+        });
+        // capacity == 0 turns off caching
+        if self.capacity > 0 {
+            self.remove(&key);
+            self.usage += charge;
+            self.table.insert(
+                key,
+                LRUEntry {
+                    entry: entry.clone(),
+                    refs: 1,
+                    lru_tick: None,
+                },
+            );
+            while self.usage > self.capacity {
+                match self.lru.keys().next().copied() {
+                    Some(oldest) => {
+                        let key = self.lru.remove(&oldest).unwrap();
+                        self.remove(&key);
+                    }
+                    None => break,
+                }
+            }
+        }
+        entry
+    }
+
+    fn lookup(&mut self, key: &K) -> Option<Arc<CacheEntry<K, V>>> {
+        let e = self.table.get_mut(key)?;
+        if let Some(tick) = e.lru_tick.take() {
+            self.lru.remove(&tick);
+        }
+        e.refs += 1;
+        Some(e.entry.clone())
+    }
+
+    fn release(&mut self, entry: &Arc<CacheEntry<K, V>>) {
+        let key = &entry.key;
+        match self.table.get_mut(key) {
+            // The entry may have been erased or replaced since
+            Some(e) if Arc::ptr_eq(&e.entry, entry) => {
+                e.refs -= 1;
+                if e.refs == 0 {
+                    // No longer in use; move to lru list
+                    self.tick += 1;
+                    e.lru_tick = Some(self.tick);
+                    self.lru.insert(self.tick, key.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Removes the entry of `key` from the cache
+    fn remove(&mut self, key: &K) {
+        if let Some(e) = self.table.remove(key) {
+            if let Some(tick) = e.lru_tick {
+                self.lru.remove(&tick);
+            }
+            self.usage -= e.entry.charge;
+        }
+    }
+
+    fn prune(&mut self) {
+        while let Some(oldest) = self.lru.keys().next().copied() {
+            let key = self.lru.remove(&oldest).unwrap();
+            self.remove(&key);
+        }
+    }
+}
+
+const NUM_SHARD_BITS: usize = 4;
+const NUM_SHARDS: usize = 1 << NUM_SHARD_BITS;
+
+/// A `Cache` with a least-recently-used eviction policy.  Keys are
+/// spread over several shards by their hash, each shard owning an equal
+/// part of the capacity, so that concurrent readers rarely contend on
+/// the same lock.
+pub struct ShardedLRUCache<K, V> {
+    shards: Vec<Arc<Mutex<LRUShard<K, V>>>>,
+    last_id: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V> ShardedLRUCache<K, V> {
+    /// Create a new cache with a fixed size capacity
+    pub fn new(capacity: usize) -> Self {
+        let per_shard = (capacity + (NUM_SHARDS - 1)) / NUM_SHARDS;
+        ShardedLRUCache {
+            shards: (0..NUM_SHARDS)
+                .map(|_| Arc::new(Mutex::new(LRUShard::new(per_shard))))
+                .collect(),
+            last_id: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, key: &K) -> &Arc<Mutex<LRUShard<K, V>>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        &self.shards[(hash >> (64 - NUM_SHARD_BITS)) as usize]
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cache<K, V> for ShardedLRUCache<K, V> {
+    fn insert(&self, key: K, value: V, charge: usize) -> CacheHandle<K, V> {
+        let shard = self.shard(&key);
+        let entry = shard.lock().unwrap().insert(key, value, charge);
+        CacheHandle {
+            entry,
+            shard: shard.clone(),
+        }
+    }
+
+    fn lookup(&self, key: &K) -> Option<CacheHandle<K, V>> {
+        let shard = self.shard(key);
+        let entry = shard.lock().unwrap().lookup(key)?;
+        Some(CacheHandle {
+            entry,
+            shard: shard.clone(),
+        })
+    }
+
+    fn erase(&self, key: &K) {
+        self.shard(key).lock().unwrap().remove(key)
+    }
+
+    fn new_id(&self) -> u64 {
+        self.last_id.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn prune(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().prune();
+        }
+    }
+
+    fn total_charge(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().usage)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CACHE_SIZE: usize = 1000;
+
+    // Records the values dropped by the cache
+    struct Value {
+        v: u32,
+        deleted: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Drop for Value {
+        fn drop(&mut self) {
+            self.deleted.lock().unwrap().push(self.v);
+        }
+    }
+
+    struct CacheTest {
+        cache: ShardedLRUCache<u32, Value>,
+        deleted: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl CacheTest {
+        fn new() -> Self {
+            CacheTest {
+                cache: ShardedLRUCache::new(CACHE_SIZE),
+                deleted: Arc::new(Mutex::new(vec![])),
+            }
+        }
+
+        fn lookup(&self, key: u32) -> Option<u32> {
+            self.cache.lookup(&key).map(|h| h.value().v)
+        }
+
+        fn insert(&self, key: u32, v: u32, charge: usize) {
+            self.insert_and_return_handle(key, v, charge);
+        }
+
+        fn insert_and_return_handle(
+            &self,
+            key: u32,
+            v: u32,
+            charge: usize,
+        ) -> CacheHandle<u32, Value> {
+            let value = Value {
+                v,
+                deleted: self.deleted.clone(),
+            };
+            self.cache.insert(key, value, charge)
+        }
+
+        fn deleted(&self) -> Vec<u32> {
+            self.deleted.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    fn test_hit_and_miss() {
+        let t = CacheTest::new();
+        assert_eq!(t.lookup(100), None);
+
+        t.insert(100, 101, 1);
+        assert_eq!(t.lookup(100), Some(101));
+        assert_eq!(t.lookup(200), None);
+        assert_eq!(t.lookup(300), None);
+
+        t.insert(200, 201, 1);
+        assert_eq!(t.lookup(100), Some(101));
+        assert_eq!(t.lookup(200), Some(201));
+        assert_eq!(t.lookup(300), None);
+
+        t.insert(100, 102, 1);
+        assert_eq!(t.lookup(100), Some(102));
+        assert_eq!(t.lookup(200), Some(201));
+        assert_eq!(t.lookup(300), None);
+        assert_eq!(t.deleted(), vec![101]);
+    }
+
+    #[test]
+    fn test_erase() {
+        let t = CacheTest::new();
+        t.cache.erase(&200);
+        assert!(t.deleted().is_empty());
+
+        t.insert(100, 101, 1);
+        t.insert(200, 201, 1);
+        t.cache.erase(&100);
+        assert_eq!(t.lookup(100), None);
+        assert_eq!(t.lookup(200), Some(201));
+        assert_eq!(t.deleted(), vec![101]);
+
+        t.cache.erase(&100);
+        assert_eq!(t.lookup(100), None);
+        assert_eq!(t.lookup(200), Some(201));
+        assert_eq!(t.deleted(), vec![101]);
+    }
+
+    #[test]
+    fn test_entries_are_pinned() {
+        let t = CacheTest::new();
+        t.insert(100, 101, 1);
+        let h1 = t.cache.lookup(&100).unwrap();
+        assert_eq!(h1.value().v, 101);
+
+        t.insert(100, 102, 1);
+        let h2 = t.cache.lookup(&100).unwrap();
+        assert_eq!(h2.value().v, 102);
+        assert!(t.deleted().is_empty());
+
+        drop(h1);
+        assert_eq!(t.deleted(), vec![101]);
+
+        t.cache.erase(&100);
+        assert_eq!(t.lookup(100), None);
+        assert_eq!(t.deleted(), vec![101]);
+
+        drop(h2);
+        assert_eq!(t.deleted(), vec![101, 102]);
+    }
+
+    #[test]
+    fn test_eviction_policy() {
+        let t = CacheTest::new();
+        t.insert(100, 101, 1);
+        t.insert(200, 201, 1);
+        t.insert(300, 301, 1);
+        let h = t.cache.lookup(&300).unwrap();
+
+        // Frequently used entry must be kept around,
+        // as must things that are still in use.
+        for i in 0..(CACHE_SIZE + 100) as u32 {
+            t.insert(1000 + i, 2000 + i, 1);
+            assert_eq!(t.lookup(1000 + i), Some(2000 + i));
+            assert_eq!(t.lookup(100), Some(101));
+        }
+        assert_eq!(t.lookup(100), Some(101));
+        assert_eq!(t.lookup(200), None);
+        assert_eq!(t.lookup(300), Some(301));
+        drop(h);
+    }
+
+    #[test]
+    fn test_dropped_handle_is_released() {
+        let t = CacheTest::new();
+        let h = t.insert_and_return_handle(100, 101, 1);
+        let h2 = t.cache.lookup(&100).unwrap();
+        drop(h);
+        // Still pinned by the second handle
+        for i in 0..(CACHE_SIZE + 100) as u32 {
+            t.insert(1000 + i, 2000 + i, 1);
+        }
+        assert_eq!(h2.value().v, 101);
+        assert!(t.deleted().iter().all(|&v| v != 101));
+
+        // Once every handle is dropped the entry can be evicted
+        drop(h2);
+        for i in 0..(CACHE_SIZE + 100) as u32 {
+            t.insert(5000 + i, 6000 + i, 1);
+        }
+        assert_eq!(t.lookup(100), None);
+        assert!(t.deleted().contains(&101));
+    }
+
+    #[test]
+    fn test_use_exceeds_cache_size() {
+        let t = CacheTest::new();
+        // Overfill the cache, keeping handles on all inserted entries.
+        let handles: Vec<_> = (0..(CACHE_SIZE + 100) as u32)
+            .map(|i| t.insert_and_return_handle(1000 + i, 2000 + i, 1))
+            .collect();
+
+        // Check that all the entries can be found in the cache.
+        for i in 0..handles.len() as u32 {
+            assert_eq!(t.lookup(1000 + i), Some(2000 + i));
+        }
+        for h in handles {
+            drop(h);
+        }
+    }
+
+    #[test]
+    fn test_heavy_entries() {
+        // Add a bunch of light and heavy entries and then count the combined
+        // size of items still in the cache, which must be approximately the
+        // same as the total capacity.
+        let t = CacheTest::new();
+        const LIGHT: usize = 1;
+        const HEAVY: usize = 10;
+        let mut added = 0;
+        let mut index = 0;
+        while added < 2 * CACHE_SIZE {
+            let weight = if index & 1 == 0 { LIGHT } else { HEAVY };
+            t.insert(index, 1000 + index, weight);
+            added += weight;
+            index += 1;
+        }
+
+        let mut cached_weight = 0;
+        for i in 0..index {
+            let weight = if i & 1 == 0 { LIGHT } else { HEAVY };
+            if let Some(r) = t.lookup(i) {
+                cached_weight += weight;
+                assert_eq!(r, 1000 + i);
+            }
+        }
+        assert!(cached_weight <= CACHE_SIZE + CACHE_SIZE / 10);
+        assert_eq!(t.cache.total_charge(), cached_weight);
+    }
+
+    #[test]
+    fn test_new_id() {
+        let t = CacheTest::new();
+        let a = t.cache.new_id();
+        let b = t.cache.new_id();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_prune() {
+        let t = CacheTest::new();
+        t.insert(1, 100, 1);
+        t.insert(2, 200, 1);
+
+        let h = t.cache.lookup(&1).unwrap();
+        t.cache.prune();
+        drop(h);
+
+        assert_eq!(t.lookup(1), Some(100));
+        assert_eq!(t.lookup(2), None);
+        assert_eq!(t.cache.total_charge(), 1);
+    }
+
+    #[test]
+    fn test_zero_size_cache() {
+        let t = CacheTest {
+            cache: ShardedLRUCache::new(0),
+            deleted: Arc::new(Mutex::new(vec![])),
+        };
+        t.insert(1, 100, 1);
+        assert_eq!(t.lookup(1), None);
+        assert_eq!(t.deleted(), vec![100]);
+    }
+}
@@ -66,7 +66,20 @@ pub fn get_varint_32_prefix_ptr(p: usize, limit: usize, key: &[u8]) -> Option<(u
 }
 pub fn extract_length_prefixed_slice(data: &[u8]) -> &[u8] {
     let (len, size) = get_varint_32_prefix_ptr(0, 5, &data).unwrap();
-    &data[1..size + len as usize]
+    &data[size..size + len as usize]
+}
+
+// Decodes a length prefixed slice from the front of `input` and advances
+// `input` past it. Returns `None` if `input` is malformed.
+pub fn read_length_prefixed_slice<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let (len, size) = get_varint_32(input)?;
+    let end = size + len as usize;
+    if input.len() < end {
+        return None;
+    }
+    let result = &input[size..end];
+    *input = &input[end..];
+    Some(result)
 }
 pub fn put_length_prefixed_slice(dst: &mut Vec<u8>, value: &[u8]) {
     put_varint_32(dst, value.len() as u32);
@@ -87,6 +100,20 @@ pub fn get_varint_32(input: &[u8]) -> Option<(u32, usize)> {
 pub fn get_varint_64(input: &[u8]) -> Option<(u64, usize)> {
     u64::decode_var(input)
 }
+
+// Decodes a varint32 from the front of `input` and advances `input` past it.
+pub fn read_varint_32(input: &mut &[u8]) -> Option<u32> {
+    let (value, size) = get_varint_32(input)?;
+    *input = &input[size..];
+    Some(value)
+}
+
+// Decodes a varint64 from the front of `input` and advances `input` past it.
+pub fn read_varint_64(input: &mut &[u8]) -> Option<u64> {
+    let (value, size) = get_varint_64(input)?;
+    *input = &input[size..];
+    Some(value)
+}
 pub fn get_fixed_32(input: &[u8]) -> u32 {
     u32::decode_fixed(input)
 }
@@ -115,10 +142,156 @@ pub fn decode_fixed_64(input: &[u8]) -> u64 {
         _ => u64::decode_fixed(&input[0..8]),
     }
 }
+// Memcomparable encodings: the encoded keys compare by byte order (as with
+// `BytewiseComparator`) the same way the values they encode compare
+// logically.  Smaller integers are encoded by widening them to 64 bits.
+
+const SIGN_BIT: u64 = 1 << 63;
+
+pub fn put_memcomparable_u64(dst: &mut Vec<u8>, value: u64) {
+    dst.extend_from_slice(&value.to_be_bytes());
+}
+
+// Decodes a memcomparable u64 from the front of `input` and advances
+// `input` past it.
+pub fn read_memcomparable_u64(input: &mut &[u8]) -> Option<u64> {
+    if input.len() < 8 {
+        return None;
+    }
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&input[..8]);
+    *input = &input[8..];
+    Some(u64::from_be_bytes(buf))
+}
+
+/// Flipping the sign bit moves the negative values before the positive ones
+pub fn put_memcomparable_i64(dst: &mut Vec<u8>, value: i64) {
+    put_memcomparable_u64(dst, value as u64 ^ SIGN_BIT);
+}
+
+pub fn read_memcomparable_i64(input: &mut &[u8]) -> Option<i64> {
+    read_memcomparable_u64(input).map(|v| (v ^ SIGN_BIT) as i64)
+}
+
+/// The values are ordered as by `f64::total_cmp`: -NaN < -inf < ... < -0.0
+/// < 0.0 < ... < inf < NaN.  Positive values get their sign bit set and
+/// negative values get all their bits flipped, so that larger magnitudes
+/// come first.
+pub fn put_memcomparable_f64(dst: &mut Vec<u8>, value: f64) {
+    let bits = value.to_bits();
+    let bits = if bits & SIGN_BIT == 0 {
+        bits | SIGN_BIT
+    } else {
+        !bits
+    };
+    put_memcomparable_u64(dst, bits);
+}
+
+pub fn read_memcomparable_f64(input: &mut &[u8]) -> Option<f64> {
+    let bits = read_memcomparable_u64(input)?;
+    let bits = if bits & SIGN_BIT != 0 {
+        bits & !SIGN_BIT
+    } else {
+        !bits
+    };
+    Some(f64::from_bits(bits))
+}
+
+/// Every `0x00` byte is escaped as `0x00 0xff` and the string ends with
+/// `0x00 0x01`, so that a string sorts before its extensions and the
+/// encoding can be followed by other values.
+pub fn put_memcomparable_bytes(dst: &mut Vec<u8>, value: &[u8]) {
+    for &b in value {
+        dst.push(b);
+        if b == 0 {
+            dst.push(0xff);
+        }
+    }
+    dst.extend_from_slice(&[0x00, 0x01]);
+}
+
+pub fn read_memcomparable_bytes(input: &mut &[u8]) -> Option<Vec<u8>> {
+    let mut value = vec![];
+    let mut i = 0;
+    loop {
+        match input.get(i)? {
+            0 => match input.get(i + 1)? {
+                0xff => value.push(0),
+                0x01 => break,
+                _ => return None,
+            },
+            &b => {
+                value.push(b);
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+    *input = &input[i + 2..];
+    Some(value)
+}
+
+/// A component of a composite key encoded by `put_memcomparable_tuple`
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyPart {
+    Bytes(Vec<u8>),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+}
+
+impl KeyPart {
+    // The byte written before the encoding of the part.  Parts of
+    // different types order by their tag.
+    fn tag(&self) -> u8 {
+        match self {
+            KeyPart::Bytes(_) => 1,
+            KeyPart::I64(_) => 2,
+            KeyPart::U64(_) => 3,
+            KeyPart::F64(_) => 4,
+        }
+    }
+}
+
+/// Encodes `parts` so that the tuples compare part by part, a tuple
+/// sorting before its extensions.  Each part is prefixed by a tag telling
+/// its type, which orders the parts of different types.
+pub fn put_memcomparable_tuple(dst: &mut Vec<u8>, parts: &[KeyPart]) {
+    for part in parts {
+        dst.push(part.tag());
+        match part {
+            KeyPart::Bytes(value) => put_memcomparable_bytes(dst, value),
+            KeyPart::I64(value) => put_memcomparable_i64(dst, *value),
+            KeyPart::U64(value) => put_memcomparable_u64(dst, *value),
+            KeyPart::F64(value) => put_memcomparable_f64(dst, *value),
+        }
+    }
+}
+
+// Decodes a tuple spanning the whole `input`
+pub fn decode_memcomparable_tuple(mut input: &[u8]) -> Option<Vec<KeyPart>> {
+    let mut parts = vec![];
+    while let Some((&tag, rest)) = input.split_first() {
+        input = rest;
+        let part = match tag {
+            1 => KeyPart::Bytes(read_memcomparable_bytes(&mut input)?),
+            2 => KeyPart::I64(read_memcomparable_i64(&mut input)?),
+            3 => KeyPart::U64(read_memcomparable_u64(&mut input)?),
+            4 => KeyPart::F64(read_memcomparable_f64(&mut input)?),
+            _ => return None,
+        };
+        parts.push(part);
+    }
+    Some(parts)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use integer_encoding::{self, FixedInt, VarInt};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     #[test]
     fn test_encode() {
         println!("{:?}", 226u64.encode_fixed_light());
@@ -344,6 +517,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_extract_and_read_length_prefixed_slice() {
+        let mut buf = vec![];
+        let long = vec![7u8; 300];
+        put_length_prefixed_slice(&mut buf, &long);
+        put_length_prefixed_slice(&mut buf, b"foo");
+        assert_eq!(extract_length_prefixed_slice(&buf), long.as_slice());
+
+        let mut input = buf.as_slice();
+        assert_eq!(
+            read_length_prefixed_slice(&mut input),
+            Some(long.as_slice())
+        );
+        assert_eq!(read_length_prefixed_slice(&mut input), Some(&b"foo"[..]));
+        assert!(input.is_empty());
+        assert_eq!(read_length_prefixed_slice(&mut input), None);
+
+        // Truncated slice
+        let mut input = &buf[..100];
+        assert_eq!(read_length_prefixed_slice(&mut input), None);
+    }
+
+    #[test]
+    fn test_read_varint() {
+        let mut buf = vec![];
+        put_varint_32(&mut buf, 300);
+        put_varint_64(&mut buf, 1 << 40);
+        put_varint_32(&mut buf, 7);
+        let mut input = buf.as_slice();
+        assert_eq!(read_varint_32(&mut input), Some(300));
+        assert_eq!(read_varint_64(&mut input), Some(1 << 40));
+        assert_eq!(read_varint_32(&mut input), Some(7));
+        assert!(input.is_empty());
+        assert_eq!(read_varint_64(&mut input), None);
+    }
+
     #[test]
     fn test_put_and_get_prefixed_slice() {
         let mut encoded: Vec<Vec<u8>> = vec![];
@@ -371,4 +580,198 @@ mod tests {
             }
         }
     }
+
+    // Checks that the encodings of `values` compare by byte order as
+    // `cmp` orders the values
+    fn check_order<T: std::fmt::Debug>(
+        values: &[T],
+        encode: impl Fn(&T) -> Vec<u8>,
+        cmp: impl Fn(&T, &T) -> std::cmp::Ordering,
+    ) {
+        let encoded: Vec<Vec<u8>> = values.iter().map(encode).collect();
+        for (a, ea) in values.iter().zip(&encoded) {
+            for (b, eb) in values.iter().zip(&encoded) {
+                assert_eq!(ea.cmp(eb), cmp(a, b), "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    // A random generator with a fresh seed.  The seed is printed, so the
+    // output of a failed test tells how to replay it.
+    fn seeded_rng() -> StdRng {
+        let seed = rand::random();
+        println!("rng seed: {}", seed);
+        StdRng::seed_from_u64(seed)
+    }
+
+    fn random_bytes(rng: &mut StdRng) -> Vec<u8> {
+        // Few distinct bytes, including the escaped ones, so that the
+        // strings share prefixes
+        let alphabet = [0x00, 0x01, b'a', 0xff];
+        (0..rng.gen_range(0, 6))
+            .map(|_| alphabet[rng.gen_range(0, alphabet.len())])
+            .collect()
+    }
+
+    fn random_key_part(rng: &mut StdRng) -> KeyPart {
+        match rng.gen_range(0, 4) {
+            0 => KeyPart::Bytes(random_bytes(rng)),
+            1 => KeyPart::I64(rng.gen::<i64>() % 3),
+            2 => KeyPart::U64(rng.gen::<u64>() % 3),
+            _ => KeyPart::F64([-1.5, -0.0, 0.0, 2.5][rng.gen_range(0, 4)]),
+        }
+    }
+
+    fn cmp_key_parts(a: &KeyPart, b: &KeyPart) -> std::cmp::Ordering {
+        match (a, b) {
+            (KeyPart::Bytes(a), KeyPart::Bytes(b)) => a.cmp(b),
+            (KeyPart::I64(a), KeyPart::I64(b)) => a.cmp(b),
+            (KeyPart::U64(a), KeyPart::U64(b)) => a.cmp(b),
+            (KeyPart::F64(a), KeyPart::F64(b)) => a.total_cmp(b),
+            _ => a.tag().cmp(&b.tag()),
+        }
+    }
+
+    #[test]
+    fn test_memcomparable_integers() {
+        let mut unsigned = vec![0, 1, 255, 256, u64::MAX - 1, u64::MAX];
+        let mut signed = vec![i64::MIN, i64::MIN + 1, -256, -1, 0, 1, 255, i64::MAX];
+        let mut rng = seeded_rng();
+        for _ in 0..200 {
+            unsigned.push(rng.gen());
+            unsigned.push(rng.gen::<u64>() % 1000);
+            signed.push(rng.gen());
+            signed.push(rng.gen::<i64>() % 1000);
+        }
+        check_order(
+            &unsigned,
+            |v| {
+                let mut buf = vec![];
+                put_memcomparable_u64(&mut buf, *v);
+                buf
+            },
+            |a, b| a.cmp(b),
+        );
+        check_order(
+            &signed,
+            |v| {
+                let mut buf = vec![];
+                put_memcomparable_i64(&mut buf, *v);
+                buf
+            },
+            |a, b| a.cmp(b),
+        );
+
+        let mut buf = vec![];
+        for (u, i) in unsigned.iter().zip(&signed) {
+            put_memcomparable_u64(&mut buf, *u);
+            put_memcomparable_i64(&mut buf, *i);
+        }
+        let mut input = buf.as_slice();
+        for (u, i) in unsigned.iter().zip(&signed) {
+            assert_eq!(read_memcomparable_u64(&mut input), Some(*u));
+            assert_eq!(read_memcomparable_i64(&mut input), Some(*i));
+        }
+        assert!(input.is_empty());
+        assert_eq!(read_memcomparable_u64(&mut &[1, 2, 3][..]), None);
+    }
+
+    #[test]
+    fn test_memcomparable_f64() {
+        let mut values = vec![
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.0,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            f64::from_bits(1),
+            f64::MIN_POSITIVE,
+            1.0,
+            f64::MAX,
+            f64::INFINITY,
+            f64::NAN,
+            -f64::NAN,
+        ];
+        let mut rng = seeded_rng();
+        for _ in 0..200 {
+            // Any bit pattern, NaNs included
+            values.push(f64::from_bits(rng.gen()));
+            values.push(rng.gen::<f64>() * 2000.0 - 1000.0);
+        }
+        check_order(
+            &values,
+            |v| {
+                let mut buf = vec![];
+                put_memcomparable_f64(&mut buf, *v);
+                buf
+            },
+            |a, b| a.total_cmp(b),
+        );
+        for v in values {
+            let mut buf = vec![];
+            put_memcomparable_f64(&mut buf, v);
+            let decoded = read_memcomparable_f64(&mut buf.as_slice()).unwrap();
+            assert_eq!(decoded.to_bits(), v.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_memcomparable_bytes() {
+        let mut values = vec![vec![], vec![0], vec![0, 0], vec![0, 1], vec![0xff], vec![1]];
+        let mut rng = seeded_rng();
+        for _ in 0..300 {
+            values.push(random_bytes(&mut rng));
+        }
+        let encode = |v: &Vec<u8>| {
+            let mut buf = vec![];
+            put_memcomparable_bytes(&mut buf, v);
+            buf
+        };
+        check_order(&values, encode, |a, b| a.cmp(b));
+        for v in &values {
+            // The encoding delimits itself
+            let mut buf = encode(v);
+            put_memcomparable_u64(&mut buf, 7);
+            let mut input = buf.as_slice();
+            assert_eq!(read_memcomparable_bytes(&mut input).as_ref(), Some(v));
+            assert_eq!(read_memcomparable_u64(&mut input), Some(7));
+        }
+        // Missing terminator, bad escape
+        assert_eq!(read_memcomparable_bytes(&mut &b"ab"[..]), None);
+        assert_eq!(read_memcomparable_bytes(&mut &b"a\x00"[..]), None);
+        assert_eq!(read_memcomparable_bytes(&mut &b"a\x00\x02"[..]), None);
+    }
+
+    #[test]
+    fn test_memcomparable_tuple() {
+        let mut tuples = vec![vec![]];
+        let mut rng = seeded_rng();
+        for _ in 0..300 {
+            let len = rng.gen_range(0, 4);
+            tuples.push((0..len).map(|_| random_key_part(&mut rng)).collect());
+        }
+        let encode = |parts: &Vec<KeyPart>| {
+            let mut buf = vec![];
+            put_memcomparable_tuple(&mut buf, parts);
+            buf
+        };
+        check_order(&tuples, encode, |a, b| {
+            a.iter()
+                .zip(b.iter())
+                .map(|(a, b)| cmp_key_parts(a, b))
+                .find(|o| *o != std::cmp::Ordering::Equal)
+                .unwrap_or_else(|| a.len().cmp(&b.len()))
+        });
+        for parts in &tuples {
+            assert_eq!(
+                decode_memcomparable_tuple(&encode(parts)).as_ref(),
+                Some(parts)
+            );
+        }
+        // Unknown tag, truncated part
+        assert_eq!(decode_memcomparable_tuple(&[9, 0]), None);
+        let encoded = encode(&vec![KeyPart::U64(3)]);
+        assert_eq!(decode_memcomparable_tuple(&encoded[..5]), None);
+    }
 }
@@ -2,11 +2,31 @@
 
 // use super::ldbslice::Slice;
 use bytes::{buf::Limit, Bytes as Slice};
-pub trait Comparator {
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// A Comparator object provides a total order across slices that are
+/// used as keys in an sstable or a database.  A Comparator implementation
+/// must be thread-safe since leveldb may invoke its methods concurrently
+/// from multiple threads.
+///
+/// The trait is object safe: a comparator picked at runtime can be used
+/// as an `Arc<dyn Comparator>`.
+pub trait Comparator: Send + Sync {
     // Three-way comparison.  Returns value:
     fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering;
 
-    fn name() -> String;
+    /// The name of the comparator.  Used to check for comparator
+    /// mismatches (i.e., a DB created with one comparator is
+    /// accessed using a different comparator.)
+    ///
+    /// The client of this package should switch to a new name whenever
+    /// the comparator implementation changes in a way that will cause
+    /// the relative ordering of any two keys to change.
+    ///
+    /// Names starting with "leveldb." are reserved and should not be used
+    /// by any clients of this package.
+    fn name(&self) -> &str;
 
     // If start < limit, return a string in [start,limit).
     // Simple comparator implementations may return with *start unchanged,
@@ -23,8 +43,8 @@ pub trait Comparator {
 pub struct BytewiseComparator {}
 
 impl Comparator for BytewiseComparator {
-    fn name() -> String {
-        String::from("leveldb.BytewiseComparator")
+    fn name(&self) -> &str {
+        "leveldb.BytewiseComparator"
     }
 
     fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
@@ -83,9 +103,107 @@ impl Comparator for BytewiseComparator {
     }
 }
 
+/// Orders the keys by decreasing byte order, the reverse of
+/// `BytewiseComparator`
+#[derive(Default, Clone)]
+pub struct ReverseBytewiseComparator {}
+
+impl Comparator for ReverseBytewiseComparator {
+    fn name(&self) -> &str {
+        "leveldb.ReverseBytewiseComparator"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        b.cmp(a)
+    }
+
+    /// `start` comes first iff it is bytewise larger than `limit`.  Once
+    /// they differ, the prefix of `start` ending at the first different
+    /// byte is still bytewise larger than `limit`, and no larger than
+    /// `start`.
+    fn find_shortest_separator(&self, start: &[u8], limit: &[u8]) -> Vec<u8> {
+        let min_len = usize::min(start.len(), limit.len());
+        let mut diff_index = 0;
+        while diff_index < min_len && start[diff_index] == limit[diff_index] {
+            diff_index += 1;
+        }
+        if diff_index < min_len && start[diff_index] > limit[diff_index] {
+            return start[..=diff_index].to_owned();
+        }
+        start.to_owned()
+    }
+
+    fn find_short_successor(&self, key: &[u8]) -> Vec<u8> {
+        key.to_owned()
+    }
+}
+
+/// Orders keys that are 8 byte big-endian `u64`s (as encoded by
+/// `put_memcomparable_u64`) by their numeric value.  Unlike
+/// `BytewiseComparator`, it never shortens the keys, so that every key
+/// the db compares is 8 bytes long.  Keys of another length are ordered
+/// bytewise, which keeps the order total.
+#[derive(Default, Clone)]
+pub struct U64Comparator {}
+
+impl U64Comparator {
+    fn decode(key: &[u8]) -> Option<u64> {
+        <[u8; 8]>::try_from(key).ok().map(u64::from_be_bytes)
+    }
+}
+
+impl Comparator for U64Comparator {
+    fn name(&self) -> &str {
+        "leveldb.U64Comparator"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        match (Self::decode(a), Self::decode(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        }
+    }
+
+    fn find_shortest_separator(&self, start: &[u8], _limit: &[u8]) -> Vec<u8> {
+        start.to_owned()
+    }
+
+    fn find_short_successor(&self, key: &[u8]) -> Vec<u8> {
+        key.to_owned()
+    }
+}
+
+impl<C: Comparator + ?Sized> Comparator for Arc<C> {
+    fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        (**self).compare(a, b)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn find_shortest_separator(&self, start: &[u8], limit: &[u8]) -> Vec<u8> {
+        (**self).find_shortest_separator(start, limit)
+    }
+
+    fn find_short_successor(&self, key: &[u8]) -> Vec<u8> {
+        (**self).find_short_successor(key)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // A random generator with a fresh seed.  The seed is printed, so the
+    // output of a failed test tells how to replay it.
+    fn seeded_rng() -> StdRng {
+        let seed = rand::random();
+        println!("rng seed: {}", seed);
+        StdRng::seed_from_u64(seed)
+    }
 
     #[test]
     fn test_bytewise_comparator_separator() {
@@ -130,4 +248,70 @@ mod tests {
             assert_eq!(res, expect)
         }
     }
+
+    #[test]
+    fn test_reverse_bytewise_comparator() {
+        let c = ReverseBytewiseComparator::default();
+        assert_eq!(c.compare(b"a", b"b"), std::cmp::Ordering::Greater);
+        assert_eq!(c.compare(b"ab", b"a"), std::cmp::Ordering::Less);
+        let mut tests = vec![
+            ("1357", "1234", "13"),
+            ("2", "1", "2"),
+            ("1234", "123", "1234"),
+            ("123", "1234", "123"),
+            ("1111", "1111", "1111"),
+        ];
+        for (a, b, expect) in tests.drain(..) {
+            let res = c.find_shortest_separator(a.as_bytes(), b.as_bytes());
+            assert_eq!(std::str::from_utf8(&res).unwrap(), expect);
+        }
+        // The separator of any ordered pair is between them
+        let mut rng = seeded_rng();
+        for _ in 0..1000 {
+            let start: Vec<u8> = (0..rng.gen_range(0, 5))
+                .map(|_| rng.gen_range(0, 4))
+                .collect();
+            let limit: Vec<u8> = (0..rng.gen_range(0, 5))
+                .map(|_| rng.gen_range(0, 4))
+                .collect();
+            if c.compare(&start, &limit) != std::cmp::Ordering::Less {
+                continue;
+            }
+            let sep = c.find_shortest_separator(&start, &limit);
+            assert_ne!(c.compare(&sep, &start), std::cmp::Ordering::Less);
+            assert_eq!(c.compare(&sep, &limit), std::cmp::Ordering::Less);
+        }
+        assert_eq!(c.find_short_successor(b"abc"), b"abc");
+    }
+
+    #[test]
+    fn test_u64_comparator() {
+        let c = U64Comparator::default();
+        let mut rng = seeded_rng();
+        for _ in 0..1000 {
+            let (a, b) = (rng.gen::<u64>(), rng.gen::<u64>() >> 32);
+            assert_eq!(c.compare(&a.to_be_bytes(), &b.to_be_bytes()), a.cmp(&b));
+        }
+        assert_eq!(
+            c.compare(&1u64.to_be_bytes(), &256u64.to_be_bytes()),
+            std::cmp::Ordering::Less
+        );
+        let (start, limit) = (5u64.to_be_bytes(), 9u64.to_be_bytes());
+        assert_eq!(c.find_shortest_separator(&start, &limit), start);
+        assert_eq!(c.find_short_successor(&limit), limit);
+    }
+
+    #[test]
+    fn test_dyn_comparator() {
+        let comparators: Vec<Arc<dyn Comparator>> = vec![
+            Arc::new(BytewiseComparator::default()),
+            Arc::new(ReverseBytewiseComparator::default()),
+        ];
+        assert_eq!(comparators[0].name(), "leveldb.BytewiseComparator");
+        assert_eq!(comparators[1].name(), "leveldb.ReverseBytewiseComparator");
+        assert_eq!(
+            comparators[1].compare(b"a", b"b"),
+            std::cmp::Ordering::Greater
+        );
+    }
 }
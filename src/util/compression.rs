@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::options::CompressionType;
+use crate::util::status::{Error, ErrorKind, Result};
+
+/// A codec for the contents of table blocks.  Every block records in its
+/// trailer the `CompressionType` it was stored with, so the codec must
+/// produce the same format whatever the process writing the table.
+pub trait Compressor: Send + Sync {
+    /// The type stored in the trailer of the blocks compressed by this codec
+    fn compression_type(&self) -> CompressionType;
+
+    fn compress(&self, input: &[u8]) -> Result<Vec<u8>>;
+
+    fn decompress(&self, input: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Snappy in its raw format (no framing), as written by upstream leveldb
+pub struct SnappyCompressor;
+
+impl Compressor for SnappyCompressor {
+    fn compression_type(&self) -> CompressionType {
+        CompressionType::SnappyCompression
+    }
+
+    fn compress(&self, input: &[u8]) -> Result<Vec<u8>> {
+        snap::raw::Encoder::new()
+            .compress_vec(input)
+            .map_err(|e| ErrorKind::CompressionFailed(e).into())
+    }
+
+    fn decompress(&self, input: &[u8]) -> Result<Vec<u8>> {
+        snap::raw::Decoder::new()
+            .decompress_vec(input)
+            .map_err(|e| ErrorKind::CompressionFailed(e).into())
+    }
+}
+
+/// The codecs of a database by the compression type byte stored in
+/// the block trailers.  The default registry holds `SnappyCompressor`.
+#[derive(Clone)]
+pub struct CompressorRegistry {
+    compressors: HashMap<u8, Arc<dyn Compressor>>,
+}
+
+impl Default for CompressorRegistry {
+    fn default() -> Self {
+        let mut registry = CompressorRegistry {
+            compressors: HashMap::new(),
+        };
+        registry.register(Arc::new(SnappyCompressor));
+        registry
+    }
+}
+
+impl CompressorRegistry {
+    /// Registers `compressor` for the blocks of its `compression_type()`,
+    /// replacing the codec registered for that type, if any.
+    /// REQUIRES: the type is neither `NoCompression` nor a `Custom` id
+    /// standing for a builtin type
+    pub fn register(&mut self, compressor: Arc<dyn Compressor>) {
+        let compression = compressor.compression_type();
+        let id = compression.to_u8();
+        assert!(id != CompressionType::NoCompression.to_u8());
+        assert_eq!(
+            compression,
+            CompressionType::from_u8(id),
+            "compression types 0 and 1 are not custom"
+        );
+        self.compressors.insert(id, compressor);
+    }
+
+    /// Returns the codec registered for `compression`, or `None` if there
+    /// is none, which is always the case for `NoCompression`.
+    pub fn get(&self, compression: CompressionType) -> Option<&dyn Compressor> {
+        self.compressors
+            .get(&compression.to_u8())
+            .map(|compressor| compressor.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snappy_round_trip() {
+        let registry = CompressorRegistry::default();
+        let snappy = registry.get(CompressionType::SnappyCompression).unwrap();
+        assert_eq!(
+            snappy.compression_type(),
+            CompressionType::SnappyCompression
+        );
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            b"hello".to_vec(),
+            b"abcd".repeat(1000),
+            (0..10000u32).map(|i| (i * 7919 % 251) as u8).collect(),
+        ];
+        for input in inputs {
+            let compressed = snappy.compress(&input).unwrap();
+            assert_eq!(snappy.decompress(&compressed).unwrap(), input);
+        }
+        let compressed = snappy.compress(&b"abcd".repeat(1000)).unwrap();
+        assert!(compressed.len() < 400);
+    }
+
+    #[test]
+    fn test_snappy_format() {
+        let snappy = SnappyCompressor;
+        // The varint of the length followed by a literal, as produced by
+        // the C++ snappy library
+        assert_eq!(snappy.compress(b"abc").unwrap(), b"\x03\x08abc");
+        assert_eq!(snappy.decompress(b"\x03\x08abc").unwrap(), b"abc");
+        let err = snappy.decompress(b"\x05\x08abc").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::CompressionFailed(_)));
+    }
+
+    #[test]
+    fn test_no_compression() {
+        let registry = CompressorRegistry::default();
+        assert!(registry.get(CompressionType::NoCompression).is_none());
+        assert!(registry.get(CompressionType::Custom(7)).is_none());
+    }
+
+    struct Custom(u8);
+
+    impl Compressor for Custom {
+        fn compression_type(&self) -> CompressionType {
+            CompressionType::Custom(self.0)
+        }
+
+        fn compress(&self, input: &[u8]) -> Result<Vec<u8>> {
+            Ok(input.to_vec())
+        }
+
+        fn decompress(&self, input: &[u8]) -> Result<Vec<u8>> {
+            Ok(input.to_vec())
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = CompressorRegistry::default();
+        registry.register(Arc::new(Custom(7)));
+        let custom = registry.get(CompressionType::from_u8(7)).unwrap();
+        assert_eq!(custom.compression_type(), CompressionType::Custom(7));
+        assert!(registry.get(CompressionType::SnappyCompression).is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_register_no_compression() {
+        CompressorRegistry::default().register(Arc::new(Custom(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "not custom")]
+    fn test_register_builtin_id() {
+        CompressorRegistry::default().register(Arc::new(Custom(1)));
+    }
+}
@@ -0,0 +1,117 @@
+// CRC32C (Castagnoli) checksum, the variant used by LevelDB to protect
+// log records and table blocks.
+
+const MASK_DELTA: u32 = 0xa282_ead8;
+
+// Reflected polynomial 0x1EDC6F41
+const POLY: u32 = 0x82f6_3b78;
+
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Return the crc32c of concat(A, data) where `init_crc` is the
+/// crc32c of some string A.  `extend()` is often used to maintain the
+/// crc32c of a stream of data.
+pub fn extend(init_crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !init_crc;
+    for &b in data {
+        crc = TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Return the crc32c of `data`.
+#[inline]
+pub fn value(data: &[u8]) -> u32 {
+    extend(0, data)
+}
+
+/// Return a masked representation of crc.
+///
+/// Motivation: it is problematic to compute the CRC of a string that
+/// contains embedded CRCs.  Therefore we recommend that CRCs stored
+/// somewhere (e.g., in files) should be masked before being stored.
+#[inline]
+pub fn mask(crc: u32) -> u32 {
+    // Rotate right by 15 bits and add a constant.
+    ((crc >> 15) | (crc << 17)).wrapping_add(MASK_DELTA)
+}
+
+/// Return the crc whose masked representation is `masked_crc`.
+#[inline]
+pub fn unmask(masked_crc: u32) -> u32 {
+    let rot = masked_crc.wrapping_sub(MASK_DELTA);
+    (rot >> 17) | (rot << 15)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_results() {
+        // From rfc3720 section B.4.
+        let buf = [0u8; 32];
+        assert_eq!(0x8a9136aa, value(&buf));
+
+        let buf = [0xffu8; 32];
+        assert_eq!(0x62a8ab43, value(&buf));
+
+        let mut buf = [0u8; 32];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        assert_eq!(0x46dd794e, value(&buf));
+
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (31 - i) as u8;
+        }
+        assert_eq!(0x113fdb5c, value(&buf));
+
+        let data = [
+            0x01, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x14,
+            0x00, 0x00, 0x00, 0x18, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(0xd9963a56, value(&data));
+    }
+
+    #[test]
+    fn test_values() {
+        assert_ne!(value(b"a"), value(b"foo"));
+    }
+
+    #[test]
+    fn test_extend() {
+        assert_eq!(value(b"hello world"), extend(value(b"hello "), b"world"));
+    }
+
+    #[test]
+    fn test_mask() {
+        let crc = value(b"foo");
+        assert_ne!(crc, mask(crc));
+        assert_ne!(crc, mask(mask(crc)));
+        assert_eq!(crc, unmask(mask(crc)));
+        assert_eq!(crc, unmask(unmask(mask(mask(crc)))));
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+
+use crate::util::status::Result;
+
+/// A file abstraction for randomly reading the contents of a file.
+pub trait RandomAccessFile: Send + Sync {
+    /// Read up to `buf.len()` bytes starting at `offset` into `buf`.
+    /// Returns the number of bytes read, which is less than `buf.len()`
+    /// only when the end of the file is reached.
+    ///
+    /// Safe for concurrent use by multiple threads.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize>;
+}
+
+impl<F: RandomAccessFile + ?Sized> RandomAccessFile for Box<F> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+}
+
+impl RandomAccessFile for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            match FileExt::read_at(self, &mut buf[n..], offset + n as u64)? {
+                0 => break,
+                m => n += m,
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl RandomAccessFile for Vec<u8> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        if offset >= self.len() as u64 {
+            return Ok(0);
+        }
+        let data = &self[offset as usize..];
+        let n = usize::min(buf.len(), data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+}
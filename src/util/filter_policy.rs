@@ -0,0 +1,248 @@
+use crate::util::hash::hash;
+
+/// A database can be configured with a custom `FilterPolicy` object.
+/// This object is responsible for creating a small filter from a set
+/// of keys.  These filters are stored in leveldb and are consulted
+/// automatically by leveldb to decide whether or not to read some
+/// information from disk. In many cases, a filter can cut down the
+/// number of disk seeks form a handful to a single disk seek per
+/// `DB::get()` call.
+pub trait FilterPolicy: Send + Sync {
+    /// Return the name of this policy.  Note that if the filter encoding
+    /// changes in an incompatible way, the name returned by this method
+    /// must be changed.  Otherwise, old incompatible filters may be
+    /// passed to methods of this type.
+    fn name(&self) -> &str;
+
+    /// `keys` contains a list of keys (potentially with duplicates)
+    /// that are ordered according to the user supplied comparator.
+    /// Append a filter that summarizes `keys` to `dst`.
+    ///
+    /// Warning: do not change the initial contents of `dst`.  Instead,
+    /// append the newly constructed filter to `dst`.
+    fn create_filter(&self, keys: &[&[u8]], dst: &mut Vec<u8>);
+
+    /// `filter` contains the data appended by a preceding call to
+    /// `create_filter()` on this type.  This method must return true if
+    /// the key was in the list of keys passed to `create_filter()`.
+    /// This method may return true or false if the key was not on the
+    /// list, but it should aim to return false with a high probability.
+    fn key_may_match(&self, key: &[u8], filter: &[u8]) -> bool;
+}
+
+/// A filter policy that uses a bloom filter with approximately
+/// the specified number of bits per key, wire-compatible with
+/// LevelDB's `NewBloomFilterPolicy`.  A good value for `bits_per_key`
+/// is 10, which yields a filter with ~ 1% false positive rate.
+pub struct BloomFilterPolicy {
+    bits_per_key: usize,
+    k: usize,
+}
+
+impl BloomFilterPolicy {
+    pub fn new(bits_per_key: usize) -> Self {
+        // We intentionally round down to reduce probing cost a little bit
+        let k = (bits_per_key as f64 * 0.69) as usize; // 0.69 =~ ln(2)
+        let k = k.clamp(1, 30);
+        BloomFilterPolicy { bits_per_key, k }
+    }
+}
+
+#[inline]
+fn bloom_hash(key: &[u8]) -> u32 {
+    hash(key, 0xbc9f_1d34)
+}
+
+impl FilterPolicy for BloomFilterPolicy {
+    fn name(&self) -> &str {
+        "leveldb.BuiltinBloomFilter2"
+    }
+
+    fn create_filter(&self, keys: &[&[u8]], dst: &mut Vec<u8>) {
+        // Compute bloom filter size (in both bits and bytes)
+        // For small n, we can see a very high false positive rate.  Fix it
+        // by enforcing a minimum bloom filter length.
+        let bits = usize::max(keys.len() * self.bits_per_key, 64);
+        let bytes = (bits + 7) / 8;
+        let bits = bytes * 8;
+
+        let init_size = dst.len();
+        dst.resize(init_size + bytes, 0);
+        dst.push(self.k as u8); // Remember # of probes in filter
+        let array = &mut dst[init_size..init_size + bytes];
+        for key in keys {
+            // Use double-hashing to generate a sequence of hash values.
+            // See analysis in [Kirsch,Mitzenmacher 2006].
+            let mut h = bloom_hash(key);
+            let delta = (h >> 17) | (h << 15); // Rotate right 17 bits
+            for _ in 0..self.k {
+                let bitpos = h as usize % bits;
+                array[bitpos / 8] |= 1 << (bitpos % 8);
+                h = h.wrapping_add(delta);
+            }
+        }
+    }
+
+    fn key_may_match(&self, key: &[u8], filter: &[u8]) -> bool {
+        let len = filter.len();
+        if len < 2 {
+            return false;
+        }
+        let array = &filter[..len - 1];
+        let bits = (len - 1) * 8;
+
+        // Use the encoded k so that we can read filters generated by
+        // bloom filters created using different parameters.
+        let k = filter[len - 1];
+        if k > 30 {
+            // Reserved for potentially new encodings for short bloom filters.
+            // Consider it a match.
+            return true;
+        }
+
+        let mut h = bloom_hash(key);
+        let delta = (h >> 17) | (h << 15); // Rotate right 17 bits
+        for _ in 0..k {
+            let bitpos = h as usize % bits;
+            if array[bitpos / 8] & (1 << (bitpos % 8)) == 0 {
+                return false;
+            }
+            h = h.wrapping_add(delta);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::coding::encode_fixed_32;
+
+    struct BloomTest {
+        policy: BloomFilterPolicy,
+        filter: Vec<u8>,
+        keys: Vec<Vec<u8>>,
+    }
+
+    impl BloomTest {
+        fn new() -> Self {
+            BloomTest {
+                policy: BloomFilterPolicy::new(10),
+                filter: vec![],
+                keys: vec![],
+            }
+        }
+
+        fn reset(&mut self) {
+            self.keys.clear();
+            self.filter.clear();
+        }
+
+        fn add(&mut self, key: &[u8]) {
+            self.keys.push(key.to_vec());
+        }
+
+        fn build(&mut self) {
+            let keys: Vec<&[u8]> = self.keys.iter().map(|k| k.as_slice()).collect();
+            self.filter.clear();
+            self.policy.create_filter(&keys, &mut self.filter);
+            self.keys.clear();
+        }
+
+        fn filter_size(&self) -> usize {
+            self.filter.len()
+        }
+
+        fn matches(&mut self, key: &[u8]) -> bool {
+            if !self.keys.is_empty() {
+                self.build();
+            }
+            self.policy.key_may_match(key, &self.filter)
+        }
+
+        fn false_positive_rate(&mut self) -> f64 {
+            let mut result = 0;
+            for i in 0..10000 {
+                if self.matches(&int_key(i + 1_000_000_000)) {
+                    result += 1;
+                }
+            }
+            result as f64 / 10000.0
+        }
+    }
+
+    fn int_key(i: u32) -> Vec<u8> {
+        let mut buf = vec![0; 4];
+        encode_fixed_32(&mut buf, i);
+        buf
+    }
+
+    fn next_length(length: u32) -> u32 {
+        if length < 10 {
+            length + 1
+        } else if length < 100 {
+            length + 10
+        } else if length < 1000 {
+            length + 100
+        } else {
+            length + 1000
+        }
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let mut t = BloomTest::new();
+        assert!(!t.matches(b"hello"));
+        assert!(!t.matches(b"world"));
+    }
+
+    #[test]
+    fn test_small() {
+        let mut t = BloomTest::new();
+        t.add(b"hello");
+        t.add(b"world");
+        assert!(t.matches(b"hello"));
+        assert!(t.matches(b"world"));
+        assert!(!t.matches(b"x"));
+        assert!(!t.matches(b"foo"));
+    }
+
+    #[test]
+    fn test_varying_lengths() {
+        let mut t = BloomTest::new();
+        // Count number of filters that significantly exceed the false positive rate
+        let mut mediocre_filters = 0;
+        let mut good_filters = 0;
+
+        let mut length = 1;
+        while length <= 10000 {
+            t.reset();
+            for i in 0..length {
+                t.add(&int_key(i));
+            }
+            t.build();
+
+            assert!(
+                t.filter_size() <= (length as usize * 10 / 8) + 40,
+                "length {}",
+                length
+            );
+
+            // All added keys must match
+            for i in 0..length {
+                assert!(t.matches(&int_key(i)), "length {}; key {}", length, i);
+            }
+
+            // Check false positive rate
+            let rate = t.false_positive_rate();
+            assert!(rate <= 0.02, "length {}; rate {}", length, rate); // Must not be over 2%
+            if rate > 0.0125 {
+                mediocre_filters += 1; // Allowed, but not too often
+            } else {
+                good_filters += 1;
+            }
+            length = next_length(length);
+        }
+        assert!(mediocre_filters <= good_filters / 5);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::util::coding::decode_fixed_32;
+
+/// Simple hash function used for internal data structures
+/// (similar to murmur hash).
+pub fn hash(data: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0xc6a4_a793;
+    const R: u32 = 24;
+    let n = data.len();
+    let mut h = seed ^ (n as u32).wrapping_mul(M);
+
+    // Pick up four bytes at a time
+    let mut chunks = data.chunks_exact(4);
+    for chunk in chunks.by_ref() {
+        let w = decode_fixed_32(chunk);
+        h = h.wrapping_add(w).wrapping_mul(M);
+        h ^= h >> 16;
+    }
+
+    // Pick up remaining bytes
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, b) in rest.iter().enumerate().rev() {
+            h = h.wrapping_add((*b as u32) << (8 * i));
+        }
+        h = h.wrapping_mul(M);
+        h ^= h >> R;
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_unsigned_issue() {
+        let data1 = [0x62];
+        let data2 = [0xc3, 0x97];
+        let data3 = [0xe2, 0x99, 0xa5];
+        let data4 = [0xe1, 0x80, 0xb9, 0x32];
+        let data5 = [
+            0x01, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x14,
+            0x00, 0x00, 0x00, 0x18, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(hash(&[], 0xbc9f1d34), 0xbc9f1d34);
+        assert_eq!(hash(&data1, 0xbc9f1d34), 0xef1345c4);
+        assert_eq!(hash(&data2, 0xbc9f1d34), 0x5b663814);
+        assert_eq!(hash(&data3, 0xbc9f1d34), 0x323c078f);
+        assert_eq!(hash(&data4, 0xbc9f1d34), 0xed21633a);
+        assert_eq!(hash(&data5, 0x12345678), 0xf333dabb);
+    }
+}
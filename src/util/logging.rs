@@ -0,0 +1,27 @@
+use std::fmt::Write;
+
+/// Returns a human-readable form of `value`: printable ASCII characters
+/// are kept and every other byte is written as `\xNN`.
+pub fn escape_string(value: &[u8]) -> String {
+    let mut s = String::with_capacity(value.len());
+    for &c in value {
+        if (b' '..=b'~').contains(&c) {
+            s.push(c as char);
+        } else {
+            let _ = write!(s, "\\x{:02x}", c);
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_string() {
+        assert_eq!(escape_string(b""), "");
+        assert_eq!(escape_string(b"key 1~"), "key 1~");
+        assert_eq!(escape_string(b"\x00a\xffb\n"), "\\x00a\\xffb\\x0a");
+    }
+}
@@ -1,4 +1,11 @@
 pub mod arena;
+pub mod cache;
 pub mod coding;
 pub mod comparator;
+pub mod compression;
+pub mod crc32c;
+pub mod file;
+pub mod filter_policy;
+pub mod hash;
+pub mod logging;
 pub mod status;
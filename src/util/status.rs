@@ -1,16 +1,27 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
 use quick_error::quick_error;
 
 quick_error! {
-    #[derive(Debug)]
-    pub enum Error{
+    /// What went wrong in an `Error`
+    #[derive(Debug, Clone)]
+    pub enum ErrorKind {
         NotFound(hint: Option<String>){
             display("key seeking failed: {:?}", hint)
         }
         Corruption(hint: String) {
             display("data corruption: {}", hint)
         }
+        NotSupported(hint: String) {
+            display("not supported: {}", hint)
+        }
         UTF8Error(err: std::string::FromUtf8Error) {
             display("UTF8 error: {:?}", err)
+            source(err)
         }
         InvalidArgument(hint: String) {
             display("invalid argument: {}", hint)
@@ -18,31 +29,281 @@ quick_error! {
         DBClosed(hint: String) {
             display("try to operate a closed db: {}", hint)
         }
-        // CompressionFailed(err: snap::Error) {
-        //     display("compression failed: {}", err)
-        //     cause(err)
-        // }
-        // IO(err: std::io::Error) {
-        //     display("I/O operation error: {}", err)
-        //     cause(err)
-        // }
-        // RecvError(err: RecvError) {
-        //     display("{:?}", err)
-        //     cause(err)
-        // }
-        // Customized(hint: String) {
-        //     display("{}", hint)
-        // }
+        CompressionFailed(err: snap::Error) {
+            display("compression failed: {}", err)
+            source(err)
+        }
+        /// The `io::Error` is shared so that the kind can be cloned
+        /// without losing e.g. its `raw_os_error()`.
+        IO(err: Arc<io::Error>) {
+            display("I/O operation error: {}", err)
+            source(&**err)
+        }
+        Busy(hint: String) {
+            display("resource busy: {}", hint)
+        }
+        Incomplete(hint: String) {
+            display("operation incomplete: {}", hint)
+        }
+        TryAgain(hint: String) {
+            display("try again: {}", hint)
+        }
+        Aborted(hint: String) {
+            display("operation aborted: {}", hint)
+        }
+    }
+}
+
+/// The errors of the crate: an `ErrorKind`, to be matched through
+/// `kind()`, and the context the error happened in, if known.
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    context: Option<Box<ErrorContext>>,
+}
+
+/// Where an error happened.  Every part is optional, an empty context
+/// is never attached to an error.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The file being accessed
+    pub path: Option<PathBuf>,
+    /// The file `path` was being renamed to
+    pub target: Option<PathBuf>,
+    /// The number of the db file being accessed, when `path` is unknown
+    pub file_number: Option<u64>,
+    /// The offset in the file of the failed access
+    pub offset: Option<u64>,
+    /// What was being done, e.g. "open" or "read"
+    pub operation: Option<&'static str>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(operation) = self.operation {
+            parts.push(format!("operation {}", operation));
+        }
+        if let Some(path) = &self.path {
+            parts.push(format!("path {}", path.display()));
+        }
+        if let Some(target) = &self.target {
+            parts.push(format!("target {}", target.display()));
+        }
+        if let Some(file_number) = self.file_number {
+            parts.push(format!("file {:06}", file_number));
+        }
+        if let Some(offset) = self.offset {
+            parts.push(format!("offset {}", offset));
+        }
+        write!(f, "{}", parts.join(", "))
     }
 }
 
+impl Error {
+    /// Returns what went wrong
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Returns the context attached to the error, if any
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_deref()
+    }
+
+    /// Returns the underlying I/O error of an `IO` error
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self.kind() {
+            ErrorKind::IO(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Returns true iff the operation failed for a transient reason and
+    /// may succeed if tried again as is.  Any other error is fatal to the
+    /// operation: retrying it without fixing the cause fails the same way.
+    pub fn is_retryable(&self) -> bool {
+        match self.kind() {
+            ErrorKind::Busy(_) | ErrorKind::TryAgain(_) => true,
+            ErrorKind::IO(err) => matches!(
+                err.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ),
+            _ => false,
+        }
+    }
+
+    /// Returns true iff the error reports damaged data
+    pub fn is_corruption(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Corruption(_))
+    }
+
+    /// Returns true iff the error reports a missing key or file
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.kind(), ErrorKind::NotFound(_))
+    }
+
+    pub fn with_path<P: AsRef<Path>>(self, path: P) -> Self {
+        self.with_context(|context| context.path = Some(path.as_ref().to_owned()))
+    }
+
+    pub fn with_target<P: AsRef<Path>>(self, target: P) -> Self {
+        self.with_context(|context| context.target = Some(target.as_ref().to_owned()))
+    }
+
+    pub fn with_file_number(self, file_number: u64) -> Self {
+        self.with_context(|context| context.file_number = Some(file_number))
+    }
+
+    pub fn with_offset(self, offset: u64) -> Self {
+        self.with_context(|context| context.offset = Some(offset))
+    }
+
+    pub fn with_operation(self, operation: &'static str) -> Self {
+        self.with_context(|context| context.operation = Some(operation))
+    }
+
+    // Updates the context of the error, attaching one if needed
+    fn with_context<F: FnOnce(&mut ErrorContext)>(mut self, f: F) -> Self {
+        f(self.context.get_or_insert_with(Box::default));
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            kind,
+            context: None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        ErrorKind::IO(Arc::new(err)).into()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.context {
+            Some(context) => write!(f, "{} ({})", self.kind, context),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.kind.source()
+    }
+}
+
+/// Converts the `std::io::Result` of an operation into a `Result`.  The
+/// name of the operation and the path of the file it accessed may be
+/// given to be recorded in the context of the error.
 macro_rules! map_io_res {
     ($result:expr) => {
-        match $result {
-            Ok(v) => Ok(v),
-            Err(e) => Err(Error::IO(e)),
-        }
+        $result.map_err($crate::util::status::Error::from)
+    };
+    ($result:expr, $operation:expr, $path:expr) => {
+        $result.map_err(|e| {
+            $crate::util::status::Error::from(e)
+                .with_operation($operation)
+                .with_path($path)
+        })
     };
 }
 
+pub(crate) use map_io_res;
+
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context() {
+        let err = Error::from(ErrorKind::Corruption("bad block".to_owned()))
+            .with_file_number(5)
+            .with_offset(1024);
+        assert!(err.is_corruption());
+        assert!(!err.is_not_found());
+        assert!(Error::from(ErrorKind::NotFound(None))
+            .with_offset(0)
+            .is_not_found());
+        assert_eq!(
+            err.context(),
+            Some(&ErrorContext {
+                file_number: Some(5),
+                offset: Some(1024),
+                ..ErrorContext::default()
+            })
+        );
+        // The context is extended, not nested
+        match err.kind() {
+            ErrorKind::Corruption(hint) => assert_eq!(hint, "bad block"),
+            _ => panic!("the error kind should be kept"),
+        }
+        assert_eq!(
+            err.to_string(),
+            "data corruption: bad block (file 000005, offset 1024)"
+        );
+        assert_eq!(err.clone().to_string(), err.to_string());
+        let err = Error::from(io::Error::from(io::ErrorKind::NotFound))
+            .with_operation("rename")
+            .with_path("/db/a")
+            .with_target("/db/b");
+        assert_eq!(
+            err.to_string(),
+            "I/O operation error: entity not found (operation rename, path /db/a, target /db/b)"
+        );
+        assert!(Error::from(ErrorKind::Aborted("stop".to_owned()))
+            .context()
+            .is_none());
+    }
+
+    #[test]
+    fn test_source() {
+        let result: io::Result<()> = Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        let err = map_io_res!(result, "open", "/db/CURRENT").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "I/O operation error: denied (operation open, path /db/CURRENT)"
+        );
+        assert_eq!(
+            err.io_error().unwrap().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        // The I/O error is the source, the context is not an error
+        assert_eq!(err.source().unwrap().to_string(), "denied");
+        assert!(Error::from(ErrorKind::Corruption("bad".to_owned()))
+            .source()
+            .is_none());
+    }
+
+    #[test]
+    fn test_retryable() {
+        let retryable = vec![
+            Error::from(ErrorKind::Busy("locked".to_owned())),
+            Error::from(ErrorKind::TryAgain("later".to_owned())).with_operation("write"),
+            Error::from(io::Error::from(io::ErrorKind::WouldBlock)),
+            Error::from(io::Error::from(io::ErrorKind::Interrupted)).with_path("/db/LOG"),
+        ];
+        for err in retryable {
+            assert!(err.is_retryable(), "{}", err);
+        }
+        let fatal = vec![
+            Error::from(ErrorKind::Corruption("bad block".to_owned())),
+            Error::from(ErrorKind::NotSupported("merge".to_owned())),
+            Error::from(ErrorKind::Incomplete("shutting down".to_owned())),
+            Error::from(ErrorKind::Aborted("stop".to_owned())),
+            Error::from(io::Error::from(io::ErrorKind::NotFound)).with_path("/db/CURRENT"),
+        ];
+        for err in fatal {
+            assert!(!err.is_retryable(), "{}", err);
+        }
+    }
+}